@@ -12,6 +12,9 @@ zip lambda.zip data/de440s.bsp
 zip lambda.zip data/moon_pa_de440_200625.bpc
 zip lambda.zip data/moon_de440_200625.tf
 zip lambda.zip data/pck00010.tpc
+if [ -f data/earth_latest_high_prec.bpc ]; then
+  zip lambda.zip data/earth_latest_high_prec.bpc
+fi
 ls -lh lambda.zip
 unzip -l lambda.zip
 cp lambda.zip lambda-$(git rev-parse --short HEAD).zip
@@ -0,0 +1,236 @@
+//! In-memory LRU cache of rendered responses.
+//!
+//! For an explicit `t` every endpoint is a pure function of its parameters and
+//! the loaded kernels, so repeated queries can be answered without touching
+//! SPICE. Identical queries that arrive while the first is still computing
+//! wait for its result instead of queueing their own.
+
+use crate::types::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+pub const DEFAULT_CACHE_SIZE: usize = 1024;
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// A request, normalized so that equivalent queries share an entry.
+///
+/// Time is keyed as UTC nanoseconds, which maps one-to-one onto ET for a
+/// given kernel set. Positions are keyed in radians.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    endpoint: String,
+    t: i128,
+    pos: Option<(Body, [u64; 3])>,
+    units: Option<UnitSpecifier>,
+    format: Option<FormatSpecifier>,
+    abcorr: &'static str,
+    surface: Surface,
+    kernels: u64,
+}
+
+impl CacheKey {
+    pub fn new(endpoint: &str, t: DateTime) -> CacheKey {
+        CacheKey {
+            endpoint: endpoint.to_string(),
+            t: t.unix_timestamp_nanos(),
+            pos: None,
+            units: None,
+            format: None,
+            abcorr: "NONE",
+            surface: Surface::Ellipsoid,
+            kernels: 0,
+        }
+    }
+    pub fn position(mut self, p: Position) -> CacheKey {
+        let p = p.to_radians();
+        self.pos = Some((p.body, [p.lat.to_bits(), p.lon.to_bits(), p.alt.to_bits()]));
+        self
+    }
+    pub fn units(mut self, u: UnitSpecifier) -> CacheKey {
+        self.units = Some(u);
+        self
+    }
+    pub fn format(mut self, f: FormatSpecifier) -> CacheKey {
+        self.format = Some(f);
+        self
+    }
+    pub fn abcorr(mut self, abcorr: &'static str) -> CacheKey {
+        self.abcorr = abcorr;
+        self
+    }
+    pub fn surface(mut self, surface: Surface) -> CacheKey {
+        self.surface = surface;
+        self
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub coalesced: u64,
+    pub entries: usize,
+    pub capacity: usize,
+    pub ttl_s: u64,
+    pub kernels: u64,
+}
+
+impl std::fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "hits: {}, misses: {}, coalesced: {}, entries: {}/{}, ttl: {} s, kernels: {:016x}",
+            self.hits,
+            self.misses,
+            self.coalesced,
+            self.entries,
+            self.capacity,
+            self.ttl_s,
+            self.kernels
+        )
+    }
+}
+
+struct Entry {
+    cell: Arc<OnceCell<String>>,
+    inserted: Instant,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CacheKey, Entry>,
+    order: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+pub struct ResponseCache {
+    lru: Mutex<Lru>,
+    capacity: usize,
+    ttl: Duration,
+    kernels: u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl ResponseCache {
+    /// `kernels` identifies the loaded kernel set, see `kernel_set_version`.
+    pub fn new(capacity: usize, ttl: Duration, kernels: u64) -> ResponseCache {
+        ResponseCache {
+            lru: Mutex::new(Lru::default()),
+            capacity,
+            ttl,
+            kernels,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached response for `key`, or computes and stores it.
+    ///
+    /// With no key (e.g. `t` defaulted to now) or a zero capacity the cache is
+    /// bypassed. Failed computations are not cached.
+    pub async fn get_or_compute<F, Fut, E>(&self, key: Option<CacheKey>, f: F) -> Result<String, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+    {
+        let key = match key {
+            Some(key) if self.capacity > 0 => key,
+            _ => return f().await,
+        };
+        let key = CacheKey {
+            kernels: self.kernels,
+            ..key
+        };
+        let cell = self.cell(key.clone());
+        let res = cell.get_or_try_init(f).await.cloned();
+        if res.is_err() {
+            self.forget(&key, &cell);
+        }
+        res
+    }
+
+    /// Drops `key` if it still maps to `cell` and nothing has filled it, so
+    /// a failure doesn't hold an LRU slot or pass for an in-flight request.
+    fn forget(&self, key: &CacheKey, cell: &Arc<OnceCell<String>>) {
+        let mut lru = self.lru.lock().unwrap();
+        let stale = match lru.entries.get(key) {
+            Some(entry) => Arc::ptr_eq(&entry.cell, cell) && !entry.cell.initialized(),
+            None => false,
+        };
+        if stale {
+            if let Some(entry) = lru.entries.remove(key) {
+                lru.order.remove(&entry.used);
+            }
+        }
+    }
+
+    fn cell(&self, key: CacheKey) -> Arc<OnceCell<String>> {
+        let mut lru = self.lru.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+
+        if let Some(entry) = lru.entries.get_mut(&key) {
+            if entry.inserted.elapsed() < self.ttl {
+                if entry.cell.initialized() {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                }
+                let used = std::mem::replace(&mut entry.used, tick);
+                let cell = entry.cell.clone();
+                lru.order.remove(&used);
+                lru.order.insert(tick, key);
+                return cell;
+            }
+            let used = entry.used;
+            lru.entries.remove(&key);
+            lru.order.remove(&used);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        while lru.entries.len() >= self.capacity {
+            match lru.order.pop_first() {
+                Some((_, oldest)) => {
+                    lru.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        let cell = Arc::new(OnceCell::new());
+        lru.entries.insert(
+            key.clone(),
+            Entry {
+                cell: cell.clone(),
+                inserted: Instant::now(),
+                used: tick,
+            },
+        );
+        lru.order.insert(tick, key);
+        cell
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            entries: self.lru.lock().unwrap().entries.len(),
+            capacity: self.capacity,
+            ttl_s: self.ttl.as_secs(),
+            kernels: self.kernels,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Mission clocks: time since a mission epoch, the local day at the site,
+//! and countdowns to what comes next there.
+//!
+//! Days are local solar days at the site, numbered from the one containing
+//! the landing: sols on Mars (counted as in `mars`), lunar days on the Moon.
+//! Sunrise, sunset, Earthrise and Earthset are over the geometric horizon,
+//! and eclipses only count while the Sun is up; these are computed for
+//! lunar sites only, and only as far ahead as `SPAN_DAYS`, so an eclipse
+//! shows up in the month before it. A mission's own events are counted
+//! down anywhere.
+
+use crate::horizon::HorizonMask;
+use crate::missions::{Mission, Since};
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::sync::{Arc, Mutex};
+
+/// Search for rises, sets and eclipses, days: a little over a lunar day.
+/// Every lunar clock runs these searches, so they stay short.
+const SPAN_DAYS: i64 = 32;
+const STEP_S: f64 = 600.0;
+
+/// Something ahead, and how long until it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClockEvent {
+    pub name: String,
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    /// Seconds from the clock's time.
+    pub until: f64,
+    pub countdown: String,
+    /// Found from the geometry, rather than given by the mission.
+    pub computed: bool,
+}
+
+impl ClockEvent {
+    fn new(name: &str, t: DateTime, now: DateTime, computed: bool) -> ClockEvent {
+        let until = (t - now).as_seconds_f64();
+        ClockEvent {
+            name: name.to_string(),
+            t,
+            until,
+            countdown: countdown(until),
+            computed,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MissionClock {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    pub mission: Option<String>,
+    pub since: Since,
+    #[serde(with = "default_datetime_standard::option", default)]
+    pub epoch: Option<DateTime>,
+    /// Seconds since the epoch; negative before it.
+    pub elapsed: Option<f64>,
+    /// Mission elapsed time, as `T+` or `T-` and a countdown.
+    pub met: Option<String>,
+    /// "Lunar day", "Sol" or "Day".
+    pub day_name: String,
+    /// Local day number since landing.
+    pub day: Option<i64>,
+    /// Soonest first.
+    pub events: Vec<ClockEvent>,
+    /// E.g. "Lunar day 3, 02:14:00 until sunset".
+    pub summary: String,
+}
+
+impl std::fmt::Display for MissionClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.summary)?;
+        for event in &self.events {
+            write!(f, "\n{}: {} ({})", event.name, event.countdown, event.t)?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds as HH:MM:SS, led by whole days if there are any. The sign is
+/// dropped.
+pub fn countdown(s: f64) -> String {
+    let s = s.abs().round() as i64;
+    let (days, s) = (s / 86400, s % 86400);
+    let hms = format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60);
+    if days > 0 {
+        format!("{}d {}", days, hms)
+    } else {
+        hms
+    }
+}
+
+fn day_name(body: Body) -> &'static str {
+    match body {
+        Body::Moon => "Lunar day",
+        Body::Mars => "Sol",
+        _ => "Day",
+    }
+}
+
+/// Mean solar day on `body`, s.
+fn solar_day(body: Body) -> f64 {
+    let days = match body {
+        Body::Moon => 29.530589,
+        Body::Earth => 1.0,
+        Body::Mars => 1.027491252,
+        Body::Mercury => 175.9421,
+        Body::Europa => 3.554094,
+    };
+    days * 86400.0
+}
+
+/// The local day at `pos` containing `et`, numbered so that the one
+/// containing `epoch` is `first`. Needs the body's ephemeris, except on Mars.
+fn local_day(lock: &SpiceLock, pos: Position, et: f64, epoch: f64, first: i64) -> Option<i64> {
+    let lon = pos.to_radians().lon;
+    if pos.body == Body::Mars {
+        let (now, then) = (crate::mars::msd(et), crate::mars::msd(epoch));
+        return Some(crate::mars::mission_sol(now, lon.to_degrees(), then, first));
+    }
+    let now = crate::local_solar_seconds(lock, et, pos.body, lon).ok()?;
+    let then = crate::local_solar_seconds(lock, epoch, pos.body, lon).ok()?;
+    // mean days elapsed, less the part covered by the change in local time
+    let days = (et - epoch) / solar_day(pos.body) - (now - then) / 86400.0;
+    Some(days.round() as i64 + first)
+}
+
+/// The next rise and set of `target` over the geometric horizon at `p`,
+/// or none if the ephemeris doesn't cover the search.
+fn rise_set(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    p: Position,
+    target: &str,
+    names: [&str; 2],
+) -> Vec<ClockEvent> {
+    let until = t + time::Duration::days(SPAN_DAYS);
+    let mask = HorizonMask::flat(p);
+    let Ok(visibility) =
+        crate::horizon::visibility(sl_mutex, &mask, target, t, Some(until), STEP_S)
+    else {
+        return Vec::new();
+    };
+    [true, false]
+        .iter()
+        .zip(names)
+        .filter_map(|(rising, name)| {
+            let event = visibility.events.iter().find(|e| e.rising == *rising)?;
+            Some(ClockEvent::new(name, event.t, t, true))
+        })
+        .collect()
+}
+
+/// The start of the next eclipse with the Sun up at `p`, or its end if one
+/// is under way.
+fn next_eclipse(sl_mutex: Arc<Mutex<SpiceLock>>, t: DateTime, p: Position) -> Option<ClockEvent> {
+    let until = t + time::Duration::days(SPAN_DAYS);
+    let eclipses = crate::eclipse::eclipses(sl_mutex, t, until, p).ok()?;
+    let eclipse = eclipses.eclipses.into_iter().find(|e| e.sun_up)?;
+    if eclipse.start > t {
+        Some(ClockEvent::new("eclipse", eclipse.start, t, true))
+    } else {
+        Some(ClockEvent::new("eclipse end", eclipse.end, t, true))
+    }
+}
+
+/// The clock at `p` at `t`, counting from `epoch` if there is one.
+pub fn mission_clock(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    p: Position,
+    since: Since,
+    epoch: Option<DateTime>,
+    mission: Option<Mission>,
+) -> MissionClock {
+    let mut events = Vec::new();
+    if p.body == Body::Moon {
+        events.extend(rise_set(
+            sl_mutex.clone(),
+            t,
+            p,
+            "SUN",
+            ["sunrise", "sunset"],
+        ));
+        events.extend(rise_set(
+            sl_mutex.clone(),
+            t,
+            p,
+            "EARTH",
+            ["earthrise", "earthset"],
+        ));
+        events.extend(next_eclipse(sl_mutex.clone(), t, p));
+    }
+    if let Some(mission) = &mission {
+        let planned = mission.events.iter().filter(|e| e.t > t);
+        events.extend(planned.map(|e| ClockEvent::new(&e.name, e.t, t, false)));
+    }
+    events.sort_by_key(|e| e.t);
+
+    let elapsed = epoch.map(|epoch| (t - epoch).as_seconds_f64());
+    let met = elapsed.map(|s| format!("T{}{}", if s < 0.0 { '-' } else { '+' }, countdown(s)));
+    let day = match epoch {
+        Some(epoch) if since == Since::Landing && epoch <= t => {
+            let lock = crate::lock_spice(&sl_mutex);
+            let et = lock.str2et(crate::to_cspice_string(t).as_str());
+            let landed = lock.str2et(crate::to_cspice_string(epoch).as_str());
+            let first = mission.as_ref().map_or(1, |m| m.first_sol);
+            local_day(&lock, p, et, landed, first)
+        }
+        _ => None,
+    };
+
+    let mut parts = Vec::new();
+    match (day, &met, elapsed) {
+        (Some(day), _, _) => parts.push(format!("{} {}", day_name(p.body), day)),
+        (None, Some(met), Some(s)) if s < 0.0 => parts.push(format!("{} to {}", met, since)),
+        (None, Some(met), _) => parts.push(format!("{} since {}", met, since)),
+        _ => {}
+    }
+    match events.first() {
+        Some(next) => parts.push(format!("{} until {}", next.countdown, next.name)),
+        None => parts.push("nothing ahead".to_string()),
+    }
+
+    MissionClock {
+        t,
+        mission: mission.map(|m| m.name),
+        since,
+        epoch,
+        elapsed,
+        met,
+        day_name: day_name(p.body).to_string(),
+        day,
+        events,
+        summary: parts.join(", "),
+    }
+}
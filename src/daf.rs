@@ -0,0 +1,289 @@
+//! Pure-Rust reader for NAIF DAF files: SPK ephemerides and binary PCKs.
+//!
+//! Only what the pure backend needs is supported: SPK types 2, 3, 13 and 21,
+//! and binary PCK type 2. Files are read whole into memory, in either byte
+//! order.
+
+const RECORD_BYTES: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DafKind {
+    Spk,
+    Pck,
+}
+
+/// A segment descriptor. Addresses are 1-based double precision word indices,
+/// as in the DAF spec.
+#[derive(Debug, Copy, Clone)]
+pub struct Segment {
+    pub start_et: f64,
+    pub end_et: f64,
+    /// SPK: target body. PCK: body frame class id.
+    pub body: i32,
+    /// SPK: center body. Unused for PCK.
+    pub center: i32,
+    /// Frame the segment's data is given relative to.
+    pub frame: i32,
+    pub data_type: i32,
+    pub begin: usize,
+    pub end: usize,
+}
+
+pub struct Daf {
+    pub kind: DafKind,
+    data: Vec<u8>,
+    little: bool,
+    pub segments: Vec<Segment>,
+}
+
+impl Daf {
+    pub fn parse(data: Vec<u8>) -> Result<Daf, String> {
+        if data.len() < RECORD_BYTES {
+            return Err("file too short for a DAF".to_string());
+        }
+        let kind = match &data[0..7] {
+            b"DAF/SPK" | b"NAIF/DA" => DafKind::Spk,
+            b"DAF/PCK" => DafKind::Pck,
+            _ => return Err("not an SPK or binary PCK file".to_string()),
+        };
+        let little = &data[88..96] != b"BIG-IEEE";
+
+        let mut daf = Daf {
+            kind,
+            data,
+            little,
+            segments: Vec::new(),
+        };
+        let nd = daf.i32_at(8) as usize;
+        let ni = daf.i32_at(12) as usize;
+        let summary_size = nd + ni.div_ceil(2);
+
+        let mut record = daf.i32_at(76) as usize;
+        while record > 0 {
+            let base = (record - 1) * RECORD_BYTES;
+            let next = daf.f64_at(base) as usize;
+            let count = daf.f64_at(base + 16) as usize;
+            for i in 0..count {
+                let at = base + 24 + i * summary_size * 8;
+                let doubles: Vec<f64> = (0..nd).map(|j| daf.f64_at(at + j * 8)).collect();
+                let ints: Vec<i32> = (0..ni).map(|j| daf.i32_at(at + nd * 8 + j * 4)).collect();
+                let segment = match kind {
+                    DafKind::Spk if nd == 2 && ni == 6 => Segment {
+                        start_et: doubles[0],
+                        end_et: doubles[1],
+                        body: ints[0],
+                        center: ints[1],
+                        frame: ints[2],
+                        data_type: ints[3],
+                        begin: ints[4] as usize,
+                        end: ints[5] as usize,
+                    },
+                    DafKind::Pck if nd == 2 && ni == 5 => Segment {
+                        start_et: doubles[0],
+                        end_et: doubles[1],
+                        body: ints[0],
+                        center: 0,
+                        frame: ints[1],
+                        data_type: ints[2],
+                        begin: ints[3] as usize,
+                        end: ints[4] as usize,
+                    },
+                    _ => return Err(format!("unexpected summary format ND={} NI={}", nd, ni)),
+                };
+                daf.segments.push(segment);
+            }
+            record = next;
+        }
+        Ok(daf)
+    }
+
+    pub fn open(path: &str) -> Result<Daf, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        Daf::parse(data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    fn i32_at(&self, offset: usize) -> i32 {
+        let bytes: [u8; 4] = self.data[offset..offset + 4].try_into().unwrap();
+        if self.little {
+            i32::from_le_bytes(bytes)
+        } else {
+            i32::from_be_bytes(bytes)
+        }
+    }
+
+    fn f64_at(&self, offset: usize) -> f64 {
+        let bytes: [u8; 8] = self.data[offset..offset + 8].try_into().unwrap();
+        if self.little {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        }
+    }
+
+    /// Reads the doubles at 1-based addresses `start..=end`.
+    fn read(&self, start: usize, end: usize) -> Vec<f64> {
+        (start..=end).map(|a| self.f64_at((a - 1) * 8)).collect()
+    }
+
+    /// Evaluates an SPK segment: position in km relative to the segment's
+    /// center, in the segment's frame.
+    pub fn spk_position(&self, seg: &Segment, et: f64) -> Result<[f64; 3], String> {
+        match seg.data_type {
+            2 => Ok(self.chebyshev(seg, et, 3)),
+            3 => Ok(self.chebyshev(seg, et, 6)),
+            13 => Ok(self.hermite(seg, et)),
+            21 => Ok(self.difference_lines(seg, et)),
+            t => Err(format!("SPK type {} is not supported", t)),
+        }
+    }
+
+    /// Evaluates a binary PCK segment: the 3-1-3 Euler angles of the body frame.
+    pub fn pck_angles(&self, seg: &Segment, et: f64) -> Result<[f64; 3], String> {
+        match seg.data_type {
+            2 => Ok(self.chebyshev(seg, et, 3)),
+            t => Err(format!("PCK type {} is not supported", t)),
+        }
+    }
+
+    /// Types 2 and 3: fixed-length records of Chebyshev coefficients.
+    fn chebyshev(&self, seg: &Segment, et: f64, components: usize) -> [f64; 3] {
+        let trailer = self.read(seg.end - 3, seg.end);
+        let (init, intlen, rsize, n) = (trailer[0], trailer[1], trailer[2] as usize, trailer[3]);
+        let index = ((et - init) / intlen).floor().clamp(0.0, n - 1.0) as usize;
+        let start = seg.begin + index * rsize;
+        let record = self.read(start, start + rsize - 1);
+
+        let (mid, radius) = (record[0], record[1]);
+        let ncoef = (rsize - 2) / components;
+        let x = (et - mid) / radius;
+        let mut out = [0.0; 3];
+        for (i, out) in out.iter_mut().enumerate() {
+            let coeffs = &record[2 + i * ncoef..2 + (i + 1) * ncoef];
+            *out = chebyshev_sum(coeffs, x);
+        }
+        out
+    }
+
+    /// Type 13: Hermite interpolation of unequally spaced states.
+    fn hermite(&self, seg: &Segment, et: f64) -> [f64; 3] {
+        let trailer = self.read(seg.end - 1, seg.end);
+        let window = trailer[0] as usize + 1;
+        let n = trailer[1] as usize;
+        let epochs = self.read(seg.begin + 6 * n, seg.begin + 7 * n - 1);
+
+        let upper = epochs.partition_point(|&e| e <= et).clamp(1, n - 1);
+        let lower = upper - 1;
+        let first = if window.is_multiple_of(2) {
+            lower.saturating_sub(window / 2 - 1)
+        } else if et - epochs[lower] < epochs[upper] - et {
+            lower.saturating_sub(window / 2)
+        } else {
+            upper.saturating_sub(window / 2)
+        };
+        let first = first.min(n.saturating_sub(window));
+        let last = (first + window).min(n);
+
+        let xs = &epochs[first..last];
+        let states = self.read(seg.begin + 6 * first, seg.begin + 6 * last - 1);
+        let mut out = [0.0; 3];
+        for (i, out) in out.iter_mut().enumerate() {
+            let pos: Vec<f64> = states.chunks(6).map(|s| s[i]).collect();
+            let vel: Vec<f64> = states.chunks(6).map(|s| s[i + 3]).collect();
+            *out = hermite_interpolate(xs, &pos, &vel, et);
+        }
+        out
+    }
+
+    /// Type 21: extended modified difference arrays, as in SPICE's SPKE21.
+    fn difference_lines(&self, seg: &Segment, et: f64) -> [f64; 3] {
+        let trailer = self.read(seg.end - 1, seg.end);
+        let maxdim = trailer[0] as usize;
+        let n = trailer[1] as usize;
+        let rsize = 4 * maxdim + 11;
+        let epochs = self.read(seg.begin + n * rsize, seg.begin + n * rsize + n - 1);
+        let index = epochs.partition_point(|&e| e < et).min(n - 1);
+        let start = seg.begin + index * rsize;
+        let record = self.read(start, start + rsize - 1);
+
+        let tl = record[0];
+        let g = &record[1..1 + maxdim];
+        let refpos = [record[maxdim + 1], record[maxdim + 3], record[maxdim + 5]];
+        let refvel = [record[maxdim + 2], record[maxdim + 4], record[maxdim + 6]];
+        let dt = |j: usize, i: usize| record[maxdim + 7 + i * maxdim + j];
+        let kqmax1 = record[4 * maxdim + 7] as usize;
+        let kq = [
+            record[4 * maxdim + 8] as usize,
+            record[4 * maxdim + 9] as usize,
+            record[4 * maxdim + 10] as usize,
+        ];
+
+        let delta = et - tl;
+        let mut tp = delta;
+        let mq2 = kqmax1.saturating_sub(2);
+        let mut fc = vec![0.0; maxdim + 1];
+        let mut wc = vec![0.0; maxdim + 1];
+        for j in 0..mq2 {
+            fc[j] = tp / g[j];
+            wc[j] = delta / g[j];
+            tp = delta + g[j];
+        }
+        // 1-based like the Fortran, to keep the index arithmetic recognisable
+        let mut w = vec![0.0; kqmax1 + 2];
+        for (j, w) in w.iter_mut().enumerate().skip(1).take(kqmax1) {
+            *w = 1.0 / j as f64;
+        }
+        let mut ks = kqmax1 - 1;
+        let mut ks1 = ks - 1;
+        let mut jx = 0;
+        while ks >= 2 {
+            jx += 1;
+            for j in 1..=jx {
+                w[j + ks] = fc[j - 1] * w[j + ks1] - wc[j - 1] * w[j + ks];
+            }
+            ks = ks1;
+            ks1 -= 1;
+        }
+
+        let mut out = [0.0; 3];
+        for i in 0..3 {
+            let sum: f64 = (1..=kq[i]).rev().map(|j| dt(j - 1, i) * w[j + ks]).sum();
+            out[i] = refpos[i] + delta * (refvel[i] + delta * sum);
+        }
+        out
+    }
+}
+
+fn chebyshev_sum(coeffs: &[f64], x: f64) -> f64 {
+    let mut b1 = 0.0;
+    let mut b2 = 0.0;
+    for c in coeffs.iter().skip(1).rev() {
+        let b = 2.0 * x * b1 - b2 + c;
+        b2 = b1;
+        b1 = b;
+    }
+    x * b1 - b2 + coeffs[0]
+}
+
+/// Hermite interpolation through values `ys` with derivatives `dys` at `xs`,
+/// using divided differences over doubled nodes.
+fn hermite_interpolate(xs: &[f64], ys: &[f64], dys: &[f64], x: f64) -> f64 {
+    let n = 2 * xs.len();
+    let z: Vec<f64> = xs.iter().flat_map(|&x| [x, x]).collect();
+    let mut q: Vec<f64> = ys.iter().flat_map(|&y| [y, y]).collect();
+    let mut coeffs = vec![q[0]];
+    for level in 1..n {
+        for i in (level..n).rev() {
+            q[i] = if level == 1 && i % 2 == 1 {
+                dys[i / 2]
+            } else {
+                (q[i] - q[i - 1]) / (z[i] - z[i - level])
+            };
+        }
+        coeffs.push(q[level]);
+    }
+    let mut value = coeffs[n - 1];
+    for i in (0..n - 1).rev() {
+        value = value * (x - z[i]) + coeffs[i];
+    }
+    value
+}
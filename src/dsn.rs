@@ -0,0 +1,568 @@
+//! Deep Space Network visibility of the Moon or a lunar site.
+//!
+//! Stations are points on Earth's reference ellipsoid with an elevation
+//! mask. A pass is a window with the target above a station's mask; the
+//! schedule hands the link from one station to the next, staying on each
+//! until it sets so there are as few handovers as possible. Link geometry
+//! (range rate, Doppler, path loss) comes from the station's and target's
+//! states relative to Earth, in J2000. Stations are fixed in ITRF93 when
+//! the high-precision Earth PCK covers the request, IAU_EARTH otherwise.
+
+use crate::earth::{rotation, EarthOrientation, Topo};
+use crate::search::{changes, greatest, spans};
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+const FRAME: &str = "MOON_ME_DE440_ME421";
+const CLIGHT_KM_S: f64 = 299_792.458;
+/// DSN X-band downlink, Hz.
+pub const DEFAULT_FREQUENCY_HZ: f64 = 8.4e9;
+pub const DEFAULT_MASK_DEG: f64 = 6.0;
+pub const DEFAULT_STEP_S: f64 = 300.0;
+pub const DEFAULT_SPAN_DAYS: i64 = 1;
+/// Most user-defined stations in one request.
+pub const MAX_STATIONS: usize = 16;
+/// Upper bound on samples per station.
+pub const MAX_SAMPLES: usize = 100_000;
+
+fn default_mask() -> f64 {
+    DEFAULT_MASK_DEG
+}
+
+/// A ground station: geodetic lat/lon, altitude in km, and the lowest
+/// elevation it can track at.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Station {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    #[serde(default)]
+    pub alt: f64,
+    #[serde(default = "default_mask")]
+    pub mask: f64,
+    #[serde(default = "default_degrees")]
+    pub units: UnitSpecifier,
+}
+
+impl Angular for Station {
+    fn to_degrees(&self) -> Station {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Station {
+                lat: self.lat.to_degrees(),
+                lon: self.lon.to_degrees(),
+                mask: self.mask.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Station {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Station {
+                lat: self.lat.to_radians(),
+                lon: self.lon.to_radians(),
+                mask: self.mask.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+impl Station {
+    /// Where the station stands on Earth.
+    fn position(&self) -> Position {
+        Position::new(self.lat, self.lon, self.alt, self.units).on(Body::Earth)
+    }
+
+    fn dsn(name: &str, lat: f64, lon: f64, alt: f64) -> Station {
+        Station {
+            name: name.to_string(),
+            lat,
+            lon,
+            alt,
+            mask: DEFAULT_MASK_DEG,
+            units: UnitSpecifier::Degrees,
+        }
+    }
+
+    /// The three DSN complexes, at their 70 m antennas (DSS-14, DSS-63,
+    /// DSS-43).
+    pub fn dsn_complexes() -> Vec<Station> {
+        vec![
+            Station::dsn("GOLDSTONE", 35.4259, -116.8895, 1.0014),
+            Station::dsn("MADRID", 40.4313, -4.2481, 0.8651),
+            Station::dsn("CANBERRA", -35.4024, 148.9813, 0.6890),
+        ]
+    }
+
+    /// A DSN complex by name, ignoring case.
+    pub fn named(name: &str) -> Option<Station> {
+        Station::dsn_complexes()
+            .into_iter()
+            .find(|s| s.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        let s = self.to_degrees();
+        if s.name.trim().is_empty() {
+            return Err("stations need a name".to_string());
+        }
+        if !(-90.0..=90.0).contains(&s.lat) || !s.lon.is_finite() || !s.alt.is_finite() {
+            return Err(format!("station {} is not on Earth", s.name));
+        }
+        if !(-90.0..90.0).contains(&s.mask) {
+            return Err(format!("station {} mask must be below 90 degrees", s.name));
+        }
+        Ok(())
+    }
+}
+
+/// A station as a query string gives it, `name:lat:lon[:alt[:mask]]` in
+/// degrees and km.
+impl std::str::FromStr for Station {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Station, String> {
+        let bad = || format!("bad station '{}', want name:lat:lon[:alt[:mask]]", text);
+        let mut parts = text.split(':').map(str::trim);
+        let name = parts.next().unwrap_or_default().to_string();
+        let numbers = parts
+            .map(|n| n.parse::<f64>().map_err(|_| bad()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (lat, lon, alt, mask) = match numbers[..] {
+            [lat, lon] => (lat, lon, 0.0, DEFAULT_MASK_DEG),
+            [lat, lon, alt] => (lat, lon, alt, DEFAULT_MASK_DEG),
+            [lat, lon, alt, mask] => (lat, lon, alt, mask),
+            _ => return Err(bad()),
+        };
+        Ok(Station {
+            name,
+            lat,
+            lon,
+            alt,
+            mask,
+            units: UnitSpecifier::Degrees,
+        })
+    }
+}
+
+/// The target above one station's mask.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pass {
+    pub station: String,
+    /// Clipped to the search window.
+    #[serde(with = "default_datetime_standard")]
+    pub start: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub end: DateTime,
+    pub max_el: f64,
+    #[serde(with = "default_datetime_standard")]
+    pub max_at: DateTime,
+}
+
+/// Which station has the link, from `start` to `end`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Coverage {
+    pub station: String,
+    #[serde(with = "default_datetime_standard")]
+    pub start: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub end: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DsnVisibility {
+    /// "MOON", or the site.
+    pub target: String,
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub until: DateTime,
+    /// Sorted by start.
+    pub passes: Vec<Pass>,
+    /// Who holds the link; a change of station between entries is a
+    /// handover, and time between them a gap.
+    pub schedule: Vec<Coverage>,
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for DsnVisibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "target: {}, passes: {}, orientation: {}, u: {}",
+            self.target,
+            self.passes.len(),
+            self.orientation,
+            self.units
+        )?;
+        for pass in &self.passes {
+            write!(
+                f,
+                "\n{}: {} to {}, max el {} at {}",
+                pass.station, pass.start, pass.end, pass.max_el, pass.max_at
+            )?;
+        }
+        write!(f, "\nschedule:")?;
+        for coverage in &self.schedule {
+            write!(
+                f,
+                "\n{}: {} to {}",
+                coverage.station, coverage.start, coverage.end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for DsnVisibility {
+    fn to_degrees(&self) -> DsnVisibility {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => DsnVisibility {
+                passes: self
+                    .passes
+                    .iter()
+                    .map(|p| Pass {
+                        max_el: p.max_el.to_degrees(),
+                        ..p.clone()
+                    })
+                    .collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> DsnVisibility {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => DsnVisibility {
+                passes: self
+                    .passes
+                    .iter()
+                    .map(|p| Pass {
+                        max_el: p.max_el.to_radians(),
+                        ..p.clone()
+                    })
+                    .collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Moon center, or the site on it, from Earth's center in `earth_frame`.
+fn target_from_earth(lock: &SpiceLock, et: f64, earth_frame: &str, site: Option<Vec3>) -> Vec3 {
+    let (moon, _lt) = lock.spkpos("MOON", et, earth_frame, "NONE", "EARTH");
+    match site {
+        Some(site) => add(moon, mxv(rotation(lock, FRAME, earth_frame, et), site)),
+        None => moon,
+    }
+}
+
+/// Hands the link over only when a station sets, to whichever station in
+/// view stays up longest; gaps are left out.
+fn schedule(passes: &[Pass]) -> Vec<Coverage> {
+    let mut coverage: Vec<Coverage> = Vec::new();
+    let mut at = match passes.iter().map(|p| p.start).min() {
+        Some(at) => at,
+        None => return coverage,
+    };
+    loop {
+        let up = passes
+            .iter()
+            .filter(|p| p.start <= at && p.end > at)
+            .max_by_key(|p| p.end);
+        match up {
+            Some(pass) => {
+                coverage.push(Coverage {
+                    station: pass.station.clone(),
+                    start: at,
+                    end: pass.end,
+                });
+                at = pass.end;
+            }
+            None => match passes
+                .iter()
+                .filter(|p| p.start > at)
+                .map(|p| p.start)
+                .min()
+            {
+                Some(next) => at = next,
+                None => return coverage,
+            },
+        }
+    }
+}
+
+/// Passes of the Moon (or the site at `site`) over `stations` between `t`
+/// and `until`, sampled every `step` seconds and refined to a second.
+pub fn dsn_visibility(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    until: DateTime,
+    step: f64,
+    site: Option<Position>,
+    stations: &[Station],
+) -> Result<DsnVisibility, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let end = lock.str2et(crate::to_cspice_string(until).as_str());
+        let point = site.map(|p| crate::ellipsoid_point(&lock, p));
+        let orientation = EarthOrientation::at(&lock, &[et, end]);
+        let earth_frame = orientation.frame();
+        let to_time = |at: f64| t + time::Duration::seconds_f64(at - et);
+
+        let mut passes = Vec::new();
+        for station in stations {
+            let topo = Topo::new(&lock, station.position());
+            let mask = station.to_radians().mask;
+            let height = |et: f64| topo.elevation(target_from_earth(&lock, et, earth_frame, point));
+
+            let above = |et: f64| height(et) > mask;
+            let windows = spans(
+                above(et),
+                et,
+                end,
+                changes(et, end, step, above).map(|c| c.0),
+            );
+            for (start, stop) in windows {
+                let max_at = greatest(start, stop, step, height);
+                passes.push(Pass {
+                    station: station.name.clone(),
+                    start: to_time(start),
+                    end: to_time(stop),
+                    max_el: height(max_at),
+                    max_at: to_time(max_at),
+                });
+            }
+        }
+        passes.sort_by_key(|p| p.start);
+
+        DsnVisibility {
+            target: match site {
+                Some(p) => {
+                    let p = p.to_degrees();
+                    format!("site ({}, {}, {} km)", p.lat, p.lon, p.alt)
+                }
+                None => "MOON".to_string(),
+            },
+            t,
+            until,
+            schedule: schedule(&passes),
+            passes,
+            orientation,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
+
+/// What the station talks to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum CommsTarget {
+    /// A point on the Moon; `alt` is the antenna height.
+    Site(Position),
+    /// A SPICE body name or NAIF id, e.g. a spacecraft with a loaded SPK.
+    Body(String),
+}
+
+impl std::fmt::Display for CommsTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CommsTarget::Site(p) => {
+                let p = p.to_degrees();
+                write!(f, "site ({}, {}, {} km)", p.lat, p.lon, p.alt)
+            }
+            CommsTarget::Body(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Link geometry between a station and its target at one instant.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Comms {
+    pub station: String,
+    pub target: String,
+    /// km.
+    pub range: f64,
+    /// km/s, positive when the target is moving away.
+    pub range_rate: f64,
+    /// Seconds for a signal to cross the current range.
+    pub light_time: f64,
+    /// Seconds for a signal to go and return at the current range.
+    pub round_trip: f64,
+    /// Carrier, Hz.
+    pub frequency: f64,
+    /// Shift of a one-way carrier at the receiver, Hz.
+    pub doppler_one_way: f64,
+    /// Shift of a carrier sent from the station and coherently returned, Hz.
+    pub doppler_two_way: f64,
+    /// Free-space path loss at `frequency`, dB.
+    pub path_loss: f64,
+    /// Target elevation above the station's horizon.
+    pub elevation: f64,
+    /// Earth frame the station is fixed in.
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for Comms {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "station: {}, target: {}, range: {} km, range rate: {} km/s, light time: {} s, \
+             round trip: {} s, doppler: {} Hz one-way, {} Hz two-way at {} Hz, \
+             path loss: {} dB, el: {}, orientation: {}, u: {}",
+            self.station,
+            self.target,
+            self.range,
+            self.range_rate,
+            self.light_time,
+            self.round_trip,
+            self.doppler_one_way,
+            self.doppler_two_way,
+            self.frequency,
+            self.path_loss,
+            self.elevation,
+            self.orientation,
+            self.units
+        )
+    }
+}
+
+impl Angular for Comms {
+    fn to_degrees(&self) -> Comms {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Comms {
+                elevation: self.elevation.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Comms {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Comms {
+                elevation: self.elevation.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+type State = [f64; 6];
+
+/// State of a point fixed in `frame`, relative to that frame's center, in
+/// J2000.
+fn fixed_state(_lock: &SpiceLock, frame: &str, et: f64, point: Vec3) -> State {
+    let mut xform = [[0.0; 6]; 6];
+    unsafe {
+        spice::c::sxform_c(cstr!(frame), cstr!("J2000"), et, xform.as_mut_ptr());
+    }
+    let fixed = [point[0], point[1], point[2], 0.0, 0.0, 0.0];
+    let mut state = [0.0; 6];
+    for (i, row) in xform.iter().enumerate() {
+        state[i] = row.iter().zip(&fixed).map(|(a, b)| a * b).sum();
+    }
+    state
+}
+
+/// State of `target` relative to Earth's center, in J2000.
+fn target_state(lock: &SpiceLock, et: f64, target: &CommsTarget) -> Result<State, String> {
+    let body = match target {
+        CommsTarget::Site(_) => "MOON",
+        CommsTarget::Body(name) => name.as_str(),
+    };
+    let mut state = [0.0; 6];
+    let mut lt = 0.0;
+    crate::spice_try(lock, || unsafe {
+        spice::c::spkezr_c(
+            cstr!(body),
+            et,
+            cstr!("J2000"),
+            cstr!("NONE"),
+            cstr!("EARTH"),
+            state.as_mut_ptr(),
+            &mut lt,
+        );
+    })?;
+    if let CommsTarget::Site(p) = target {
+        let site = fixed_state(lock, FRAME, et, crate::ellipsoid_point(lock, *p));
+        for (s, offset) in state.iter_mut().zip(site) {
+            *s += offset;
+        }
+    }
+    Ok(state)
+}
+
+/// Range, range rate, light time, Doppler at `frequency` Hz and path loss
+/// between `station` and `target` at `t`. Geometric: states are not
+/// corrected for light time.
+pub fn comms(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    station: &Station,
+    target: &CommsTarget,
+    frequency: f64,
+) -> Result<Comms, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+
+        let topo = Topo::new(&lock, station.position());
+        let orientation = EarthOrientation::at(&lock, &[et]);
+        let earth_frame = orientation.frame();
+        let ground = fixed_state(&lock, earth_frame, et, topo.point);
+        let far = target_state(&lock, et, target)?;
+        let r = sub([far[0], far[1], far[2]], [ground[0], ground[1], ground[2]]);
+        let v = sub([far[3], far[4], far[5]], [ground[3], ground[4], ground[5]]);
+        let range = norm(r);
+        let range_rate = dot(r, v) / range;
+
+        // elevation in Earth's frame, where the station's vertical is fixed
+        let to_earth_frame = rotation(&lock, "J2000", earth_frame, et);
+        let elevation = topo.elevation(add(topo.point, mxv(to_earth_frame, r)));
+
+        let beta = range_rate / CLIGHT_KM_S;
+        Ok(Comms {
+            station: station.name.clone(),
+            target: target.to_string(),
+            range,
+            range_rate,
+            light_time: range / CLIGHT_KM_S,
+            round_trip: 2.0 * range / CLIGHT_KM_S,
+            frequency,
+            doppler_one_way: frequency * (1.0 / (1.0 + beta) - 1.0),
+            doppler_two_way: frequency * ((1.0 - beta) / (1.0 + beta) - 1.0),
+            // (4 pi d / wavelength)^2, with d and c both in km
+            path_loss: 20.0 * (4.0 * PI * range * frequency / CLIGHT_KM_S).log10(),
+            elevation,
+            orientation,
+            units: UnitSpecifier::Radians,
+        })
+    })?
+}
@@ -0,0 +1,485 @@
+//! Earth as it appears from a lunar site.
+//!
+//! Phase, size and orientation of Earth's disk in the local sky, and the
+//! point on Earth directly below the site (which side of Earth faces it).
+//! Positions are geometric; Earth's rotation is taken at the light-time
+//! corrected epoch, so the sub-observer longitude is what a camera sees.
+//!
+//! Earth's body-fixed frame is ITRF93 wherever the high-precision binary
+//! PCK covers the epoch, and the IAU_EARTH model otherwise; results say
+//! which was used.
+
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+const FRAME: &str = "MOON_ME_DE440_ME421";
+const CLIGHT_KM_S: f64 = 299_792.458;
+
+/// Which model of Earth's orientation an Earth-fixed result used.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EarthOrientation {
+    /// Measured orientation from `earth_latest_high_prec.bpc`, including
+    /// nutation and polar motion; good to milliarcseconds.
+    #[serde(rename = "ITRF93")]
+    Itrf93,
+    /// The IAU rotation model from the text PCK, which leaves out nutation
+    /// and polar motion and is off by several arcseconds or more.
+    #[serde(rename = "IAU_EARTH")]
+    IauEarth,
+}
+
+impl EarthOrientation {
+    /// The SPICE frame name.
+    pub fn frame(&self) -> &'static str {
+        match self {
+            EarthOrientation::Itrf93 => "ITRF93",
+            EarthOrientation::IauEarth => "IAU_EARTH",
+        }
+    }
+
+    /// ITRF93 if the loaded kernels cover every one of `ets`, else IAU_EARTH.
+    pub(crate) fn at(lock: &SpiceLock, ets: &[f64]) -> EarthOrientation {
+        let covered = ets
+            .iter()
+            .all(|&et| crate::spice_try(lock, || rotation(lock, "J2000", "ITRF93", et)).is_ok());
+        if covered {
+            EarthOrientation::Itrf93
+        } else {
+            EarthOrientation::IauEarth
+        }
+    }
+}
+
+impl std::fmt::Display for EarthOrientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.frame())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct EarthView {
+    /// Fraction of Earth's disk that is lit, 0 to 1.
+    pub illuminated_fraction: f64,
+    /// Sun-Earth-site angle; 0 is "full Earth".
+    pub phase: f64,
+    /// Apparent diameter of Earth's equator.
+    pub angular_diameter: f64,
+    /// Direction of Earth's north pole on its disk, from the local zenith,
+    /// positive toward the left as the site faces Earth (like position
+    /// angles on the sky, north through east).
+    pub north_pa: f64,
+    /// Planetocentric point on Earth with the site at its zenith.
+    pub sub_lon: f64,
+    pub sub_lat: f64,
+    /// Distance to Earth's center, km.
+    pub range: f64,
+    /// Earth frame behind `north_pa` and the sub-site point.
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for EarthView {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "illuminated: {}, phase: {}, diameter: {}, north pa: {}, sub lon: {}, sub lat: {}, \
+             range: {} km, orientation: {}, u: {}",
+            self.illuminated_fraction,
+            self.phase,
+            self.angular_diameter,
+            self.north_pa,
+            self.sub_lon,
+            self.sub_lat,
+            self.range,
+            self.orientation,
+            self.units
+        )
+    }
+}
+
+impl Angular for EarthView {
+    fn to_degrees(&self) -> EarthView {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => EarthView {
+                phase: self.phase.to_degrees(),
+                angular_diameter: self.angular_diameter.to_degrees(),
+                north_pa: self.north_pa.to_degrees(),
+                sub_lon: self.sub_lon.to_degrees(),
+                sub_lat: self.sub_lat.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> EarthView {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => EarthView {
+                phase: self.phase.to_radians(),
+                angular_diameter: self.angular_diameter.to_radians(),
+                north_pa: self.north_pa.to_radians(),
+                sub_lon: self.sub_lon.to_radians(),
+                sub_lat: self.sub_lat.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// An observer's place and local axes in an Earth-fixed frame.
+pub(crate) struct Topo {
+    pub(crate) point: Vec3,
+    up: Vec3,
+    east: Vec3,
+    north: Vec3,
+}
+
+impl Topo {
+    pub(crate) fn new(lock: &SpiceLock, observer: Position) -> Topo {
+        let observer = observer.on(Body::Earth);
+        let p = observer.to_radians();
+        let up = unit(p.lat, p.lon);
+        let east = normalize(cross([0.0, 0.0, 1.0], up));
+        Topo {
+            point: crate::ellipsoid_point(lock, observer),
+            up,
+            east,
+            north: cross(up, east),
+        }
+    }
+
+    /// Azimuth (counterclockwise from north) and elevation of `target`,
+    /// given from Earth's center.
+    pub(crate) fn azel(&self, target: Vec3) -> (f64, f64) {
+        let d = normalize(sub(target, self.point));
+        let az = -dot(d, self.east).atan2(dot(d, self.north));
+        (az.rem_euclid(2.0 * PI), self.elevation(target))
+    }
+
+    /// Elevation of `target`, given from Earth's center.
+    pub(crate) fn elevation(&self, target: Vec3) -> f64 {
+        dot(normalize(sub(target, self.point)), self.up)
+            .clamp(-1.0, 1.0)
+            .asin()
+    }
+}
+
+pub(crate) fn rotation(_lock: &SpiceLock, from: &str, to: &str, et: f64) -> [[f64; 3]; 3] {
+    let mut m = [[0.0; 3]; 3];
+    unsafe {
+        spice::c::pxform_c(cstr!(from), cstr!(to), et, m.as_mut_ptr());
+    }
+    m
+}
+
+/// Earth's appearance from the site at `pos` at `t`.
+pub fn earth_view(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+) -> Result<EarthView, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+
+        let site = crate::ellipsoid_point(&lock, pos);
+        let (earth, _lt) = lock.spkpos("EARTH", et, FRAME, "NONE", "MOON");
+        let (sun, _lt) = lock.spkpos("SUN", et, FRAME, "NONE", "MOON");
+        let to_earth = sub(earth, site);
+        let range = norm(to_earth);
+        let phase = angle(scale(to_earth, -1.0), sub(sun, earth));
+        let radius = crate::body_radii(&lock, "EARTH")[0];
+
+        // Earth as it was when the light left it
+        let epoch = et - range / CLIGHT_KM_S;
+        let orientation = EarthOrientation::at(&lock, &[epoch]);
+        let earth_frame = orientation.frame();
+        let pole = mxv(rotation(&lock, earth_frame, FRAME, epoch), [0.0, 0.0, 1.0]);
+        let line = normalize(to_earth);
+        let p = pos.to_radians();
+        let up = unit(p.lat, p.lon);
+        let up = normalize(sub(up, scale(line, dot(up, line))));
+        let left = cross(up, line);
+        let north_pa = dot(pole, left).atan2(dot(pole, up)).rem_euclid(2.0 * PI);
+
+        let mut below = mxv(
+            rotation(&lock, FRAME, earth_frame, epoch),
+            scale(to_earth, -1.0),
+        );
+        let (mut r, mut sub_lon, mut sub_lat) = (0.0, 0.0, 0.0);
+        unsafe {
+            spice::c::reclat_c(below.as_mut_ptr(), &mut r, &mut sub_lon, &mut sub_lat);
+        }
+
+        EarthView {
+            illuminated_fraction: 0.5 * (1.0 + phase.cos()),
+            phase,
+            angular_diameter: 2.0 * (radius / range).asin(),
+            north_pa,
+            sub_lon,
+            sub_lat,
+            range,
+            orientation,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
+
+pub const DEFAULT_TRACK_STEP_S: f64 = 3600.0;
+pub const DEFAULT_TRACK_DAYS: i64 = 28;
+pub const MAX_TRACK_POINTS: usize = 50_000;
+/// Most date labels on a plot; longer tracks label every few days.
+const MAX_LABELS: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct TrackPoint {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    pub az: f64,
+    pub el: f64,
+}
+
+/// Earth's az/el from a site over a window, and the box it stays in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EarthTrack {
+    pub points: Vec<TrackPoint>,
+    /// Azimuth extent, going counterclockwise from `az_min` to `az_max`.
+    pub az_min: f64,
+    pub az_max: f64,
+    pub el_min: f64,
+    pub el_max: f64,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for EarthTrack {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "points: {}, az: {} to {}, el: {} to {}, u: {}",
+            self.points.len(),
+            self.az_min,
+            self.az_max,
+            self.el_min,
+            self.el_max,
+            self.units
+        )?;
+        for point in &self.points {
+            write!(f, "\n{} {} {}", point.t, point.az, point.el)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for EarthTrack {
+    fn to_degrees(&self) -> EarthTrack {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => EarthTrack {
+                points: self
+                    .points
+                    .iter()
+                    .map(|p| TrackPoint {
+                        az: p.az.to_degrees(),
+                        el: p.el.to_degrees(),
+                        ..*p
+                    })
+                    .collect(),
+                az_min: self.az_min.to_degrees(),
+                az_max: self.az_max.to_degrees(),
+                el_min: self.el_min.to_degrees(),
+                el_max: self.el_max.to_degrees(),
+                units: UnitSpecifier::Degrees,
+            },
+        }
+    }
+    fn to_radians(&self) -> EarthTrack {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => EarthTrack {
+                points: self
+                    .points
+                    .iter()
+                    .map(|p| TrackPoint {
+                        az: p.az.to_radians(),
+                        el: p.el.to_radians(),
+                        ..*p
+                    })
+                    .collect(),
+                az_min: self.az_min.to_radians(),
+                az_max: self.az_max.to_radians(),
+                el_min: self.el_min.to_radians(),
+                el_max: self.el_max.to_radians(),
+                units: UnitSpecifier::Radians,
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Signed difference `a - b` of two angles, in (-π, π].
+fn wrapped(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(2.0 * PI);
+    if d > PI {
+        d - 2.0 * PI
+    } else {
+        d
+    }
+}
+
+impl EarthTrack {
+    /// One `t,az,el` line per point, after a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("t,az,el\n");
+        for point in &self.points {
+            let t = point
+                .t
+                .format(&time::format_description::well_known::Iso8601::DEFAULT)
+                .unwrap_or_default();
+            csv.push_str(&format!("{},{},{}\n", t, point.az, point.el));
+        }
+        csv
+    }
+
+    /// The loop on az/el axes in degrees, to the same scale both ways, as
+    /// seen facing Earth: azimuth increases to the left. The first point of
+    /// each day (or of every few days) is marked with its date.
+    pub fn to_svg(&self) -> String {
+        use std::fmt::Write;
+        const W: f64 = 560.0;
+        const H: f64 = 420.0;
+        const MARGIN: f64 = 50.0;
+        let track = self.to_degrees();
+        let (az_span, el_span) = (
+            (track.az_max - track.az_min).rem_euclid(360.0).max(1e-3),
+            (track.el_max - track.el_min).max(1e-3),
+        );
+        let scale = ((W - 2.0 * MARGIN) / az_span).min((H - 2.0 * MARGIN) / el_span);
+        let x0 = W / 2.0 + scale * az_span / 2.0;
+        let y0 = H / 2.0 + scale * el_span / 2.0;
+        let point = |az: f64, el: f64| {
+            let az = (az - track.az_min).rem_euclid(360.0);
+            (x0 - scale * az, y0 - scale * (el - track.el_min))
+        };
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{W}\" height=\"{H}\" \
+             viewBox=\"0 0 {W} {H}\" font-family=\"sans-serif\" font-size=\"11\">"
+        );
+        let (left, top) = point(track.az_max, track.el_max);
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" \
+             stroke=\"#bbb\" stroke-dasharray=\"4 4\"/>",
+            left,
+            top,
+            scale * az_span,
+            scale * el_span
+        );
+        let mut path = String::new();
+        for (i, p) in track.points.iter().enumerate() {
+            let (x, y) = point(p.az, p.el);
+            let _ = write!(path, "{}{:.1},{:.1} ", if i == 0 { "M" } else { "L" }, x, y);
+        }
+        let _ = write!(
+            svg,
+            "<path d=\"{}\" fill=\"none\" stroke=\"#2a6fb0\" stroke-width=\"1.5\"/>",
+            path
+        );
+
+        let days: Vec<&TrackPoint> = track
+            .points
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| *i == 0 || p.t.date() != track.points[i - 1].t.date())
+            .map(|(_, p)| p)
+            .collect();
+        let every = days.len().div_ceil(MAX_LABELS).max(1);
+        for p in days.iter().step_by(every) {
+            let (x, y) = point(p.az, p.el);
+            let _ = write!(
+                svg,
+                "<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"2.5\" fill=\"#2a6fb0\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                x + 4.0,
+                y - 4.0,
+                p.t.date()
+            );
+        }
+        let _ = write!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">az {:.2} to {:.2}, \
+             el {:.2} to {:.2} (degrees)</text>",
+            W / 2.0,
+            H - 12.0,
+            track.az_min,
+            track.az_max,
+            track.el_min,
+            track.el_max
+        );
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+/// Earth's az/el from `pos` every `step` seconds from `t` to `until`.
+pub fn earth_track(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    until: DateTime,
+    step: f64,
+    pos: Position,
+) -> Result<EarthTrack, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    let points = crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let end = lock.str2et(crate::to_cspice_string(until).as_str());
+        let frame = crate::body_frame(&lock, pos.body, &[et, end]);
+
+        let mut points = Vec::new();
+        let mut at = et;
+        while at <= end {
+            let azel = crate::target_azel_et(&lock, at, pos, frame, "EARTH");
+            points.push(TrackPoint {
+                t: t + time::Duration::seconds_f64(at - et),
+                az: azel.az,
+                el: azel.el,
+            });
+            at += step;
+        }
+        points
+    })?;
+
+    // azimuths as offsets from the mean direction, so the box doesn't break
+    // where azimuth wraps
+    let (s, c) = points
+        .iter()
+        .fold((0.0, 0.0), |(s, c), p| (s + p.az.sin(), c + p.az.cos()));
+    let mean = s.atan2(c);
+    let offsets = points.iter().map(|p| wrapped(p.az, mean));
+    let lowest = offsets.clone().fold(f64::INFINITY, f64::min);
+    let highest = offsets.fold(f64::NEG_INFINITY, f64::max);
+    let els = points.iter().map(|p| p.el);
+
+    Ok(EarthTrack {
+        az_min: (mean + lowest).rem_euclid(2.0 * PI),
+        az_max: (mean + highest).rem_euclid(2.0 * PI),
+        el_min: els.clone().fold(f64::INFINITY, f64::min),
+        el_max: els.fold(f64::NEG_INFINITY, f64::max),
+        points,
+        units: UnitSpecifier::Radians,
+    })
+}
@@ -0,0 +1,294 @@
+//! Lunar eclipses seen from the ground: Earth passing in front of the Sun.
+//!
+//! `gfsep_c` finds when, seen from the Moon's center, the limbs of the Sun
+//! and Earth come closer than the parallax of a site on the lunar surface.
+//! Those windows bracket the eclipses at any site, grazing ones at the limb
+//! included, and each is then searched at the site itself, where the Sun and
+//! Earth are treated as disks: the site is in the penumbra while Earth covers
+//! part of the Sun and in the umbra while it covers all of it. Light time is
+//! corrected; terrain is ignored.
+
+use crate::search::{changes, greatest, spans};
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+const FRAME: &str = "MOON_ME_DE440_ME421";
+pub const DEFAULT_SPAN_DAYS: i64 = 366;
+/// Longest search, days; about five years, which fits the worker timeout.
+pub const MAX_SPAN_DAYS: i64 = 1827;
+/// `gfsep_c` step. The widened windows outlast even grazing eclipses.
+const SEARCH_STEP_S: f64 = 600.0;
+/// Earth at lunar perigee, km: the largest parallax a site can have.
+const PERIGEE_KM: f64 = 356_000.0;
+const SCAN_STEP_S: f64 = 60.0;
+/// Room in the result window, in intervals: about 2.5 eclipses a year.
+const MAX_WINDOWS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EclipseKind {
+    /// Earth covers part of the Sun.
+    Penumbral,
+    /// Earth covers all of the Sun.
+    Umbral,
+}
+
+impl std::fmt::Display for EclipseKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EclipseKind::Penumbral => write!(f, "penumbral"),
+            EclipseKind::Umbral => write!(f, "umbral"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct EclipsePhase {
+    pub kind: EclipseKind,
+    #[serde(with = "default_datetime_standard")]
+    pub start: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub end: DateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SiteEclipse {
+    #[serde(with = "default_datetime_standard")]
+    pub start: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub end: DateTime,
+    /// Time of greatest eclipse.
+    #[serde(with = "default_datetime_standard")]
+    pub max_at: DateTime,
+    /// Largest fraction of the solar disk's area covered, 0 to 1.
+    pub max_fraction: f64,
+    /// Whether the Sun is above the horizon at greatest eclipse; if not,
+    /// the site is in night anyway.
+    pub sun_up: bool,
+    /// In order: penumbral, then umbral and penumbral again if total.
+    pub phases: Vec<EclipsePhase>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Eclipses {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub until: DateTime,
+    pub eclipses: Vec<SiteEclipse>,
+}
+
+impl std::fmt::Display for Eclipses {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "eclipses: {}", self.eclipses.len())?;
+        for eclipse in &self.eclipses {
+            write!(
+                f,
+                "\n{} to {}, max {} at {}, sun up: {}",
+                eclipse.start, eclipse.end, eclipse.max_fraction, eclipse.max_at, eclipse.sun_up
+            )?;
+            for phase in &eclipse.phases {
+                write!(f, "\n  {}: {} to {}", phase.kind, phase.start, phase.end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A CSPICE double precision window, as made by `SPICEDOUBLE_CELL`.
+struct Window {
+    // the cell points into this buffer, which never moves once allocated
+    _data: Vec<f64>,
+    cell: spice::c::SpiceCell,
+}
+
+impl Window {
+    fn new(intervals: usize) -> Window {
+        let ctrl = spice::c::SPICE_CELL_CTRLSZ as usize;
+        let size = 2 * intervals;
+        let mut data = vec![0.0; ctrl + size];
+        let base = data.as_mut_ptr();
+        let cell = spice::c::SpiceCell {
+            dtype: spice::c::_SpiceDataType_SPICE_DP,
+            length: 0,
+            size: size as i32,
+            card: 0,
+            isSet: spice::c::SPICETRUE as i32,
+            adjust: spice::c::SPICEFALSE as i32,
+            init: spice::c::SPICEFALSE as i32,
+            base: base as *mut std::ffi::c_void,
+            data: unsafe { base.add(ctrl) } as *mut std::ffi::c_void,
+        };
+        Window { _data: data, cell }
+    }
+
+    fn insert(&mut self, left: f64, right: f64) {
+        unsafe { spice::c::wninsd_c(left, right, &mut self.cell) }
+    }
+
+    fn intervals(&mut self) -> Vec<(f64, f64)> {
+        let n = unsafe { spice::c::wncard_c(&mut self.cell) };
+        (0..n)
+            .map(|i| {
+                let (mut left, mut right) = (0.0, 0.0);
+                unsafe { spice::c::wnfetd_c(&mut self.cell, i, &mut left, &mut right) };
+                (left, right)
+            })
+            .collect()
+    }
+}
+
+/// Area of overlap of two disks of radii `a` and `b` with centers `d` apart.
+fn overlap(a: f64, b: f64, d: f64) -> f64 {
+    if d >= a + b {
+        return 0.0;
+    }
+    if d <= (a - b).abs() {
+        return PI * a.min(b).powi(2);
+    }
+    let ca = ((d * d + a * a - b * b) / (2.0 * d * a)).clamp(-1.0, 1.0);
+    let cb = ((d * d + b * b - a * a) / (2.0 * d * b)).clamp(-1.0, 1.0);
+    let k = (-d + a + b) * (d + a - b) * (d - a + b) * (d + a + b);
+    a * a * ca.acos() + b * b * cb.acos() - 0.5 * k.max(0.0).sqrt()
+}
+
+/// Sun and Earth as seen from `point` at `et`.
+struct Disks {
+    /// Angular radii, radians.
+    sun: f64,
+    earth: f64,
+    /// Angle between their centers, radians.
+    separation: f64,
+}
+
+impl Disks {
+    fn at(lock: &SpiceLock, et: f64, point: Vec3, radii: (f64, f64)) -> Disks {
+        let (sun, _lt) = lock.spkpos("SUN", et, FRAME, "LT", "MOON");
+        let (earth, _lt) = lock.spkpos("EARTH", et, FRAME, "LT", "MOON");
+        let (sun, earth) = (sub(sun, point), sub(earth, point));
+        Disks {
+            sun: (radii.0 / norm(sun)).asin(),
+            earth: (radii.1 / norm(earth)).asin(),
+            separation: angle(sun, earth),
+        }
+    }
+
+    fn partial(&self) -> bool {
+        self.separation < self.sun + self.earth
+    }
+
+    fn total(&self) -> bool {
+        self.separation + self.sun <= self.earth
+    }
+
+    fn fraction(&self) -> f64 {
+        overlap(self.sun, self.earth, self.separation) / (PI * self.sun * self.sun)
+    }
+}
+
+/// Eclipses of the Sun by Earth seen from `pos` between `t` and `until`.
+pub fn eclipses(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    until: DateTime,
+    pos: Position,
+) -> Result<Eclipses, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    let et = lock.str2et(crate::to_cspice_string(t).as_str());
+    let end = lock.str2et(crate::to_cspice_string(until).as_str());
+
+    // seen from the center, a site moves Earth by at most the angle the
+    // site's distance from the center subtends at Earth
+    let reach = crate::body_radii(&lock, "MOON")[0] + pos.alt.max(0.0);
+    let parallax = (reach / PERIGEE_KM).asin();
+    let mut cnfine = Window::new(1);
+    cnfine.insert(et, end);
+    let mut widened = Window::new(MAX_WINDOWS);
+    crate::spice_try(&lock, || unsafe {
+        // separation of the limbs, negative while the disks overlap
+        spice::c::gfsep_c(
+            cstr!("SUN"),
+            cstr!("SPHERE"),
+            cstr!("NULL"),
+            cstr!("EARTH"),
+            cstr!("SPHERE"),
+            cstr!("NULL"),
+            cstr!("LT"),
+            cstr!("MOON"),
+            cstr!("<"),
+            parallax,
+            0.0,
+            SEARCH_STEP_S,
+            MAX_WINDOWS as i32,
+            &mut cnfine.cell,
+            &mut widened.cell,
+        );
+    })?;
+
+    let point = crate::ellipsoid_point(&lock, Position { alt: 0.0, ..pos });
+    let radii = (
+        crate::body_radii(&lock, "SUN")[0],
+        crate::body_radii(&lock, "EARTH")[0],
+    );
+    let disks = |et: f64| Disks::at(&lock, et, point, radii);
+    let to_time = |at: f64| t + time::Duration::seconds_f64(at - et);
+
+    let mut eclipses = Vec::new();
+    for (a, b) in widened.intervals() {
+        let partial = spans(
+            disks(a).partial(),
+            a,
+            b,
+            changes(a, b, SCAN_STEP_S, |et| disks(et).partial()).map(|c| c.0),
+        );
+        for (start, stop) in partial {
+            let umbral = spans(
+                disks(start).total(),
+                start,
+                stop,
+                changes(start, stop, SCAN_STEP_S, |et| disks(et).total()).map(|c| c.0),
+            );
+            // when Earth's center is deepest into the Sun's disk
+            let max_at = greatest(start, stop, SCAN_STEP_S, |et| {
+                let d = disks(et);
+                d.earth - d.separation
+            });
+
+            let mut phases = Vec::new();
+            let mut from = start;
+            for (u0, u1) in &umbral {
+                if *u0 > from {
+                    phases.push((EclipseKind::Penumbral, from, *u0));
+                }
+                phases.push((EclipseKind::Umbral, *u0, *u1));
+                from = *u1;
+            }
+            if stop > from {
+                phases.push((EclipseKind::Penumbral, from, stop));
+            }
+
+            eclipses.push(SiteEclipse {
+                start: to_time(start),
+                end: to_time(stop),
+                max_at: to_time(max_at),
+                max_fraction: disks(max_at).fraction().clamp(0.0, 1.0),
+                sun_up: crate::azel_from(&lock, max_at, point, "SUN").el > 0.0,
+                phases: phases
+                    .into_iter()
+                    .map(|(kind, a, b)| EclipsePhase {
+                        kind,
+                        start: to_time(a),
+                        end: to_time(b),
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(Eclipses { t, until, eclipses })
+}
@@ -0,0 +1,335 @@
+//! Chebyshev fits of sun and Earth pointing for a fixed site.
+//!
+//! For high-rate queries (simulation playback, hardware in the loop) a fit is
+//! built once over a time window and then evaluated without the SPICE lock.
+//! The window is cut into segments, and in each segment the components of the
+//! unit pointing vector in the site's az/el frame and the range are fit at
+//! Chebyshev nodes. After building, the fit is compared with `target_azel` at
+//! points between the nodes and the worst angular and range errors are kept
+//! as the fit's error bound.
+
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_SEGMENT_S: f64 = 86400.0;
+pub const DEFAULT_DEGREE: usize = 12;
+pub const MAX_DEGREE: usize = 32;
+pub const MAX_SEGMENTS: usize = 400;
+const CHECKS_PER_SEGMENT: usize = 16;
+
+struct Segment {
+    /// One `[x, y, z, r]` coefficient per degree.
+    coeffs: Vec<[f64; 4]>,
+}
+
+pub struct GeometryFit {
+    pub target: String,
+    pub pos: Position,
+    pub start: DateTime,
+    pub end: DateTime,
+    pub degree: usize,
+    segment_s: f64,
+    segments: Vec<Segment>,
+    /// Worst angle between fit and SPICE pointing at the check points, radians.
+    pub max_angle_error: f64,
+    /// Worst range difference at the check points, km.
+    pub max_range_error: f64,
+}
+
+fn sample(lock: &SpiceLock, t: DateTime, pos: Position, frame: &str, target: &str) -> [f64; 4] {
+    let et = lock.str2et(crate::to_cspice_string(t).as_str());
+    let azel = crate::target_azel_et(lock, et, pos, frame, target);
+    [
+        azel.el.cos() * azel.az.cos(),
+        azel.el.cos() * azel.az.sin(),
+        azel.el.sin(),
+        azel.r,
+    ]
+}
+
+fn clenshaw(coeffs: &[[f64; 4]], x: f64) -> [f64; 4] {
+    let mut b1 = [0.0; 4];
+    let mut b2 = [0.0; 4];
+    for c in coeffs.iter().skip(1).rev() {
+        let mut b = [0.0; 4];
+        for i in 0..4 {
+            b[i] = 2.0 * x * b1[i] - b2[i] + c[i];
+        }
+        b2 = b1;
+        b1 = b;
+    }
+    let mut f = [0.0; 4];
+    for i in 0..4 {
+        f[i] = x * b1[i] - b2[i] + coeffs[0][i];
+    }
+    f
+}
+
+fn angle_between(a: [f64; 4], b: [f64; 4]) -> f64 {
+    let cross = [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let cross = (cross[0].powi(2) + cross[1].powi(2) + cross[2].powi(2)).sqrt();
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    cross.atan2(dot)
+}
+
+impl GeometryFit {
+    /// Fits the pointing to `target` from `pos` between `start` and `end`.
+    pub fn build(
+        sl_mutex: Arc<Mutex<SpiceLock>>,
+        target: &str,
+        pos: Position,
+        start: DateTime,
+        end: DateTime,
+        segment_s: f64,
+        degree: usize,
+    ) -> Result<GeometryFit, String> {
+        let span = (end - start).as_seconds_f64();
+        if span <= 0.0 {
+            return Err("end must be after start".to_string());
+        }
+        if !(segment_s > 0.0 && segment_s.is_finite()) {
+            return Err("segment length must be positive and finite".to_string());
+        }
+        if degree == 0 || degree > MAX_DEGREE {
+            return Err(format!("degree must be between 1 and {}", MAX_DEGREE));
+        }
+        let count = (span / segment_s).ceil() as usize;
+        if count > MAX_SEGMENTS {
+            return Err(format!("window needs more than {} segments", MAX_SEGMENTS));
+        }
+
+        let lock = crate::lock_spice(&sl_mutex);
+        let frame = crate::body_frame(
+            &lock,
+            pos.body,
+            &[
+                lock.str2et(crate::to_cspice_string(start).as_str()),
+                lock.str2et(crate::to_cspice_string(end).as_str()),
+            ],
+        );
+        let n = degree + 1;
+        let mut segments = Vec::with_capacity(count);
+        for i in 0..count {
+            let seg_start = i as f64 * segment_s;
+            let seg_len = segment_s.min(span - seg_start);
+
+            let values: Vec<[f64; 4]> = (0..n)
+                .map(|k| {
+                    let x = (PI * (k as f64 + 0.5) / n as f64).cos();
+                    let s = seg_start + (x + 1.0) * 0.5 * seg_len;
+                    let at = start + time::Duration::seconds_f64(s);
+                    sample(&lock, at, pos, frame, target)
+                })
+                .collect();
+
+            let coeffs = (0..n)
+                .map(|j| {
+                    let mut c = [0.0; 4];
+                    for (k, v) in values.iter().enumerate() {
+                        let w = (PI * j as f64 * (k as f64 + 0.5) / n as f64).cos();
+                        for i in 0..4 {
+                            c[i] += 2.0 / n as f64 * v[i] * w;
+                        }
+                    }
+                    if j == 0 {
+                        c.iter_mut().for_each(|c| *c *= 0.5);
+                    }
+                    c
+                })
+                .collect();
+            segments.push(Segment { coeffs });
+        }
+
+        let mut fit = GeometryFit {
+            target: target.to_string(),
+            pos,
+            start,
+            end,
+            degree,
+            segment_s,
+            segments,
+            max_angle_error: 0.0,
+            max_range_error: 0.0,
+        };
+
+        let checks = count * CHECKS_PER_SEGMENT;
+        for i in 0..checks {
+            let s = span * (i as f64 + 0.5) / checks as f64;
+            let t = start + time::Duration::seconds_f64(s);
+            let truth = sample(&lock, t, pos, frame, target);
+            let fitted = fit.vector_at(s);
+            fit.max_angle_error = fit.max_angle_error.max(angle_between(truth, fitted));
+            fit.max_range_error = fit.max_range_error.max((truth[3] - fitted[3]).abs());
+        }
+
+        Ok(fit)
+    }
+
+    fn vector_at(&self, s: f64) -> [f64; 4] {
+        let span = (self.end - self.start).as_seconds_f64();
+        let i = ((s / self.segment_s) as usize).min(self.segments.len() - 1);
+        let seg_start = i as f64 * self.segment_s;
+        let seg_len = self.segment_s.min(span - seg_start);
+        let x = 2.0 * (s - seg_start) / seg_len - 1.0;
+        clenshaw(&self.segments[i].coeffs, x)
+    }
+
+    /// Pointing at `t`, or `None` outside the fit window.
+    pub fn evaluate(&self, t: DateTime) -> Option<RAzEl> {
+        if t < self.start || t > self.end {
+            return None;
+        }
+        let v = self.vector_at((t - self.start).as_seconds_f64());
+        Some(RAzEl {
+            az: v[1].atan2(v[0]).rem_euclid(2.0 * PI),
+            el: v[2].atan2(v[0].hypot(v[1])),
+            r: v[3],
+            units: UnitSpecifier::Radians,
+            horizon_el: None,
+        })
+    }
+}
+
+/// Sun and Earth fits for one site over one window.
+pub struct SiteFit {
+    pub sun: GeometryFit,
+    pub earth: GeometryFit,
+}
+
+impl SiteFit {
+    pub fn build(
+        sl_mutex: Arc<Mutex<SpiceLock>>,
+        pos: Position,
+        start: DateTime,
+        end: DateTime,
+        segment_s: f64,
+        degree: usize,
+    ) -> Result<SiteFit, String> {
+        let sun = GeometryFit::build(sl_mutex.clone(), "SUN", pos, start, end, segment_s, degree)?;
+        let earth = GeometryFit::build(sl_mutex, "EARTH", pos, start, end, segment_s, degree)?;
+        Ok(SiteFit { sun, earth })
+    }
+
+    pub fn evaluate(&self, t: DateTime) -> Option<SiteGeometry> {
+        Some(SiteGeometry {
+            sun: self.sun.evaluate(t)?,
+            earth: self.earth.evaluate(t)?,
+        })
+    }
+
+    pub fn info(&self, id: u64) -> FitInfo {
+        FitInfo {
+            id,
+            start: self.sun.start,
+            end: self.sun.end,
+            segments: self.sun.segments.len(),
+            degree: self.sun.degree,
+            sun_max_error: self.sun.max_angle_error,
+            sun_max_range_error: self.sun.max_range_error,
+            earth_max_error: self.earth.max_angle_error,
+            earth_max_range_error: self.earth.max_range_error,
+            units: UnitSpecifier::Radians,
+        }
+    }
+}
+
+/// What a client needs to know about a fit it created.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct FitInfo {
+    pub id: u64,
+    #[serde(with = "default_datetime_standard")]
+    pub start: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub end: DateTime,
+    pub segments: usize,
+    pub degree: usize,
+    pub sun_max_error: f64,
+    pub sun_max_range_error: f64,
+    pub earth_max_error: f64,
+    pub earth_max_range_error: f64,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for FitInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "id: {}, segments: {}, degree: {}, sun error: {} ({} km), earth error: {} ({} km), u: {}",
+            self.id,
+            self.segments,
+            self.degree,
+            self.sun_max_error,
+            self.sun_max_range_error,
+            self.earth_max_error,
+            self.earth_max_range_error,
+            self.units
+        )
+    }
+}
+
+impl Angular for FitInfo {
+    fn to_degrees(&self) -> FitInfo {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => FitInfo {
+                sun_max_error: self.sun_max_error.to_degrees(),
+                earth_max_error: self.earth_max_error.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> FitInfo {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => FitInfo {
+                sun_max_error: self.sun_max_error.to_radians(),
+                earth_max_error: self.earth_max_error.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Fits created through the API, oldest dropped first. Like masks, they
+/// live in this process only, so an id is no use on another instance.
+pub struct FitRegistry {
+    fits: Mutex<std::collections::BTreeMap<u64, Arc<SiteFit>>>,
+    capacity: usize,
+}
+
+impl FitRegistry {
+    pub fn new(capacity: usize) -> FitRegistry {
+        FitRegistry {
+            fits: Mutex::new(std::collections::BTreeMap::new()),
+            capacity,
+        }
+    }
+
+    pub fn insert(&self, fit: SiteFit) -> (u64, Arc<SiteFit>) {
+        let fit = Arc::new(fit);
+        let mut fits = self.fits.lock().unwrap();
+        let id = fits.keys().next_back().map_or(1, |id| id + 1);
+        fits.insert(id, fit.clone());
+        while fits.len() > self.capacity {
+            fits.pop_first();
+        }
+        (id, fit)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<SiteFit>> {
+        self.fits.lock().unwrap().get(&id).cloned()
+    }
+}
@@ -0,0 +1,566 @@
+//! Local horizon masks.
+//!
+//! A mask is the elevation of the terrain skyline against azimuth, seen from
+//! one site. It comes from a CSV profile (surveyed, or exported from another
+//! tool) or is traced once from the loaded DSK, and replaces "elevation > 0"
+//! when deciding whether the Sun or Earth is actually visible. Azimuths are
+//! measured like those from `solar_azel`.
+
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_AZ_STEP: f64 = 1.0;
+/// Azimuth steps allowed for DSK masks, degrees; finer ones take too long
+/// to trace within a request.
+pub const MIN_AZ_STEP: f64 = 0.5;
+pub const MAX_AZ_STEP: f64 = 10.0;
+/// How far apart a mask's site and a request's may be, km.
+const SITE_TOLERANCE_KM: f64 = 0.001;
+/// Mean lunar radius, km.
+const MOON_RADIUS_KM: f64 = 1737.4;
+/// How far out terrain is searched for the skyline, km.
+pub const MAX_RANGE_KM: f64 = 150.0;
+pub const DEFAULT_STEP_S: f64 = 600.0;
+/// Upper bound on samples in one event search.
+pub const MAX_SAMPLES: usize = 100_000;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskSource {
+    Csv,
+    Dsk,
+    /// The geometric horizon, with no skyline.
+    Flat,
+}
+
+impl std::fmt::Display for MaskSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MaskSource::Csv => write!(f, "csv"),
+            MaskSource::Dsk => write!(f, "dsk"),
+            MaskSource::Flat => write!(f, "flat"),
+        }
+    }
+}
+
+/// Skyline elevation at each azimuth, sorted by azimuth in [0, 360°).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HorizonMask {
+    pub p: Position,
+    pub source: MaskSource,
+    pub az: Vec<f64>,
+    pub el: Vec<f64>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for HorizonMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "source: {}, points: {}, u: {}",
+            self.source,
+            self.az.len(),
+            self.units
+        )?;
+        for (az, el) in self.az.iter().zip(&self.el) {
+            write!(f, "\n{} {}", az, el)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for HorizonMask {
+    fn to_degrees(&self) -> HorizonMask {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => HorizonMask {
+                az: self.az.iter().map(|a| a.to_degrees()).collect(),
+                el: self.el.iter().map(|e| e.to_degrees()).collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> HorizonMask {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => HorizonMask {
+                az: self.az.iter().map(|a| a.to_radians()).collect(),
+                el: self.el.iter().map(|e| e.to_radians()).collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Summary returned when a mask is created.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct MaskInfo {
+    pub id: u64,
+    pub source: MaskSource,
+    pub points: usize,
+    /// Highest point of the skyline.
+    pub max_el: f64,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for MaskInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "id: {}, source: {}, points: {}, max el: {}, u: {}",
+            self.id, self.source, self.points, self.max_el, self.units
+        )
+    }
+}
+
+impl Angular for MaskInfo {
+    fn to_degrees(&self) -> MaskInfo {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => MaskInfo {
+                max_el: self.max_el.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> MaskInfo {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => MaskInfo {
+                max_el: self.max_el.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+impl HorizonMask {
+    fn new(p: Position, source: MaskSource, mut points: Vec<(f64, f64)>) -> HorizonMask {
+        for point in points.iter_mut() {
+            point.0 = point.0.rem_euclid(2.0 * PI);
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        HorizonMask {
+            p,
+            source,
+            az: points.iter().map(|p| p.0).collect(),
+            el: points.iter().map(|p| p.1).collect(),
+            units: UnitSpecifier::Radians,
+        }
+    }
+
+    /// A skyline at zero elevation all around: rises and sets over the
+    /// geometric horizon.
+    pub fn flat(p: Position) -> HorizonMask {
+        HorizonMask::new(p, MaskSource::Flat, vec![(0.0, 0.0)])
+    }
+
+    /// Parses `az,el` lines in degrees. Blank lines, `#` comments and a
+    /// header line are skipped.
+    pub fn from_csv(p: Position, csv: &str) -> Result<HorizonMask, String> {
+        let mut points = Vec::new();
+        for (n, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let parsed = match fields.as_slice() {
+                [az, el, ..] => az
+                    .parse::<f64>()
+                    .and_then(|az| Ok((az, el.parse::<f64>()?))),
+                _ => return Err(format!("line {}: expected az,el", n + 1)),
+            };
+            match parsed {
+                Ok((az, el)) if az.is_finite() && (-90.0..=90.0).contains(&el) => {
+                    points.push((az.to_radians(), el.to_radians()))
+                }
+                Ok(_) => return Err(format!("line {}: elevation out of range", n + 1)),
+                Err(_) if points.is_empty() => continue,
+                Err(_) => return Err(format!("line {}: expected numbers", n + 1)),
+            }
+        }
+        if points.is_empty() {
+            return Err("no az,el points in the csv".to_string());
+        }
+        Ok(HorizonMask::new(p, MaskSource::Csv, points))
+    }
+
+    /// Skyline elevation at `az`, interpolated linearly around the circle.
+    /// Both in radians.
+    /// Whether the mask was made for the site at `p`, give or take
+    /// `SITE_TOLERANCE_KM` of rounding.
+    pub fn is_for(&self, p: Position) -> bool {
+        let (a, b) = (self.p.to_radians(), p.to_radians());
+        let apart = angle(unit(a.lat, a.lon), unit(b.lat, b.lon)) * MOON_RADIUS_KM;
+        let above = (a.alt - b.alt).abs();
+        a.body == b.body && apart < SITE_TOLERANCE_KM && above < SITE_TOLERANCE_KM
+    }
+
+    pub fn elevation_at(&self, az: f64) -> f64 {
+        if self.units == UnitSpecifier::Degrees {
+            return self.to_radians().elevation_at(az);
+        }
+        let n = self.az.len();
+        let az = az.rem_euclid(2.0 * PI);
+        let i = self.az.partition_point(|a| *a <= az);
+        let (a0, e0) = if i == 0 {
+            (self.az[n - 1] - 2.0 * PI, self.el[n - 1])
+        } else {
+            (self.az[i - 1], self.el[i - 1])
+        };
+        let (a1, e1) = if i == n {
+            (self.az[0] + 2.0 * PI, self.el[0])
+        } else {
+            (self.az[i], self.el[i])
+        };
+        if a1 - a0 <= 0.0 {
+            return e0;
+        }
+        e0 + (e1 - e0) * (az - a0) / (a1 - a0)
+    }
+
+    /// How far `azel` is above the skyline, radians; negative when hidden.
+    pub fn clearance(&self, azel: &RAzEl) -> f64 {
+        let azel = azel.to_radians();
+        azel.el - self.elevation_at(azel.az)
+    }
+
+    pub fn info(&self, id: u64) -> MaskInfo {
+        let mask = self.to_radians();
+        MaskInfo {
+            id,
+            source: self.source,
+            points: self.az.len(),
+            max_el: mask.el.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            units: UnitSpecifier::Radians,
+        }
+    }
+
+    /// A polar plot of the sky: zenith at the center, the geometric horizon
+    /// as the outer solid ring, north up and east right, terrain shaded.
+    pub fn to_svg(&self) -> String {
+        const SIZE: f64 = 400.0;
+        const C: f64 = SIZE / 2.0;
+        // pixels per degree from the zenith; leaves room below the horizon
+        const SCALE: f64 = 160.0 / 90.0;
+        let mask = self.to_degrees();
+        let point = |az: f64, el: f64| {
+            let r = (90.0 - el) * SCALE;
+            // azimuths increase from north toward west
+            let a = az.to_radians();
+            (C - r * a.sin(), C - r * a.cos())
+        };
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" \
+             viewBox=\"0 0 {0} {0}\">",
+            SIZE
+        );
+        let mut path = String::new();
+        for (i, (az, el)) in mask.az.iter().zip(&mask.el).enumerate() {
+            let (x, y) = point(*az, *el);
+            let _ = write!(path, "{}{:.1},{:.1} ", if i == 0 { "M" } else { "L" }, x, y);
+        }
+        let _ = write!(
+            svg,
+            "<path d=\"{}Z\" fill=\"none\" stroke=\"#444\" stroke-width=\"1.5\"/>",
+            path
+        );
+        // shade between the skyline and a circle below the lowest point
+        let low = mask.el.iter().copied().fold(0.0, f64::min) - 5.0;
+        let _ = write!(
+            svg,
+            "<path d=\"{}Z M{:.1},{:.1} a{r:.1},{r:.1} 0 1,0 0,{d:.1} a{r:.1},{r:.1} 0 1,0 0,-{d:.1}Z\" \
+             fill=\"#8a7f70\" fill-opacity=\"0.5\" fill-rule=\"evenodd\"/>",
+            path,
+            C,
+            C - (90.0 - low) * SCALE,
+            r = (90.0 - low) * SCALE,
+            d = 2.0 * (90.0 - low) * SCALE
+        );
+        for el in [0.0, 30.0, 60.0] {
+            let dash = if el == 0.0 {
+                ""
+            } else {
+                " stroke-dasharray=\"4 4\""
+            };
+            let _ = write!(
+                svg,
+                "<circle cx=\"{C}\" cy=\"{C}\" r=\"{:.1}\" fill=\"none\" stroke=\"#999\"{}/>",
+                (90.0 - el) * SCALE,
+                dash
+            );
+        }
+        for (label, az) in [("N", 0.0), ("W", 90.0), ("S", 180.0), ("E", 270.0)] {
+            let (x, y) = point(az, -8.0);
+            let _ = write!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" \
+                 dominant-baseline=\"middle\" font-family=\"sans-serif\" \
+                 font-size=\"14\">{}</text>",
+                x, y, label
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+/// Traces the skyline around `p` from the loaded DSK, every `step` degrees
+/// of azimuth, out to `MAX_RANGE_KM`.
+pub fn from_dsk(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    p: Position,
+    step: f64,
+) -> Result<HorizonMask, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    trace(&lock, p, step)
+}
+
+/// As `from_dsk`, for callers that already hold the lock.
+pub(crate) fn trace(lock: &SpiceLock, p: Position, step: f64) -> Result<HorizonMask, String> {
+    let n = (360.0 / step).round() as usize;
+    let azimuths: Vec<f64> = (0..n).map(|i| (i as f64 * step).to_radians()).collect();
+    let points = skyline(lock, p, &azimuths)?;
+    Ok(HorizonMask::new(p, MaskSource::Dsk, points))
+}
+
+/// The DSK skyline's elevation at azimuth `az` (radians) from `p`.
+pub(crate) fn skyline_at(lock: &SpiceLock, p: Position, az: f64) -> Result<f64, String> {
+    Ok(skyline(lock, p, &[az])?[0].1)
+}
+
+/// (azimuth, elevation) of the DSK skyline around `p` at each of
+/// `azimuths`, radians.
+fn skyline(lock: &SpiceLock, p: Position, azimuths: &[f64]) -> Result<Vec<(f64, f64)>, String> {
+    // lunar DSKs are body-fixed, so any epoch will do
+    let et = 0.0;
+    let site = crate::site_point(lock, et, p, Surface::Terrain)?;
+    let radius = crate::body_radii(lock, "MOON")[0];
+    let pos = p.to_radians();
+    let up = unit(pos.lat, pos.lon);
+
+    // sample spacing grows with distance, as far terrain must be taller to show
+    let mut distances = Vec::new();
+    let mut d = 0.05;
+    while d < MAX_RANGE_KM {
+        distances.push(d);
+        d = d * 1.02 + 0.05;
+    }
+
+    let mut points = Vec::with_capacity(azimuths.len());
+    for &az in azimuths {
+        // compass bearing, clockwise from north
+        let bearing = -az;
+        let lonlat: Vec<[f64; 2]> = distances
+            .iter()
+            .map(|d| {
+                let delta = d / radius;
+                let lat = (pos.lat.sin() * delta.cos()
+                    + pos.lat.cos() * delta.sin() * bearing.cos())
+                .asin();
+                let lon = pos.lon
+                    + (bearing.sin() * delta.sin() * pos.lat.cos())
+                        .atan2(delta.cos() - pos.lat.sin() * lat.sin());
+                [lon, lat]
+            })
+            .collect();
+        let mut srfpts = vec![[0.0; 3]; lonlat.len()];
+        crate::spice_try(lock, || unsafe {
+            spice::c::latsrf_c(
+                cstr!(Surface::Terrain.spice_method()),
+                cstr!("MOON"),
+                et,
+                cstr!("MOON_ME_DE440_ME421"),
+                lonlat.len() as i32,
+                lonlat.as_ptr(),
+                srfpts.as_mut_ptr(),
+            );
+        })?;
+        let el = srfpts
+            .iter()
+            .map(|pt| dot(normalize(sub(*pt, site)), up).clamp(-1.0, 1.0).asin())
+            .fold(f64::NEG_INFINITY, f64::max);
+        points.push((az, el));
+    }
+    Ok(points)
+}
+
+/// A rise or set of a target over the mask.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct VisibilityEvent {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    pub rising: bool,
+    /// Azimuth where the target crosses the skyline.
+    pub az: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Visibility {
+    pub target: String,
+    pub visible: bool,
+    pub az: f64,
+    pub el: f64,
+    /// Skyline elevation at the target's azimuth.
+    pub mask_el: f64,
+    /// Rises and sets between `t` and `until`.
+    pub events: Vec<VisibilityEvent>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for Visibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "target: {}, visible: {}, az: {}, el: {}, mask el: {}, u: {}",
+            self.target, self.visible, self.az, self.el, self.mask_el, self.units
+        )?;
+        for event in &self.events {
+            let kind = if event.rising { "rise" } else { "set" };
+            write!(f, "\n{} {} az: {}", kind, event.t, event.az)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for Visibility {
+    fn to_degrees(&self) -> Visibility {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Visibility {
+                az: self.az.to_degrees(),
+                el: self.el.to_degrees(),
+                mask_el: self.mask_el.to_degrees(),
+                events: self
+                    .events
+                    .iter()
+                    .map(|e| VisibilityEvent {
+                        az: e.az.to_degrees(),
+                        ..*e
+                    })
+                    .collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Visibility {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Visibility {
+                az: self.az.to_radians(),
+                el: self.el.to_radians(),
+                mask_el: self.mask_el.to_radians(),
+                events: self
+                    .events
+                    .iter()
+                    .map(|e| VisibilityEvent {
+                        az: e.az.to_radians(),
+                        ..*e
+                    })
+                    .collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Whether `target` is above `mask` at `t`, and when it rises or sets over
+/// it until `until`, sampling every `step` seconds.
+pub fn visibility(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    mask: &HorizonMask,
+    target: &str,
+    t: DateTime,
+    until: Option<DateTime>,
+    step: f64,
+) -> Result<Visibility, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let end = until.map(|until| lock.str2et(crate::to_cspice_string(until).as_str()));
+        let frame = crate::body_frame(&lock, mask.p.body, &[et, end.unwrap_or(et)]);
+        let clearance = |et: f64| {
+            let azel = crate::target_azel_et(&lock, et, mask.p, frame, target);
+            (mask.clearance(&azel), azel)
+        };
+
+        let (now, azel) = clearance(et);
+        let mut events = Vec::new();
+        if let Some(end) = end {
+            for (at, rising) in crate::search::changes(et, end, step, |et| clearance(et).0 > 0.0) {
+                events.push(VisibilityEvent {
+                    t: t + time::Duration::seconds_f64(at - et),
+                    rising,
+                    az: clearance(at).1.az,
+                });
+            }
+        }
+
+        Visibility {
+            target: target.to_string(),
+            visible: now > 0.0,
+            az: azel.az,
+            el: azel.el,
+            mask_el: mask.elevation_at(azel.az),
+            events,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
+
+/// Masks created through the API, oldest dropped first. They live in this
+/// process only: another server, or another Lambda instance, knows none of
+/// its ids.
+pub struct MaskRegistry {
+    masks: Mutex<std::collections::BTreeMap<u64, Arc<HorizonMask>>>,
+    capacity: usize,
+}
+
+impl MaskRegistry {
+    pub fn new(capacity: usize) -> MaskRegistry {
+        MaskRegistry {
+            masks: Mutex::new(std::collections::BTreeMap::new()),
+            capacity,
+        }
+    }
+
+    pub fn insert(&self, mask: HorizonMask) -> (u64, Arc<HorizonMask>) {
+        let mask = Arc::new(mask);
+        let mut masks = self.masks.lock().unwrap();
+        let id = masks.keys().next_back().map_or(1, |id| id + 1);
+        masks.insert(id, mask.clone());
+        while masks.len() > self.capacity {
+            masks.pop_first();
+        }
+        (id, mask)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<HorizonMask>> {
+        self.masks.lock().unwrap().get(&id).cloned()
+    }
+}
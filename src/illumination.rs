@@ -0,0 +1,193 @@
+//! Illumination angles at a point on the Moon.
+//!
+//! Incidence (Sun from the surface normal), emission (observer from the
+//! normal) and phase (Sun to observer, seen from the point). Body observers
+//! (Earth, a spacecraft with loaded ephemeris) go through `ilumin_c`; an
+//! observer at another site, such as a camera on a mast, is handled with the
+//! same geometry computed directly. All geometric, in MOON_ME. On terrain,
+//! incidence and emission are from the local DSK facet, not the level plane.
+
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::sync::{Arc, Mutex};
+
+const FRAME: &str = "MOON_ME_DE440_ME421";
+
+/// Who is looking at the point.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Observer {
+    /// A SPICE body name or NAIF id, e.g. "EARTH" or "-85".
+    Body(String),
+    /// Another site on the Moon; `alt` is the camera height.
+    Site(Position),
+}
+
+impl std::fmt::Display for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Observer::Body(name) => write!(f, "{}", name),
+            Observer::Site(p) => {
+                let p = p.to_degrees();
+                write!(f, "site ({}, {}, {} km)", p.lat, p.lon, p.alt)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Illumination {
+    pub incidence: f64,
+    pub emission: f64,
+    pub phase: f64,
+    /// Local true solar time at the point.
+    pub solar_time: String,
+    pub observer: String,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for Illumination {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "incidence: {}, emission: {}, phase: {}, solar time: {}, observer: {}, u: {}",
+            self.incidence, self.emission, self.phase, self.solar_time, self.observer, self.units
+        )
+    }
+}
+
+impl Angular for Illumination {
+    fn to_degrees(&self) -> Illumination {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Illumination {
+                incidence: self.incidence.to_degrees(),
+                emission: self.emission.to_degrees(),
+                phase: self.phase.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Illumination {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Illumination {
+                incidence: self.incidence.to_radians(),
+                emission: self.emission.to_radians(),
+                phase: self.phase.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Outward surface normal at `spoint`.
+fn normal(
+    lock: &SpiceLock,
+    et: f64,
+    pos: Position,
+    spoint: Vec3,
+    surface: Surface,
+) -> Result<Vec3, String> {
+    if surface == Surface::Ellipsoid {
+        // geodetic normal of the reference ellipsoid
+        let p = pos.to_radians();
+        return Ok(unit(p.lat, p.lon));
+    }
+    let spoints = [spoint];
+    let mut normals = [[0.0; 3]];
+    crate::spice_try(lock, || unsafe {
+        spice::c::srfnrm_c(
+            cstr!(surface.spice_method()),
+            cstr!("MOON"),
+            et,
+            cstr!(FRAME),
+            1,
+            spoints.as_ptr(),
+            normals.as_mut_ptr(),
+        );
+    })?;
+    Ok(normals[0])
+}
+
+/// (phase, incidence, emission) in radians.
+fn angles(
+    lock: &SpiceLock,
+    et: f64,
+    pos: Position,
+    observer: &Observer,
+    surface: Surface,
+) -> Result<[f64; 3], String> {
+    // the lit point is the ground; only an observer site has a height
+    let pos = Position { alt: 0.0, ..pos };
+    let mut spoint = crate::site_point(lock, et, pos, surface)?;
+    match observer {
+        Observer::Body(name) => {
+            let mut trgepc = 0.0;
+            let mut srfvec = [0.0; 3];
+            let (mut phase, mut incidence, mut emission) = (0.0, 0.0, 0.0);
+            crate::spice_try(lock, || unsafe {
+                spice::c::ilumin_c(
+                    cstr!(surface.spice_method()),
+                    cstr!("MOON"),
+                    et,
+                    cstr!(FRAME),
+                    cstr!("NONE"),
+                    cstr!(name.as_str()),
+                    spoint.as_mut_ptr(),
+                    &mut trgepc,
+                    srfvec.as_mut_ptr(),
+                    &mut phase,
+                    &mut incidence,
+                    &mut emission,
+                );
+            })?;
+            Ok([phase, incidence, emission])
+        }
+        Observer::Site(other) => {
+            let (sun, _lt) = lock.spkpos("SUN", et, FRAME, "NONE", "MOON");
+            let to_sun = sub(sun, spoint);
+            let to_observer = sub(crate::site_point(lock, et, *other, surface)?, spoint);
+            if norm(to_observer) == 0.0 {
+                return Err("observer site is the surface point".to_string());
+            }
+            let normal = normal(lock, et, pos, spoint, surface)?;
+            Ok([
+                angle(to_sun, to_observer),
+                angle(normal, to_sun),
+                angle(normal, to_observer),
+            ])
+        }
+    }
+}
+
+/// Illumination angles at the ground below `pos` seen by `observer` at `t`.
+pub fn illumination(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+    observer: Observer,
+    surface: Surface,
+) -> Result<Illumination, String> {
+    let [phase, incidence, emission] = {
+        let lock = crate::lock_spice(&sl_mutex);
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        crate::spice_try(&lock, || angles(&lock, et, pos, &observer, surface))??
+    };
+    Ok(Illumination {
+        incidence,
+        emission,
+        phase,
+        solar_time: crate::solar_time(sl_mutex, t, pos)?,
+        observer: observer.to_string(),
+        units: UnitSpecifier::Radians,
+    })
+}
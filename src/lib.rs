@@ -1,40 +1,212 @@
 pub mod types;
 pub use types::*;
+pub mod cache;
+#[cfg(feature = "cspice")]
+pub mod clock;
+#[cfg(feature = "pure-rust")]
+pub mod daf;
+#[cfg(feature = "cspice")]
+pub mod dsn;
+#[cfg(feature = "cspice")]
+pub mod earth;
+#[cfg(feature = "cspice")]
+pub mod eclipse;
+#[cfg(feature = "cspice")]
+pub mod fit;
+#[cfg(feature = "cspice")]
+pub mod horizon;
+#[cfg(feature = "cspice")]
+pub mod illumination;
+#[cfg(feature = "cspice")]
+pub mod live;
+#[cfg(feature = "cspice")]
+pub mod maps;
+#[cfg(feature = "cspice")]
+pub mod mars;
+pub mod missions;
+#[cfg(feature = "cspice")]
+pub mod observing;
+#[cfg(feature = "cspice")]
+pub mod pool;
+#[cfg(feature = "cspice")]
+pub mod power;
+#[cfg(feature = "pure-rust")]
+pub mod pure;
+#[cfg(feature = "cspice")]
+mod search;
+#[cfg(feature = "cspice")]
+pub mod shadow;
+#[cfg(feature = "cspice")]
+pub mod subpoint;
+#[cfg(feature = "cspice")]
+pub mod terminator;
+#[cfg(feature = "cspice")]
+mod vector;
+#[cfg(feature = "cspice")]
+pub mod worker;
+pub use cache::{CacheKey, CacheStats, ResponseCache};
+#[cfg(feature = "cspice")]
+pub use clock::{ClockEvent, MissionClock};
+#[cfg(feature = "cspice")]
+pub use dsn::{Comms, CommsTarget, Coverage, DsnVisibility, Pass, Station};
+#[cfg(feature = "cspice")]
+pub use earth::{EarthOrientation, EarthTrack, EarthView, TrackPoint};
+#[cfg(feature = "cspice")]
+pub use eclipse::{EclipseKind, EclipsePhase, Eclipses, SiteEclipse};
+#[cfg(feature = "cspice")]
+pub use fit::{FitInfo, FitRegistry, GeometryFit, SiteFit};
+#[cfg(feature = "cspice")]
+pub use horizon::{HorizonMask, MaskInfo, MaskRegistry, Visibility, VisibilityEvent};
+#[cfg(feature = "cspice")]
+pub use illumination::{Illumination, Observer};
+#[cfg(feature = "cspice")]
+pub use live::{LiveHub, LiveSample};
+#[cfg(feature = "cspice")]
+pub use maps::{IlluminationMap, MapCell, MapLayer, Region};
+#[cfg(feature = "cspice")]
+pub use mars::MarsTime;
+pub use missions::{Mission, MissionEvent, MissionRegistry, Since};
+#[cfg(feature = "cspice")]
+pub use observing::{MoonRiseSet, MoonSky, SiteFromEarth};
+#[cfg(feature = "cspice")]
+pub use pool::{SpiceRequest, SpiceResponse, WorkerPool};
+#[cfg(feature = "cspice")]
+pub use power::{Panel, PanelPower};
+#[cfg(feature = "pure-rust")]
+pub use pure::Ephemeris;
+#[cfg(feature = "cspice")]
+pub use shadow::{ObjectShadow, Shadows};
+#[cfg(feature = "cspice")]
+pub use subpoint::SubPoint;
+#[cfg(feature = "cspice")]
+pub use terminator::{Terminator, TerminatorDistance, TerminatorKind, TerminatorShape};
+#[cfg(feature = "cspice")]
+pub use worker::{SpiceWorker, WorkerError, WorkerStatus};
 
+#[cfg(feature = "cspice")]
 use core::ffi::CStr;
+#[cfg(feature = "cspice")]
 use spice::{cstr, SpiceLock};
+#[cfg(feature = "cspice")]
 use std::sync::{Arc, Mutex};
 
+#[cfg(feature = "cspice")]
 use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
-fn to_cspice_string(t: OffsetDateTime) -> String {
+/// Kernels loaded by the server and by every pool worker, relative to the
+/// working directory.
+pub const KERNELS: &[&str] = &[
+    "data/latest_leapseconds.tls",
+    //"data/earth_fixed.tf",
+    //"data/de440.bsp",
+    "data/de440s.bsp",
+    //"data/moon_080317.tf",
+    //"data/moon_assoc_me.tf",
+    //"data/moon_assoc_pa.tf",
+    "data/moon_pa_de440_200625.bpc",
+    "data/moon_de440_200625.tf",
+    "data/pck00010.tpc",
+];
+
+/// Kernels loaded only when present. The high-precision Earth PCK (ITRF93)
+/// is reissued every few days and not bundled; without it, Earth-fixed
+/// results use IAU_EARTH.
+pub const OPTIONAL_KERNELS: &[&str] = &["data/earth_latest_high_prec.bpc"];
+
+/// Lists lunar DSK files to load for terrain, separated like `PATH`.
+pub const DSK_ENV: &str = "MOONTIME_DSK";
+
+/// Lists further kernels to load, separated like `PATH`: ephemerides and
+/// PCKs for other bodies, such as `mar097.bsp` for Mars or `jup365.bsp`
+/// for Europa.
+pub const KERNELS_ENV: &str = "MOONTIME_KERNELS";
+
+fn env_kernels(var: &str) -> Vec<String> {
+    std::env::var_os(var)
+        .map(|paths| {
+            std::env::split_paths(&paths)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| p.to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// DSK files named in `MOONTIME_DSK`. They are large and site-specific, so
+/// none are loaded by default.
+pub fn dsk_kernels() -> Vec<String> {
+    env_kernels(DSK_ENV)
+}
+
+/// Kernels named in `MOONTIME_KERNELS`, loaded after the defaults.
+pub fn extra_kernels() -> Vec<String> {
+    env_kernels(KERNELS_ENV)
+}
+
+#[cfg(feature = "cspice")]
+pub fn load_kernels(sl: &SpiceLock) {
+    for kernel in KERNELS {
+        sl.furnsh(kernel);
+    }
+    for kernel in OPTIONAL_KERNELS {
+        if std::path::Path::new(kernel).exists() {
+            sl.furnsh(kernel);
+        }
+    }
+    for kernel in extra_kernels().into_iter().chain(dsk_kernels()) {
+        sl.furnsh(&kernel);
+    }
+}
+
+/// Identifies the kernel set on disk, so cached results can't outlive a
+/// kernel update.
+pub fn kernel_set_version() -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let files = [extra_kernels(), dsk_kernels()].concat();
+    let kernels = KERNELS
+        .iter()
+        .chain(OPTIONAL_KERNELS)
+        .copied()
+        .chain(files.iter().map(String::as_str));
+    for kernel in kernels {
+        kernel.hash(&mut hasher);
+        if let Ok(meta) = std::fs::metadata(kernel) {
+            meta.len().hash(&mut hasher);
+            meta.modified().ok().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(feature = "cspice")]
+pub(crate) fn to_cspice_string(t: OffsetDateTime) -> String {
     let format = Rfc3339;
     t.format(&format).unwrap()
 }
 
+#[cfg(feature = "cspice")]
 pub fn get_et(sl_mutex: Arc<Mutex<SpiceLock>>, t: OffsetDateTime) -> f64 {
-    let lock = sl_mutex.lock().unwrap();
+    let lock = lock_spice(&sl_mutex);
     let dt = to_cspice_string(t);
     lock.str2et(dt.as_str())
 }
 
+#[cfg(feature = "cspice")]
 #[allow(dead_code)]
 pub fn solar_time(
     sl_mutex: Arc<Mutex<SpiceLock>>,
     t: OffsetDateTime,
     pos: types::Position,
-) -> Result<String, ()> {
-    let lock = sl_mutex.lock().unwrap();
+) -> Result<String, String> {
+    let lock = lock_spice(&sl_mutex);
     let lon = pos.to_radians().lon;
     let dt = to_cspice_string(t);
     let et: f64 = lock.str2et(dt.as_str());
 
-    println!("et: {}", et);
-    println!("lon: {}", lon);
-    let result: String;
-    unsafe {
+    let result = spice_try(&lock, || unsafe {
         let et_c = et as f64;
-        let body_c = 301 as i32;
+        let body_c = pos.body.id();
         let lon_c = lon as f64;
         let type_of_coord = "PLANETOCENTRIC";
         let type_c = cstr!(type_of_coord);
@@ -62,24 +234,139 @@ pub fn solar_time(
             time_c.as_mut_ptr(),
             ampm_c.as_mut_ptr(),
         );
-        let time = CStr::from_ptr(time_c.as_ptr()).to_str().unwrap();
         let ampm = CStr::from_ptr(ampm_c.as_ptr()).to_str().unwrap();
-        println!("solar time: {} {}", time, ampm);
         //cleanup
-        result = format!("{}", ampm);
-    }
+        ampm.to_string()
+    })?;
     Ok(result)
 }
 
+#[cfg(feature = "cspice")]
 #[allow(dead_code)]
 pub fn solar_azel(
     sl_mutex: Arc<Mutex<SpiceLock>>,
     time: OffsetDateTime,
     pos: types::Position,
 ) -> types::RAzEl {
-    let lock = sl_mutex.lock().unwrap();
+    target_azel(sl_mutex, time, pos, "SUN")
+}
+
+/// Range, azimuth and elevation of any SPICE body from a site on the Moon
+/// or Earth. Sites on other bodies go through `target_azel_surface`.
+#[cfg(feature = "cspice")]
+pub fn target_azel(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    time: OffsetDateTime,
+    pos: types::Position,
+    target: &str,
+) -> types::RAzEl {
+    let lock = lock_spice(&sl_mutex);
     let time = to_cspice_string(time);
+    let et = lock.str2et(time.as_str());
+    let frame = body_frame(&lock, pos.body, &[et]);
+    target_azel_et(&lock, et, pos, frame, target)
+}
+
+/// Runs `f` with CSPICE set to return on errors instead of aborting, for
+/// calls whose inputs come from the client (body names, ephemeris coverage).
+/// The error is reset before returning so the next call starts clean.
+#[cfg(feature = "cspice")]
+pub(crate) fn spice_try<T>(_lock: &SpiceLock, f: impl FnOnce() -> T) -> Result<T, String> {
+    const MSGLEN: usize = 1841;
+    let mut previous = ErrorAction([0i8; 32]);
+    unsafe {
+        spice::c::erract_c(cstr!("GET"), previous.0.len() as i32, previous.0.as_mut_ptr());
+        spice::c::erract_c(cstr!("SET"), 0, cstr!("RETURN"));
+    }
+    let value = f();
+    unsafe {
+        if spice::c::failed_c() != 0 {
+            let mut msg = [0i8; MSGLEN];
+            spice::c::getmsg_c(cstr!("LONG"), MSGLEN as i32, msg.as_mut_ptr());
+            spice::c::reset_c();
+            Err(CStr::from_ptr(msg.as_ptr()).to_string_lossy().into_owned())
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// The CSPICE error action `spice_try` replaced, put back when dropped so
+/// that a panic inside it doesn't leave CSPICE returning on errors.
+#[cfg(feature = "cspice")]
+struct ErrorAction([i8; 32]);
+
+#[cfg(feature = "cspice")]
+impl Drop for ErrorAction {
+    fn drop(&mut self) {
+        unsafe {
+            spice::c::erract_c(cstr!("SET"), 0, self.0.as_mut_ptr());
+        }
+    }
+}
 
+/// Takes the SPICE lock, recovering it first if a job panicked while
+/// holding it, so one panic doesn't lock every later caller out.
+#[cfg(feature = "cspice")]
+pub(crate) fn lock_spice(sl_mutex: &Mutex<SpiceLock>) -> std::sync::MutexGuard<'_, SpiceLock> {
+    sl_mutex.lock().unwrap_or_else(|poisoned| {
+        unsafe {
+            spice::c::reset_c();
+        }
+        sl_mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Makes the lock usable again after a job panicked while holding it:
+/// clears any CSPICE error the job left signalled, then the poison.
+#[cfg(feature = "cspice")]
+pub(crate) fn recover_lock(sl_mutex: &Mutex<SpiceLock>) {
+    let _lock = sl_mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    unsafe {
+        spice::c::reset_c();
+    }
+    sl_mutex.clear_poison();
+}
+
+/// Local true solar time at east longitude `lon` (radians) on `body`, as
+/// seconds after local midnight, from `et2lst_c`.
+#[cfg(feature = "cspice")]
+pub(crate) fn local_solar_seconds(
+    lock: &SpiceLock,
+    et: f64,
+    body: types::Body,
+    lon: f64,
+) -> Result<f64, String> {
+    spice_try(lock, || unsafe {
+        const LEN: i32 = 64;
+        let mut hr = 0i32;
+        let mut mn = 0i32;
+        let mut sc = 0i32;
+        let mut time = [0i8; LEN as usize];
+        let mut ampm = [0i8; LEN as usize];
+        spice::c::et2lst_c(
+            et,
+            body.id(),
+            lon,
+            cstr!("PLANETOCENTRIC"),
+            LEN,
+            LEN,
+            &mut hr,
+            &mut mn,
+            &mut sc,
+            time.as_mut_ptr(),
+            ampm.as_mut_ptr(),
+        );
+        (hr * 3600 + mn * 60 + sc) as f64
+    })
+}
+
+/// Triaxial radii of `body` in km, from the loaded PCK.
+#[cfg(feature = "cspice")]
+pub(crate) fn body_radii(_lock: &SpiceLock, body: &str) -> [f64; 3] {
     let mut radius = [0.0, 0.0, 0.0];
 
     unsafe {
@@ -87,76 +374,204 @@ pub fn solar_azel(
         let mut out_dim: i32 = 0;
         let out_dim_p = &mut out_dim as *mut i32;
         spice::c::bodvrd_c(
-            cstr!("MOON"),
+            cstr!(body),
             cstr!("RADII"),
             3,
             out_dim_p,
             radius.as_mut_ptr(),
         );
     }
+    radius
+}
+
+/// Site position on the reference ellipsoid of `pos.body`, in its
+/// body-fixed frame (see `body_frame`).
+#[cfg(feature = "cspice")]
+pub(crate) fn ellipsoid_point(lock: &SpiceLock, pos: types::Position) -> [f64; 3] {
+    let radius = body_radii(lock, pos.body.name());
 
     let re = radius[0];
-    println!("re: {}", re);
-    let et = lock.str2et(time.as_str());
-    println!("et: {}", et);
     let flat = radius[0] - radius[2];
     let flat = flat / radius[0];
 
     let pos = pos.to_radians();
-    println!(
-        "pos: {}",
-        serde_json::to_string(&pos).unwrap_or("err".to_string())
-    );
-    let mut rect_coord = lock.georec(pos.lon, pos.lat, pos.alt, re, flat);
-    println!("rect_coord: {:?}", rect_coord);
+    lock.georec(pos.lon, pos.lat, pos.alt, re, flat)
+}
+
+/// Fails unless at least one DSK is loaded, so terrain requests don't
+/// silently fall back to the ellipsoid.
+#[cfg(feature = "cspice")]
+pub(crate) fn require_terrain(_lock: &SpiceLock) -> Result<(), String> {
+    let mut count = 0;
+    unsafe {
+        spice::c::ktotal_c(cstr!("DSK"), &mut count);
+    }
+    if count == 0 {
+        return Err(format!("terrain requires DSK kernels; none are loaded (see {})", DSK_ENV));
+    }
+    Ok(())
+}
+
+/// Site position in MOON_ME on `surface`, with `alt` as height above it.
+/// On terrain, lat/lon are planetocentric and `alt` is along the radius.
+#[cfg(feature = "cspice")]
+pub(crate) fn site_point(
+    lock: &SpiceLock,
+    et: f64,
+    pos: types::Position,
+    surface: types::Surface,
+) -> Result<[f64; 3], String> {
+    if surface == types::Surface::Ellipsoid {
+        return Ok(ellipsoid_point(lock, pos));
+    }
+    if pos.body != types::Body::Moon {
+        return Err("terrain is only available on the Moon".to_string());
+    }
+    require_terrain(lock)?;
+    let pos = pos.to_radians();
+    let lonlat = [[pos.lon, pos.lat]];
+    let mut srfpts = [[0.0; 3]];
+    spice_try(lock, || unsafe {
+        spice::c::latsrf_c(
+            cstr!(surface.spice_method()),
+            cstr!("MOON"),
+            et,
+            cstr!("MOON_ME_DE440_ME421"),
+            1,
+            lonlat.as_ptr(),
+            srfpts.as_mut_ptr(),
+        );
+    })?;
+    let up = vector::unit(pos.lat, pos.lon);
+    Ok(vector::add(srfpts[0], vector::scale(up, pos.alt)))
+}
+
+/// Body-fixed frame of `body` over `ets`: MOON_ME for the Moon, for Earth
+/// ITRF93 where the high-precision PCK covers every one of `ets` (else
+/// IAU_EARTH), and the IAU model from the text PCK for the others. Probing
+/// the PCK costs a few SPICE calls, so requests look the frame up once.
+#[cfg(feature = "cspice")]
+pub(crate) fn body_frame(lock: &SpiceLock, body: types::Body, ets: &[f64]) -> &'static str {
+    match body {
+        types::Body::Moon => "MOON_ME_DE440_ME421",
+        types::Body::Earth => earth::EarthOrientation::at(lock, ets).frame(),
+        types::Body::Mars => "IAU_MARS",
+        types::Body::Mercury => "IAU_MERCURY",
+        types::Body::Europa => "IAU_EUROPA",
+    }
+}
+
+/// Range, azimuth and elevation of `target` from a point fixed on the Moon,
+/// with the horizon taken as the ellipsoid's level plane there.
+#[cfg(feature = "cspice")]
+pub(crate) fn azel_from(
+    lock: &SpiceLock,
+    et: f64,
+    rect_coord: [f64; 3],
+    target: &str,
+) -> types::RAzEl {
+    let frame = body_frame(lock, types::Body::Moon, &[et]);
+    azel_on(lock, et, rect_coord, types::Body::Moon, frame, target)
+}
+
+/// As `azel_from`, for a point fixed on `body` in its body-fixed `frame`.
+#[cfg(feature = "cspice")]
+pub(crate) fn azel_on(
+    _lock: &SpiceLock,
+    et: f64,
+    mut rect_coord: [f64; 3],
+    body: types::Body,
+    frame: &str,
+    target: &str,
+) -> types::RAzEl {
     let mut azlsta = [0.0; 6];
     let mut lt = 0.0;
 
     unsafe {
         spice::c::azlcpo_c(
             cstr!("ELLIPSOID"),
-            cstr!("SUN"),
+            cstr!(target),
             et,
             //TODO: provide aberration correction
             cstr!("NONE"),
             spice::c::SPICETRUE as i32,
             spice::c::SPICETRUE as i32,
             rect_coord.as_mut_ptr(),
-            cstr!("MOON"),
-            cstr!("MOON_ME_DE440_ME421"),
+            cstr!(body.name()),
+            cstr!(frame),
             azlsta.as_mut_ptr(),
             &mut lt,
         );
     }
 
-    println!("azlsta: {:?} lt: {:?}", azlsta, lt);
     let range = azlsta[0];
     let azimuth = azlsta[1];
     let elevation = azlsta[2];
-    println!(
-        "range: {} azimuth: {} elevation: {} in degrees",
-        range,
-        azimuth.to_degrees(),
-        elevation.to_degrees()
-    );
-    println!(
-        "range: {} azimuth: {} elevation: {} in radians",
-        range, azimuth, elevation
-    );
 
     types::RAzEl {
         r: range,
         az: azimuth,
         el: elevation,
         units: types::UnitSpecifier::Radians,
+        horizon_el: None,
     }
 }
 
+/// As `target_azel`, for callers that already hold the lock, have an ET and
+/// have looked up the site body's `frame` with `body_frame`.
+#[cfg(feature = "cspice")]
+pub fn target_azel_et(
+    lock: &SpiceLock,
+    et: f64,
+    pos: types::Position,
+    frame: &str,
+    target: &str,
+) -> types::RAzEl {
+    let point = ellipsoid_point(lock, pos);
+    azel_on(lock, et, point, pos.body, frame, target)
+}
+
+/// As `target_azel_et`, with the site placed on `surface`. Over terrain the
+/// elevation is still from the level plane at the site, and `horizon_el`
+/// gives the DSK skyline in the target's direction.
+#[cfg(feature = "cspice")]
+pub fn target_azel_on(
+    lock: &SpiceLock,
+    et: f64,
+    pos: types::Position,
+    target: &str,
+    surface: types::Surface,
+) -> Result<types::RAzEl, String> {
+    let point = site_point(lock, et, pos, surface)?;
+    let frame = body_frame(lock, pos.body, &[et]);
+    let mut azel = azel_on(lock, et, point, pos.body, frame, target);
+    if surface == types::Surface::Terrain {
+        azel.horizon_el = Some(horizon::skyline_at(lock, pos, azel.az)?);
+    }
+    Ok(azel)
+}
+
+/// Range, azimuth and elevation of any SPICE body from a site on `surface`.
+/// Missing kernels for the site's body or the target are an error.
+#[cfg(feature = "cspice")]
+pub fn target_azel_surface(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    time: OffsetDateTime,
+    pos: types::Position,
+    target: &str,
+    surface: types::Surface,
+) -> Result<types::RAzEl, String> {
+    let lock = lock_spice(&sl_mutex);
+    let et = lock.str2et(to_cspice_string(time).as_str());
+    spice_try(&lock, || target_azel_on(&lock, et, pos, target, surface))?
+}
+
+#[cfg(feature = "cspice")]
 pub fn earth_position_from_sun(
     sl_mutex: Arc<Mutex<SpiceLock>>,
     time: OffsetDateTime,
 ) -> types::PositionFull {
-    let lock = sl_mutex.lock().unwrap();
+    let lock = lock_spice(&sl_mutex);
     let time_str = to_cspice_string(time);
     let et = lock.str2et(time_str.as_str());
 
@@ -187,11 +602,12 @@ pub fn earth_position_from_sun(
     }
 }
 
+#[cfg(feature = "cspice")]
 pub fn earth_position_ecliptic(
     sl_mutex: Arc<Mutex<SpiceLock>>,
     time: OffsetDateTime,
 ) -> types::PositionFull {
-    let lock = sl_mutex.lock().unwrap();
+    let lock = lock_spice(&sl_mutex);
     let time_str = to_cspice_string(time);
     let et = lock.str2et(time_str.as_str());
 
@@ -222,7 +638,7 @@ pub fn earth_position_ecliptic(
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "cspice"))]
 mod tests {
     use super::*;
     use std::sync::{Arc, Mutex, OnceLock};
@@ -234,11 +650,7 @@ mod tests {
     fn setup_spice() -> Arc<Mutex<SpiceLock>> {
         SPICE_LOCK.get_or_init(|| {
             let sl = SpiceLock::try_acquire().unwrap();
-            sl.furnsh("data/latest_leapseconds.tls");
-            sl.furnsh("data/de440s.bsp");
-            sl.furnsh("data/moon_pa_de440_200625.bpc");
-            sl.furnsh("data/moon_de440_200625.tf");
-            sl.furnsh("data/pck00010.tpc");
+            load_kernels(&sl);
             Arc::new(Mutex::new(sl))
         }).clone()
     }
@@ -249,6 +661,12 @@ mod tests {
         OffsetDateTime::new_utc(date, time)
     }
 
+    /// After the end of de440s, so SPICE has no ephemeris for it.
+    fn uncovered_datetime() -> OffsetDateTime {
+        let date = Date::from_calendar_date(2200, Month::January, 1).unwrap();
+        OffsetDateTime::new_utc(date, Time::MIDNIGHT)
+    }
+
     #[test]
     fn test_get_et_exact() {
         let sl = setup_spice();
@@ -257,6 +675,84 @@ mod tests {
         assert_eq!(et, 770515269.1848872);
     }
 
+    #[tokio::test]
+    async fn test_worker_get_et_exact() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let worker = SpiceWorker::new(sl, 4, worker::DEFAULT_TIMEOUT);
+        let et = worker.run(move |sl| get_et(sl, t)).await.unwrap();
+        assert_eq!(et, 770515269.1848872);
+        assert_eq!(worker.status().queue_depth, 0);
+        assert_eq!(worker.status().panics, 0);
+
+        // a panicking job is counted, and the next one still gets the lock
+        let res = worker.run(|_| -> f64 { panic!("job failed") }).await;
+        assert_eq!(res, Err(WorkerError::Failed));
+        assert_eq!(worker.status().panics, 1);
+        let et = worker.run(move |sl| get_et(sl, t)).await.unwrap();
+        assert_eq!(et, 770515269.1848872);
+    }
+
+    #[test]
+    fn test_spice_request_roundtrip() {
+        let sl = setup_spice();
+        let req = SpiceRequest::SolarAzel {
+            t: test_datetime(),
+            p: Position::cadre(),
+        };
+        let req = serde_json::to_string(&req).unwrap();
+        let req: SpiceRequest = serde_json::from_str(&req).unwrap();
+        let res = serde_json::to_string(&req.execute(sl.clone())).unwrap();
+        let res: SpiceResponse = serde_json::from_str(&res).unwrap();
+        match res {
+            SpiceResponse::RAzEl(azel) => {
+                assert_eq!(azel.az, 1.6349707743817739);
+                assert_eq!(azel.el, 0.6110381109126339);
+            }
+            _ => panic!("unexpected response {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_hits_and_coalesces() {
+        let cache = ResponseCache::new(2, std::time::Duration::from_secs(60), 0);
+        let key = || Some(CacheKey::new("cadre/sun", test_datetime()).format(FormatSpecifier::Txt));
+        let compute = || async { Ok::<_, ()>("computed".to_string()) };
+
+        let (a, b) = tokio::join!(
+            cache.get_or_compute(key(), compute),
+            cache.get_or_compute(key(), compute)
+        );
+        assert_eq!(a, Ok("computed".to_string()));
+        assert_eq!(b, Ok("computed".to_string()));
+        let res = cache.get_or_compute(key(), || async { Err(()) }).await;
+        assert_eq!(res, Ok("computed".to_string()));
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.coalesced + stats.hits, 2);
+
+        let res = cache.get_or_compute(None, || async { Ok::<_, ()>("fresh".to_string()) }).await;
+        assert_eq!(res, Ok("fresh".to_string()));
+        assert_eq!(cache.stats().entries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_forgets_failures() {
+        let cache = ResponseCache::new(2, std::time::Duration::from_secs(60), 0);
+        let key = || Some(CacheKey::new("cadre/sun", test_datetime()).format(FormatSpecifier::Txt));
+
+        let res = cache.get_or_compute(key(), || async { Err::<String, _>(()) }).await;
+        assert_eq!(res, Err(()));
+        assert_eq!(cache.stats().entries, 0);
+
+        let retry = || async { Ok::<_, ()>("retried".to_string()) };
+        let res = cache.get_or_compute(key(), retry).await;
+        assert_eq!(res, Ok("retried".to_string()));
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.coalesced), (2, 0));
+    }
+
     #[test]
     fn test_cadre_solar_azel_exact() {
         let sl = setup_spice();
@@ -270,6 +766,570 @@ mod tests {
         assert_eq!(azel.units, UnitSpecifier::Radians);
     }
 
+    #[test]
+    fn test_site_fit_matches_solar_azel() {
+        let sl = setup_spice();
+        let start = test_datetime();
+        let end = start + time::Duration::DAY * 3;
+        let pos = Position::cadre();
+        let fit = SiteFit::build(sl.clone(), pos, start, end, 86400.0, 12).unwrap();
+        assert!(fit.sun.max_angle_error < 1e-9);
+        assert!(fit.earth.max_angle_error < 1e-9);
+
+        let t = start + time::Duration::seconds_f64(123456.7);
+        let fitted = fit.evaluate(t).unwrap().sun;
+        let azel = solar_azel(sl.clone(), t, pos);
+        assert!((fitted.az - azel.az).abs() < 1e-9);
+        assert!((fitted.el - azel.el).abs() < 1e-9);
+        assert!(fit.evaluate(end + time::Duration::SECOND).is_none());
+        for segment in [0.0, f64::NAN, f64::INFINITY] {
+            assert!(SiteFit::build(sl.clone(), pos, start, end, segment, 12).is_err());
+        }
+    }
+
+    #[test]
+    fn test_live_sample_matches_endpoints() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let sample = live::sample(sl.clone(), t, pos).unwrap();
+        assert_eq!(sample.et, 770515269.1848872);
+        assert_eq!(sample.sun.az, solar_azel(sl.clone(), t, pos).az);
+        assert_eq!(sample.earth.el, target_azel(sl.clone(), t, pos, "EARTH").el);
+        assert_eq!(sample.solar_time, solar_time(sl, t, pos).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_live_streams_are_capped() {
+        let worker = SpiceWorker::new(setup_spice(), 4, worker::DEFAULT_TIMEOUT);
+        let hub = Arc::new(LiveHub::new(worker));
+        let site = |i: usize| Position::new(i as f64, 0.0, 0.0, UnitSpecifier::Degrees);
+        let receivers: Vec<_> = (0..live::MAX_STREAMS)
+            .map(|i| hub.subscribe(site(i), live::DEFAULT_CADENCE).unwrap())
+            .collect();
+        assert_eq!(hub.streams(), live::MAX_STREAMS);
+        assert!(hub.subscribe(site(live::MAX_STREAMS), live::DEFAULT_CADENCE).is_none());
+        // joining a running stream is still allowed
+        assert!(hub.subscribe(site(0), live::DEFAULT_CADENCE).is_some());
+        drop(receivers);
+    }
+
+    #[test]
+    fn test_sub_points_are_overhead() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        for (point, target) in [
+            (subpoint::subsolar_point(sl.clone(), t, MoonFrame::Me).unwrap(), "SUN"),
+            (subpoint::sub_earth_point(sl.clone(), t, MoonFrame::Me).unwrap(), "EARTH"),
+        ] {
+            let pos = Position {
+                lat: point.lat,
+                lon: point.lon,
+                alt: 0.0,
+                units: UnitSpecifier::Radians,
+                body: Body::Moon,
+            };
+            let azel = target_azel(sl.clone(), t, pos, target);
+            assert!(azel.el > std::f64::consts::FRAC_PI_2 - 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_terminator_is_on_the_horizon() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let kind = TerminatorKind::Umbral;
+        let term = terminator::terminator(sl.clone(), t, MoonFrame::Me, kind, 36).unwrap();
+        let far = uncovered_datetime();
+        assert!(terminator::terminator(sl.clone(), far, MoonFrame::Me, kind, 36).is_err());
+        assert_eq!(term.points.len(), 36);
+        for [lon, lat] in &term.points {
+            let pos = Position {
+                lat: *lat,
+                lon: *lon,
+                alt: 0.0,
+                units: UnitSpecifier::Radians,
+                body: Body::Moon,
+            };
+            assert!(solar_azel(sl.clone(), t, pos).el.abs() < 0.01);
+        }
+
+        let polygon = term.to_geojson(TerminatorShape::Polygon);
+        let ring = polygon["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.first(), ring.last());
+        assert_eq!(ring.len(), 36 + 5);
+
+        let line = term.to_geojson(TerminatorShape::Line);
+        let line = line["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(line.len(), 36 + 2);
+        assert_eq!(line[0][0], -180.0);
+        assert_eq!(line[37][0], 180.0);
+    }
+
+    #[test]
+    fn test_terminator_distance_predicts_sunset() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let dist = terminator::terminator_distance(sl.clone(), t, pos).unwrap();
+        assert!(dist.ground_speed > 13.0 && dist.ground_speed < 17.0);
+        assert_eq!(dist.sun_up, solar_azel(sl.clone(), t, pos).el > 0.0);
+
+        let sunset = t + time::Duration::seconds_f64(dist.sunset_in.unwrap());
+        assert!(solar_azel(sl.clone(), sunset, pos).el.abs() < 0.1);
+        let sunrise = t + time::Duration::seconds_f64(dist.sunrise_in.unwrap());
+        assert!(solar_azel(sl, sunrise, pos).el.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_illumination_matches_elevations() {
+        use std::f64::consts::FRAC_PI_2;
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let earth = Observer::Body("EARTH".to_string());
+        let flat = Surface::Ellipsoid;
+        let ilum = illumination::illumination(sl.clone(), t, pos, earth, flat).unwrap();
+        let sun = solar_azel(sl.clone(), t, pos);
+        let earth = target_azel(sl.clone(), t, pos, "EARTH");
+        assert!((ilum.incidence - (FRAC_PI_2 - sun.el)).abs() < 1e-3);
+        assert!((ilum.emission - (FRAC_PI_2 - earth.el)).abs() < 1e-3);
+
+        // a camera straight above the site looks down the normal
+        let mast = Position { alt: pos.alt + 0.002, ..pos };
+        let ilum = illumination::illumination(sl.clone(), t, pos, Observer::Site(mast), flat);
+        let ilum = ilum.unwrap();
+        assert!(ilum.emission < 1e-6);
+        assert!((ilum.phase - ilum.incidence).abs() < 1e-6);
+
+        let nobody = Observer::Body("NOT A BODY".to_string());
+        assert!(illumination::illumination(sl, t, pos, nobody, flat).is_err());
+    }
+
+    #[test]
+    fn test_terrain_sun_azel() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let flat = target_azel_surface(sl.clone(), t, pos, "SUN", Surface::Ellipsoid).unwrap();
+        let azel = solar_azel(sl.clone(), t, pos);
+        assert_eq!((flat.az, flat.el, flat.r), (azel.az, azel.el, azel.r));
+
+        let terrain = target_azel_surface(sl.clone(), t, pos, "SUN", Surface::Terrain);
+        if dsk_kernels().is_empty() {
+            assert!(terrain.is_err());
+        } else {
+            // a few km of relief barely moves the Sun, but may hide it
+            let terrain = terrain.unwrap();
+            assert!((terrain.el - azel.el).abs() < 1e-3);
+            assert!((terrain.r - azel.r).abs() < 20.0);
+            let skyline = horizon::from_dsk(sl, pos, 1.0).unwrap();
+            let horizon_el = terrain.horizon_el.unwrap();
+            assert!((horizon_el - skyline.elevation_at(terrain.az)).abs() < 0.02);
+            assert_eq!(terrain.visible(), terrain.el > horizon_el);
+        }
+    }
+
+    #[test]
+    fn test_panel_power_follows_the_sun() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let sun = solar_azel(sl.clone(), t, pos).to_degrees();
+        let facing = Panel {
+            tilt: 90.0 - sun.el,
+            azimuth: sun.az,
+            area: 2.0,
+            units: UnitSpecifier::Degrees,
+        };
+        let res = power::panel_power(
+            sl.clone(),
+            t,
+            pos,
+            facing,
+            None,
+            power::DEFAULT_STEP_S,
+            None,
+        )
+        .unwrap();
+        assert!((res.cos_incidence - 1.0).abs() < 1e-9);
+        assert!(res.irradiance > 1300.0 && res.irradiance < 1420.0);
+        assert_eq!(res.power, 2.0 * res.irradiance);
+
+        // an hour at nearly constant power
+        let flat = Panel { tilt: 0.0, ..facing };
+        let hour = t + time::Duration::hours(1);
+        let res = power::panel_power(sl.clone(), t, pos, flat, Some(hour), 60.0, None).unwrap();
+        let energy = res.energy.unwrap();
+        assert!((energy - res.power).abs() / res.power < 0.01);
+
+        let late = uncovered_datetime();
+        assert!(power::panel_power(sl, late, pos, flat, None, 60.0, None).is_err());
+    }
+
+    #[test]
+    fn test_shadow_shrinks_as_the_sun_rises() {
+        use std::f64::consts::PI;
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let res = shadow::shadows(sl.clone(), t, pos, &[2.0], Some(0.5), None).unwrap();
+        let sun = solar_azel(sl.clone(), t, pos);
+        let length = res.shadows[0].length.unwrap();
+        assert!((length * sun.el.tan() - 2.0).abs() < 1e-9);
+        assert!((res.direction - (sun.az + PI).rem_euclid(2.0 * PI)).abs() < 1e-9);
+
+        let at = res.shadows[0].shorter_at.unwrap();
+        let el = solar_azel(sl.clone(), at, pos).el;
+        assert!((2.0 / el.tan() - 0.5).abs() < 0.01);
+
+        let late = uncovered_datetime();
+        assert!(shadow::shadows(sl, late, pos, &[2.0], None, None).is_err());
+    }
+
+    #[test]
+    fn test_earth_view_turns_with_earth() {
+        use std::f64::consts::PI;
+        let sl = setup_spice();
+        let t = test_datetime();
+        let pos = Position::cadre();
+        let view = earth::earth_view(sl.clone(), t, pos).unwrap();
+        assert!((view.illuminated_fraction - 0.5 * (1.0 + view.phase.cos())).abs() < 1e-12);
+        // about 1.9 degrees at lunar distance
+        assert!((view.angular_diameter.to_degrees() - 1.9).abs() < 0.15);
+        assert!((0.0..2.0 * PI).contains(&view.north_pa));
+        // Earth turns about 15 degrees an hour under the site
+        let later = earth::earth_view(sl.clone(), t + time::Duration::hours(1), pos).unwrap();
+        let turned = (later.sub_lon - view.sub_lon).rem_euclid(2.0 * PI).to_degrees();
+        assert!((turned - 345.0).abs() < 1.0);
+        assert!(earth::earth_view(sl, uncovered_datetime(), pos).is_err());
+    }
+
+    #[test]
+    fn test_earth_track_stays_in_its_box() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let until = t + time::Duration::days(28);
+        let track = earth::earth_track(sl.clone(), t, until, 3600.0, Position::cadre()).unwrap();
+        assert_eq!(track.points.len(), 28 * 24 + 1);
+        // libration keeps Earth within about 10 degrees of its mean place
+        let track = track.to_degrees();
+        assert!((track.az_max - track.az_min).rem_euclid(360.0) < 20.0);
+        assert!(track.el_max - track.el_min < 20.0);
+        assert!(track.points.iter().all(|p| p.el >= track.el_min && p.el <= track.el_max));
+        assert!(track.to_svg().starts_with("<svg"));
+        assert_eq!(track.to_csv().lines().count(), track.points.len() + 1);
+
+        let late = uncovered_datetime();
+        assert!(earth::earth_track(sl, late, late, 3600.0, Position::cadre()).is_err());
+    }
+
+    #[test]
+    fn test_sun_from_other_bodies() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        // the generic path agrees with the lunar one on the Moon
+        let site = Position::cadre();
+        let generic = target_azel_surface(sl.clone(), t, site, "SUN", Surface::Ellipsoid);
+        let lunar = solar_azel(sl.clone(), t, site);
+        assert!((generic.unwrap().el - lunar.el).abs() < 1e-12);
+
+        // Mercury is in the default ephemeris
+        let site = Position::new(0.0, 0.0, 0.0, UnitSpecifier::Degrees).on(Body::Mercury);
+        let azel = target_azel_surface(sl.clone(), t, site, "SUN", Surface::Ellipsoid).unwrap();
+        assert!((0.30..0.47).contains(&(azel.r / 149_597_870.7)));
+        assert!(solar_time(sl.clone(), t, site).is_ok());
+        assert!(target_azel_surface(sl.clone(), t, site, "SUN", Surface::Terrain).is_err());
+
+        // Mars needs its own SPK; without one, an error rather than an abort
+        if extra_kernels().is_empty() {
+            let site = site.on(Body::Mars);
+            assert!(target_azel_surface(sl.clone(), t, site, "SUN", Surface::Ellipsoid).is_err());
+            assert!(solar_time(sl, t, site).is_err());
+        }
+    }
+
+    #[test]
+    fn test_mars_time_and_mission_sols() {
+        let sl = setup_spice();
+        let missions = MissionRegistry::new(missions::builtin_missions());
+        let curiosity = missions.get("Curiosity").unwrap().clone();
+
+        // Curiosity landed in the mid-afternoon of sol 0
+        let (landed, site) = (curiosity.landing, curiosity.site);
+        let landing = mars::mars_time(sl.clone(), landed, site, Some(curiosity.clone())).unwrap();
+        assert_eq!(landing.sol, Some(0));
+        assert!(landing.lmst.starts_with("15:0"));
+
+        // mid-morning of sol 1001 at Gale
+        let date = Date::from_calendar_date(2015, Month::May, 31).unwrap();
+        let t = OffsetDateTime::new_utc(date, Time::from_hms(12, 0, 0).unwrap());
+        let res = mars::mars_time(sl.clone(), t, site, Some(curiosity.clone())).unwrap();
+        assert_eq!(res.sol, Some(1001));
+        assert_eq!(res.lmst, "09:27:32");
+        // the equation of time stays within about 51 minutes
+        assert!(res.eot.abs() < 13.0_f64.to_radians());
+
+        // at the prime meridian local mean time is MTC
+        let res = mars::mars_time(sl.clone(), t, Position { lon: 0.0, ..site }, None).unwrap();
+        assert_eq!(res.sol, None);
+        assert_eq!(res.lmst, res.mtc);
+
+        // SPICE agrees on true solar time when it has a Mars ephemeris
+        match res.ltst_spice_diff {
+            Some(diff) => assert!(diff.abs() < 60.0, "{}", diff),
+            None => assert!(extra_kernels().is_empty()),
+        }
+
+        // past the ephemeris only the SPICE cross-check is missing
+        let late = mars::mars_time(sl, uncovered_datetime(), site, None).unwrap();
+        assert!(late.ltst_spice.is_none());
+    }
+
+    #[test]
+    fn test_mission_clock() {
+        assert_eq!(clock::countdown(8040.0), "02:14:00");
+        assert_eq!(clock::countdown(-(3.0 * 86400.0 + 1.0)), "3d 00:00:01");
+
+        let sl = setup_spice();
+        let t = test_datetime();
+        let p = Position::cadre();
+        let clock = clock::mission_clock(sl.clone(), t, p, Since::Landing, None, None);
+        assert_eq!(clock.day, None);
+        assert!(clock.events.windows(2).all(|w| w[0].t <= w[1].t));
+        assert!(clock.events.iter().all(|e| e.until <= 32.0 * 86400.0));
+        let sunset = clock.events.iter().find(|e| e.name == "sunset").unwrap();
+        assert!(solar_azel(sl.clone(), sunset.t, p).el.abs() < 0.001);
+        assert!(clock.events.iter().any(|e| e.name == "sunrise"));
+        let next = &clock.events[0];
+        assert_eq!(clock.summary, format!("{} until {}", next.countdown, next.name));
+
+        // the landing day is day 1, and a lunar day later it is day 2
+        let landed = t - time::Duration::hours(1);
+        let clock = clock::mission_clock(sl.clone(), t, p, Since::Landing, Some(landed), None);
+        assert_eq!(clock.day, Some(1));
+        assert!(clock.summary.starts_with("Lunar day 1, "));
+        let later = t + time::Duration::days(30);
+        let clock = clock::mission_clock(sl.clone(), later, p, Since::Landing, Some(landed), None);
+        assert_eq!(clock.day, Some(2));
+        assert!(clock.met.unwrap().starts_with("T+30d 01:00:00"));
+
+        // sols agree with Mars time, and there are no sols since launch
+        let missions = MissionRegistry::new(missions::builtin_missions());
+        let curiosity = missions.get("curiosity").unwrap().clone();
+        let date = Date::from_calendar_date(2015, Month::May, 31).unwrap();
+        let t = OffsetDateTime::new_utc(date, Time::from_hms(12, 0, 0).unwrap());
+        let site = curiosity.site;
+        let landing = curiosity.epoch(Since::Landing);
+        let clock = clock::mission_clock(
+            sl.clone(),
+            t,
+            site,
+            Since::Landing,
+            landing,
+            Some(curiosity.clone()),
+        );
+        assert_eq!(clock.day, Some(1001));
+        assert!(clock.summary.starts_with("Sol 1001"));
+        let launch = curiosity.epoch(Since::Launch);
+        let clock = clock::mission_clock(sl, t, site, Since::Launch, launch, Some(curiosity));
+        assert_eq!(clock.day, None);
+        assert!(clock.summary.ends_with("since launch, nothing ahead"));
+    }
+
+    #[test]
+    fn test_moon_from_an_earth_observer() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let o = Position::new(35.4259, -116.8895, 1.0, UnitSpecifier::Degrees).on(Body::Earth);
+        let sky = observing::moon_sky(sl.clone(), t, o).unwrap();
+        // the generic az/el path agrees for an observer on Earth
+        let azel = target_azel(sl.clone(), t, o, "MOON");
+        assert!((azel.el - sky.el).abs() < 1e-6);
+        assert!((azel.r - sky.r).abs() < 1e-3);
+
+        let until = t + time::Duration::days(2);
+        let rise_set = observing::moon_rise_set(sl.clone(), t, until, 600.0, o).unwrap();
+        assert!((2..=5).contains(&rise_set.events.len()));
+        let mut up = rise_set.up;
+        for event in &rise_set.events {
+            assert_ne!(event.rising, up);
+            up = event.rising;
+            // the upper limb is on the refracted horizon
+            let sky = observing::moon_sky(sl.clone(), event.t, o).unwrap();
+            let limb = (sky.el + sky.semidiameter).to_degrees() + 34.0 / 60.0;
+            assert!(limb.abs() < 0.01);
+        }
+
+        let near = observing::site_from_earth(sl.clone(), t, o, Position::cadre()).unwrap();
+        assert!(near.on_disk);
+        assert_eq!(near.visible, near.el > 0.0);
+        let far = Position::new(0.0, 180.0, 0.0, UnitSpecifier::Degrees);
+        let far_view = observing::site_from_earth(sl.clone(), t, o, far).unwrap();
+        assert!(!far_view.on_disk);
+
+        let late = uncovered_datetime();
+        assert!(observing::moon_sky(sl.clone(), late, o).is_err());
+        let until = late + time::Duration::days(1);
+        assert!(observing::moon_rise_set(sl.clone(), late, until, 600.0, o).is_err());
+        assert!(observing::site_from_earth(sl, late, o, far).is_err());
+    }
+
+    #[test]
+    fn test_earth_orientation_falls_back_to_iau() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        // the high-precision PCK starts in 2000
+        let date = Date::from_calendar_date(1995, Month::June, 1).unwrap();
+        let early = OffsetDateTime::new_utc(date, Time::MIDNIGHT);
+        let view = earth::earth_view(sl.clone(), early, Position::cadre()).unwrap();
+        assert_eq!(view.orientation, EarthOrientation::IauEarth);
+
+        let view = earth::earth_view(sl.clone(), t, Position::cadre()).unwrap();
+        if !std::path::Path::new(OPTIONAL_KERNELS[0]).exists() {
+            assert_eq!(view.orientation, EarthOrientation::IauEarth);
+        }
+        let goldstone = Station::named("GOLDSTONE").unwrap();
+        let target = CommsTarget::Site(Position::cadre());
+        let link = dsn::comms(sl, t, &goldstone, &target, 8.4e9).unwrap();
+        assert_eq!(link.orientation, view.orientation);
+    }
+
+    #[test]
+    fn test_dsn_covers_the_moon() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let until = t + time::Duration::days(2);
+        let stations = Station::dsn_complexes();
+        let res = dsn::dsn_visibility(sl.clone(), t, until, 300.0, None, &stations).unwrap();
+        // each complex sees the Moon about once a day
+        for station in &stations {
+            let passes = res.passes.iter().filter(|p| p.station == station.name).count();
+            assert!((1..=3).contains(&passes));
+        }
+        assert!(res.passes.iter().all(|p| p.start < p.end && p.max_el > 6f64.to_radians()));
+        // the schedule only uses passes, in order
+        for pair in res.schedule.windows(2) {
+            assert!(pair[0].end <= pair[1].start);
+        }
+        for c in &res.schedule {
+            assert!(res
+                .passes
+                .iter()
+                .any(|p| p.station == c.station && p.start <= c.start && c.end <= p.end));
+        }
+
+        let late = uncovered_datetime();
+        let until = late + time::Duration::days(1);
+        assert!(dsn::dsn_visibility(sl, late, until, 300.0, None, &stations).is_err());
+    }
+
+    #[test]
+    fn test_comms_doppler_follows_range_rate() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let goldstone = Station::named("goldstone").unwrap();
+        let target = CommsTarget::Site(Position::cadre());
+        let link = dsn::comms(sl.clone(), t, &goldstone, &target, 8.4e9).unwrap();
+        assert!((link.light_time - link.range / 299_792.458).abs() < 1e-9);
+        assert!((link.doppler_one_way + 8.4e9 * link.range_rate / 299_792.458).abs() < 1.0);
+        assert!((link.doppler_two_way - 2.0 * link.doppler_one_way).abs() < 1.0);
+        // about 222 dB at X-band to the Moon
+        assert!((link.path_loss - 222.5).abs() < 1.5);
+
+        // range rate agrees with the change in range over a second
+        let second = t + time::Duration::seconds(1);
+        let later = dsn::comms(sl.clone(), second, &goldstone, &target, 8.4e9);
+        assert!((later.unwrap().range - link.range - link.range_rate).abs() < 1e-3);
+
+        let late = uncovered_datetime();
+        assert!(dsn::comms(sl, late, &goldstone, &target, 8.4e9).is_err());
+    }
+
+    #[test]
+    fn test_eclipse_of_march_2025() {
+        // total lunar eclipse, greatest at about 06:59 UTC on 2025-03-14
+        let utc = |month, day, hour, minute| {
+            let date = Date::from_calendar_date(2025, month, day).unwrap();
+            OffsetDateTime::new_utc(date, Time::from_hms(hour, minute, 0).unwrap())
+        };
+        let sl = setup_spice();
+        let t = utc(Month::March, 1, 0, 0);
+        let until = utc(Month::April, 1, 0, 0);
+        let near_side = Position::new(0.0, 0.0, 0.0, UnitSpecifier::Degrees);
+        let res = eclipse::eclipses(sl, t, until, near_side).unwrap();
+        assert_eq!(res.eclipses.len(), 1);
+        let eclipse = &res.eclipses[0];
+        assert_eq!(eclipse.max_fraction, 1.0);
+        assert!(eclipse.sun_up);
+        let greatest = utc(Month::March, 14, 6, 59);
+        assert!((eclipse.max_at - greatest).abs() < time::Duration::minutes(30));
+        let kinds: Vec<_> = eclipse.phases.iter().map(|p| p.kind).collect();
+        assert_eq!(
+            kinds,
+            [EclipseKind::Penumbral, EclipseKind::Umbral, EclipseKind::Penumbral]
+        );
+    }
+
+    #[test]
+    fn test_illumination_map_near_the_sub_earth_point() {
+        let sl = setup_spice();
+        let t = test_datetime();
+        let until = t + time::Duration::days(30);
+        let sites = [Position::new(0.0, 0.0, 0.0, UnitSpecifier::Degrees), Position::cadre()];
+        let cells = maps::cells(sl, &sites, t, until, 3600.0, Surface::Ellipsoid).unwrap();
+        // Earth never sets near the sub-Earth point, and the Sun is up about half the month
+        assert_eq!(cells[0].earth, 100.0);
+        for cell in &cells {
+            assert!((cell.sun - 50.0).abs() < 5.0);
+            assert!(cell.both <= cell.sun.min(cell.earth));
+        }
+
+        let region = Region {
+            lat_min: -1.0,
+            lat_max: 1.0,
+            lon_min: -2.0,
+            lon_max: 2.0,
+            spacing: 1.0,
+            units: UnitSpecifier::Degrees,
+        };
+        assert!(region.check().is_ok());
+        assert_eq!((region.lats().len(), region.lons().len()), (3, 5));
+
+        // a year of hourly samples is several jobs' worth of cells, not one
+        let year = maps::samples(0.0, 366.0 * 86400.0, 3600.0);
+        assert_eq!(maps::cells_per_job(year, Surface::Ellipsoid), Some(5));
+        assert_eq!(maps::cells_per_job(maps::MAX_JOB_EVALUATIONS + 1, Surface::Ellipsoid), None);
+        assert!(try_format_as(cells[0].sun, FormatSpecifier::Pgm, None).is_err());
+        let json = try_format_as(cells[0].sun, FormatSpecifier::Json, None).unwrap();
+        assert_eq!(format_as(cells[0].sun, FormatSpecifier::Pgm, None), json);
+    }
+
+    #[test]
+    fn test_horizon_mask_events() {
+        let pos = Position::cadre();
+        let csv = "az,el\n0,10\n90,20\n180,10\n270,0\n";
+        let mask = HorizonMask::from_csv(pos, csv).unwrap();
+        assert!((mask.elevation_at(45f64.to_radians()) - 15f64.to_radians()).abs() < 1e-12);
+        assert!((mask.elevation_at(315f64.to_radians()) - 5f64.to_radians()).abs() < 1e-12);
+        assert!(mask.to_svg().starts_with("<svg"));
+        // the same site round-tripped through radians is still the mask's
+        assert!(mask.is_for(pos.to_radians()));
+        assert!(!mask.is_for(Position { lat: pos.lat + 0.01, ..pos }));
+
+        let sl = setup_spice();
+        let t = test_datetime();
+        let until = t + time::Duration::days(30);
+        let vis = horizon::visibility(sl.clone(), &mask, "SUN", t, Some(until), 600.0).unwrap();
+        assert_eq!(vis.visible, vis.el > vis.mask_el);
+        assert!(vis.events.iter().any(|e| e.rising));
+        for event in vis.events {
+            let sun = solar_azel(sl.clone(), event.t, pos);
+            assert!(mask.clearance(&sun).abs() < 1e-3);
+        }
+        assert!(horizon::visibility(sl, &mask, "SUN", uncovered_datetime(), None, 600.0).is_err());
+    }
+
     #[test]
     fn test_earth_position_from_sun_exact() {
         let sl = setup_spice();
@@ -300,6 +1360,42 @@ mod tests {
         assert_eq!(pos.units, UnitSpecifier::Radians);
     }
 
+    #[cfg(feature = "pure-rust")]
+    #[test]
+    fn test_pure_matches_cspice() {
+        let sl = setup_spice();
+        let eph = Ephemeris::load(KERNELS).unwrap();
+        let start = test_datetime();
+        for day in 0..30 {
+            let t = start + time::Duration::hours(day * 29);
+            let et = pure::get_et(&eph, t).unwrap();
+            assert!((et - get_et(sl.clone(), t)).abs() < 1e-6);
+
+            let pairs = [
+                (
+                    pure::earth_position_from_sun(&eph, t).unwrap(),
+                    earth_position_from_sun(sl.clone(), t),
+                ),
+                (
+                    pure::earth_position_ecliptic(&eph, t).unwrap(),
+                    earth_position_ecliptic(sl.clone(), t),
+                ),
+            ];
+            for (a, b) in pairs {
+                assert!((a.x - b.x).abs() < 1e-3);
+                assert!((a.y - b.y).abs() < 1e-3);
+                assert!((a.z - b.z).abs() < 1e-3);
+            }
+
+            let pos = Position::cadre();
+            let a = pure::solar_azel(&eph, t, pos).unwrap();
+            let b = solar_azel(sl.clone(), t, pos);
+            assert!((a.az - b.az).abs() < 1e-9);
+            assert!((a.el - b.el).abs() < 1e-9);
+            assert!((a.r - b.r).abs() < 1e-3);
+        }
+    }
+
     #[test]
     fn test_position_full_to_xyz_conversion() {
         let full = PositionFull {
@@ -357,3 +1453,56 @@ mod tests {
     }
 }
 
+#[cfg(all(test, feature = "pure-rust"))]
+mod pure_tests {
+    use super::*;
+    use std::sync::OnceLock;
+    use time::{Date, Month, OffsetDateTime, Time};
+
+    static EPHEMERIS: OnceLock<Ephemeris> = OnceLock::new();
+
+    fn ephemeris() -> &'static Ephemeris {
+        EPHEMERIS.get_or_init(|| Ephemeris::load(KERNELS).unwrap())
+    }
+
+    fn test_datetime() -> OffsetDateTime {
+        let date = Date::from_calendar_date(2024, Month::June, 1).unwrap();
+        let time = Time::from_hms(12, 0, 0).unwrap();
+        OffsetDateTime::new_utc(date, time)
+    }
+
+    #[test]
+    fn test_pure_get_et() {
+        let et = pure::get_et(ephemeris(), test_datetime()).unwrap();
+        assert!((et - 770515269.1848872).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pure_earth_position_from_sun() {
+        let pos = pure::earth_position_from_sun(ephemeris(), test_datetime()).unwrap();
+        assert!((pos.x - 145931759.53936464).abs() < 1e-3);
+        assert!((pos.y - 41434333.28598916).abs() < 1e-3);
+        assert!((pos.z - -1534006.420671329).abs() < 1e-3);
+        assert!((pos.lon - 0.2766488084928851).abs() < 1e-12);
+        assert!((pos.lat - -0.010111762624525013).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pure_earth_position_ecliptic() {
+        let pos = pure::earth_position_ecliptic(ephemeris(), test_datetime()).unwrap();
+        assert!((pos.x - -49025028.037208125).abs() < 1e-3);
+        assert!((pos.y - -143568040.1350961).abs() < 1e-3);
+        assert!((pos.z - 8398.000590592623).abs() < 1e-3);
+        assert!((pos.lon - -1.8998572176894484).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pure_cadre_solar_azel() {
+        let azel = pure::solar_azel(ephemeris(), test_datetime(), Position::cadre()).unwrap();
+        assert!((azel.az - 1.6349707743817739).abs() < 1e-9);
+        assert!((azel.el - 0.6110381109126339).abs() < 1e-9);
+        assert!((azel.r - 151559808.5801367).abs() < 1e-3);
+        let earth = Position::cadre().on(Body::Earth);
+        assert!(pure::solar_azel(ephemeris(), test_datetime(), earth).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Live site geometry for streaming clients.
+//!
+//! Every subscriber to the same site and cadence shares one producer task, so
+//! a wall of displays watching one site costs one SPICE call per tick rather
+//! than one per display. The producer stops once its last subscriber leaves.
+
+use crate::types::*;
+use crate::worker::SpiceWorker;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+
+pub const DEFAULT_CADENCE: Duration = Duration::from_secs(1);
+pub const MIN_CADENCE: Duration = Duration::from_millis(100);
+/// Samples a slow subscriber may fall behind before it skips ahead.
+const BACKLOG: usize = 16;
+/// Most distinct streams at once. Each one queues a SPICE job every tick,
+/// so without a cap clients asking for slightly different sites could keep
+/// the worker too busy to answer anything else.
+pub const MAX_STREAMS: usize = 16;
+
+/// One tick of a live stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiveSample {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    pub et: f64,
+    pub solar_time: String,
+    pub sun: RAzEl,
+    pub earth: RAzEl,
+}
+
+impl std::fmt::Display for LiveSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "t: {}, et: {}, solar time: {}, sun: {}; earth: {}",
+            self.t, self.et, self.solar_time, self.sun, self.earth
+        )
+    }
+}
+
+impl Angular for LiveSample {
+    fn to_degrees(&self) -> LiveSample {
+        LiveSample {
+            sun: self.sun.to_degrees(),
+            earth: self.earth.to_degrees(),
+            ..self.clone()
+        }
+    }
+    fn to_radians(&self) -> LiveSample {
+        LiveSample {
+            sun: self.sun.to_radians(),
+            earth: self.earth.to_radians(),
+            ..self.clone()
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.sun.units
+    }
+}
+
+/// Sun and Earth pointing, solar time and ET for `pos` at `t`.
+pub fn sample(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+) -> Result<LiveSample, String> {
+    let solar_time = crate::solar_time(sl_mutex.clone(), t, pos)?;
+    let lock = crate::lock_spice(&sl_mutex);
+    let et = lock.str2et(crate::to_cspice_string(t).as_str());
+    let frame = crate::body_frame(&lock, pos.body, &[et]);
+    Ok(LiveSample {
+        t,
+        et,
+        solar_time,
+        sun: crate::target_azel_et(&lock, et, pos, frame, "SUN"),
+        earth: crate::target_azel_et(&lock, et, pos, frame, "EARTH"),
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct StreamKey {
+    pos: [u64; 3],
+    body: Body,
+    cadence_ms: u64,
+}
+
+/// A sample, or why this tick has none.
+pub type LiveReceiver = broadcast::Receiver<Result<LiveSample, String>>;
+type LiveSender = broadcast::Sender<Result<LiveSample, String>>;
+
+pub struct LiveHub {
+    worker: SpiceWorker,
+    streams: Mutex<HashMap<StreamKey, LiveSender>>,
+}
+
+impl LiveHub {
+    pub fn new(worker: SpiceWorker) -> LiveHub {
+        LiveHub {
+            worker,
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Joins the stream for `pos` at `cadence`, starting it if needed.
+    /// Cadences below `MIN_CADENCE` are raised to it. `None` when starting it
+    /// would make more than `MAX_STREAMS`.
+    pub fn subscribe(self: &Arc<Self>, pos: Position, cadence: Duration) -> Option<LiveReceiver> {
+        let cadence = cadence.max(MIN_CADENCE);
+        let p = pos.to_radians();
+        let key = StreamKey {
+            pos: [p.lat.to_bits(), p.lon.to_bits(), p.alt.to_bits()],
+            body: p.body,
+            cadence_ms: cadence.as_millis() as u64,
+        };
+
+        let mut streams = self.streams.lock().unwrap();
+        if let Some(tx) = streams.get(&key) {
+            return Some(tx.subscribe());
+        }
+        if streams.len() >= MAX_STREAMS {
+            return None;
+        }
+        let (tx, rx) = broadcast::channel(BACKLOG);
+        streams.insert(key, tx.clone());
+        tokio::spawn(self.clone().produce(key, tx, pos, cadence));
+        Some(rx)
+    }
+
+    async fn produce(
+        self: Arc<Self>,
+        key: StreamKey,
+        tx: LiveSender,
+        pos: Position,
+        cadence: Duration,
+    ) {
+        let mut ticks = tokio::time::interval(cadence);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticks.tick().await;
+            {
+                // checked under the map lock so a concurrent subscribe either
+                // finds this stream still running or starts a new one
+                let mut streams = self.streams.lock().unwrap();
+                if tx.receiver_count() == 0 {
+                    streams.remove(&key);
+                    return;
+                }
+            }
+            let t = default_datetime();
+            let msg = match self.worker.live_sample(t, pos).await {
+                Ok(sample) => sample,
+                Err(e) => Err(e.to_string()),
+            };
+            let _ = tx.send(msg);
+        }
+    }
+
+    /// Number of running streams.
+    pub fn streams(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+}
@@ -3,18 +3,24 @@ mod readme;
 use moontime::*;
 
 use std::{
+    convert::Infallible,
     env::set_var,
     sync::{Arc, Mutex},
 };
 
+#[cfg(feature = "ws")]
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::{
-    extract::{Json, Path, Query, State},
+    extract::{FromRef, Json, Path, Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
     routing::get,
     routing::post,
     Router,
 };
 use serde::{Deserialize, Serialize};
 use spice::SpiceLock;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 
 #[derive(Debug, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -26,6 +32,117 @@ enum CoordFormat {
 
 use lambda_http::{run, Error};
 
+#[derive(Clone)]
+struct AppState {
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    fits: Arc<FitRegistry>,
+    masks: Arc<MaskRegistry>,
+    live: Arc<LiveHub>,
+    missions: Arc<MissionRegistry>,
+}
+
+impl FromRef<AppState> for SpiceWorker {
+    fn from_ref(state: &AppState) -> SpiceWorker {
+        state.worker.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ResponseCache> {
+    fn from_ref(state: &AppState) -> Arc<ResponseCache> {
+        state.cache.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<FitRegistry> {
+    fn from_ref(state: &AppState) -> Arc<FitRegistry> {
+        state.fits.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<MaskRegistry> {
+    fn from_ref(state: &AppState) -> Arc<MaskRegistry> {
+        state.masks.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LiveHub> {
+    fn from_ref(state: &AppState) -> Arc<LiveHub> {
+        state.live.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<MissionRegistry> {
+    fn from_ref(state: &AppState) -> Arc<MissionRegistry> {
+        state.missions.clone()
+    }
+}
+
+fn worker_error(e: WorkerError) -> (StatusCode, String) {
+    let code = match e {
+        WorkerError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+        WorkerError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        WorkerError::Failed => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (code, e.to_string())
+}
+
+/// Rejects sites off the Moon, for endpoints that only model lunar sites.
+fn on_moon(p: &Position) -> Result<(), (StatusCode, String)> {
+    if p.body != Body::Moon {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("this endpoint needs a site on the moon, not {}", p.body),
+        ));
+    }
+    Ok(())
+}
+
+/// Sun az/el for the sun endpoints; terrain errors (no DSK loaded, no
+/// coverage at the site) and missing kernels for other bodies are the
+/// client's to fix.
+async fn sun_azel(
+    worker: &SpiceWorker,
+    t: DateTime,
+    p: Position,
+    surface: Surface,
+) -> Result<RAzEl, (StatusCode, String)> {
+    if surface == Surface::Ellipsoid && p.body == Body::Moon {
+        return worker.solar_azel(t, p).await.map_err(worker_error);
+    }
+    worker
+        .target_azel(t, p, "SUN", surface)
+        .await
+        .map_err(worker_error)?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+fn unsupported_format() -> (StatusCode, String) {
+    (
+        StatusCode::BAD_REQUEST,
+        "format not supported for this endpoint".to_string(),
+    )
+}
+
+/// `res` as json or txt, the forms every endpoint has.
+fn render<T: Serialize + std::fmt::Display>(
+    res: T,
+    f: FormatSpecifier,
+    hint: &str,
+) -> Result<String, (StatusCode, String)> {
+    moontime::try_format_as(res, f, Some(hint)).map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+/// Turns away formats other than json, txt and `extra` before any work is
+/// done, for endpoints slow enough that rendering last would waste it.
+fn check_format(f: FormatSpecifier, extra: &[FormatSpecifier]) -> Result<(), (StatusCode, String)> {
+    match f {
+        FormatSpecifier::Json | FormatSpecifier::Txt => Ok(()),
+        f if extra.contains(&f) => Ok(()),
+        _ => Err(unsupported_format()),
+    }
+}
+
 async fn get_readme() -> Result<String, String> {
     Ok(format!(
         "\nVersion: {}\nAuthor: {}\nHomepage: {}\n\n{}",
@@ -38,6 +155,13 @@ async fn get_readme() -> Result<String, String> {
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
+    if std::env::var(pool::WORKER_ENV).is_ok() {
+        let sl = SpiceLock::try_acquire().unwrap();
+        moontime::load_kernels(&sl);
+        pool::serve_stdio(Arc::new(Mutex::new(sl)));
+        return Ok(());
+    }
+
     set_var("AWS_LAMBDA_HTTP_IGNORE_STAGE_IN_PATH", "true");
 
     for (key, value) in std::env::vars() {
@@ -62,32 +186,63 @@ async fn main() -> Result<(), Error> {
 
     let in_lambda = std::env::var("LAMBDA_TASK_ROOT").is_ok();
 
-    //let mut tlskernel = spice::furnsh("./latest_leapseconds.tls");
     let sl = SpiceLock::try_acquire().unwrap();
-    sl.furnsh("data/latest_leapseconds.tls");
-    //sl.furnsh("data/earth_fixed.tf");
-    //sl.furnsh("data/de440.bsp");
-    sl.furnsh("data/de440s.bsp");
-    //sl.furnsh("data/moon_080317.tf");
-    //sl.furnsh("data/moon_assoc_me.tf");
-    //sl.furnsh("data/moon_assoc_pa.tf");
-    //sl.furnsh("data/earth_latest_high_prec.bpc");
-    //sl.furnsh("data/moon_pa_de440_200625.bpc");
-    sl.furnsh("data/moon_pa_de440_200625.bpc");
-    sl.furnsh("data/moon_de440_200625.tf");
-    sl.furnsh("data/pck00010.tpc");
+    moontime::load_kernels(&sl);
 
     let tlskernel = Arc::new(Mutex::new(sl));
 
-    let app: Router = Router::new()
+    let queue_capacity = std::env::var("MOONTIME_QUEUE_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(worker::DEFAULT_QUEUE_CAPACITY);
+    let timeout = std::env::var("MOONTIME_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(worker::DEFAULT_TIMEOUT);
+    let mut worker = SpiceWorker::new(tlskernel, queue_capacity, timeout);
+
+    let processes: usize = std::env::var("MOONTIME_PROCESSES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if processes > 0 {
+        println!("Starting {} worker processes", processes);
+        let pool = WorkerPool::new(processes, queue_capacity, timeout)?;
+        worker = worker.with_pool(pool);
+    }
+
+    let cache_size = std::env::var("MOONTIME_CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(cache::DEFAULT_CACHE_SIZE);
+    let cache_ttl = std::env::var("MOONTIME_CACHE_TTL_S")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(cache::DEFAULT_CACHE_TTL);
+    let cache = ResponseCache::new(cache_size, cache_ttl, moontime::kernel_set_version());
+
+    let missions = MissionRegistry::load()?;
+
+    let state = AppState {
+        live: Arc::new(LiveHub::new(worker.clone())),
+        worker,
+        cache: Arc::new(cache),
+        fits: Arc::new(FitRegistry::new(64)),
+        masks: Arc::new(MaskRegistry::new(64)),
+        missions: Arc::new(missions),
+    };
+
+    let app = Router::new()
         .route("/s/et", get(get_et_time))
         .route("/s/et", post(post_et_time))
-        .route("/s/moon/solartime", get(moon_get_solar_time))
-        .route("/s/moon/solartime", post(moon_post_solar_time))
-        .route("/s/moon/sun", get(moon_get_sun_azel))
-        .route("/s/moon/sun", post(moon_post_sun_azel))
-        .route("/s/moon/sun/:format", get(moon_get_sun))
-        .route("/s/moon/sun/:format", post(moon_post_sun))
+        .route("/s/:body/solartime", get(body_get_solar_time))
+        .route("/s/:body/solartime", post(body_post_solar_time))
+        .route("/s/:body/sun", get(body_get_sun_azel))
+        .route("/s/:body/sun", post(body_post_sun_azel))
+        .route("/s/:body/sun/:format", get(body_get_sun))
+        .route("/s/:body/sun/:format", post(body_post_sun))
         .route("/s/cadre/solartime", get(cadre_get_solar_time))
         .route("/s/cadre/solartime", post(cadre_post_solar_time))
         .route("/s/cadre/sun", get(cadre_get_sun_azel))
@@ -105,7 +260,83 @@ async fn main() -> Result<(), Error> {
         //.route("/cadre/daylighthours", get(get_daylight_hours))
         .route("/s/readme", get(get_readme))
         .route("/s/readme", post(get_readme))
-        .with_state(tlskernel);
+        .route("/s/status", get(get_status))
+        .route("/s/cache", get(get_cache_stats))
+        .route("/s/fit", get(get_fit))
+        .route("/s/fit", post(post_fit))
+        .route("/s/fit/:id", get(get_fit_eval))
+        .route("/s/fit/:id", post(post_fit_eval))
+        .route("/s/moon/subsolar", get(get_subsolar))
+        .route("/s/moon/subsolar", post(post_subsolar))
+        .route("/s/moon/subearth", get(get_sub_earth))
+        .route("/s/moon/subearth", post(post_sub_earth))
+        .route("/s/moon/terminator", get(get_terminator))
+        .route("/s/moon/terminator", post(post_terminator))
+        .route("/s/moon/terminator/distance", get(moon_get_terminator_distance))
+        .route("/s/moon/terminator/distance", post(moon_post_terminator_distance))
+        .route("/s/cadre/terminator/distance", get(cadre_get_terminator_distance))
+        .route("/s/cadre/terminator/distance", post(cadre_post_terminator_distance))
+        .route("/s/moon/illumination", get(moon_get_illumination))
+        .route("/s/moon/illumination", post(moon_post_illumination))
+        .route("/s/cadre/illumination", get(cadre_get_illumination))
+        .route("/s/cadre/illumination", post(cadre_post_illumination))
+        .route("/s/moon/power", get(moon_get_power))
+        .route("/s/moon/power", post(moon_post_power))
+        .route("/s/cadre/power", get(cadre_get_power))
+        .route("/s/cadre/power", post(cadre_post_power))
+        .route("/s/moon/shadow", get(moon_get_shadow))
+        .route("/s/moon/shadow", post(moon_post_shadow))
+        .route("/s/cadre/shadow", get(cadre_get_shadow))
+        .route("/s/cadre/shadow", post(cadre_post_shadow))
+        .route("/s/moon/earth", get(moon_get_earth))
+        .route("/s/moon/earth", post(moon_post_earth))
+        .route("/s/cadre/earth", get(cadre_get_earth))
+        .route("/s/cadre/earth", post(cadre_post_earth))
+        .route("/s/moon/earth/track", get(moon_get_earth_track))
+        .route("/s/moon/earth/track", post(moon_post_earth_track))
+        .route("/s/cadre/earth/track", get(cadre_get_earth_track))
+        .route("/s/cadre/earth/track", post(cadre_post_earth_track))
+        .route("/s/moon/dsn", get(moon_get_dsn))
+        .route("/s/moon/dsn", post(moon_post_dsn))
+        .route("/s/cadre/dsn", get(cadre_get_dsn))
+        .route("/s/cadre/dsn", post(cadre_post_dsn))
+        .route("/s/earth/moon", get(earth_get_moon))
+        .route("/s/earth/moon", post(earth_post_moon))
+        .route("/s/earth/moon/riseset", get(earth_get_moon_rise_set))
+        .route("/s/earth/moon/riseset", post(earth_post_moon_rise_set))
+        .route("/s/earth/site", get(earth_get_site))
+        .route("/s/earth/site", post(earth_post_site))
+        .route("/s/earth/cadre", get(earth_get_cadre))
+        .route("/s/earth/cadre", post(earth_post_cadre))
+        .route("/s/moon/comms", get(moon_get_comms))
+        .route("/s/moon/comms", post(moon_post_comms))
+        .route("/s/cadre/comms", get(cadre_get_comms))
+        .route("/s/cadre/comms", post(cadre_post_comms))
+        .route("/s/mars/time", get(mars_get_time))
+        .route("/s/mars/time", post(mars_post_time))
+        .route("/s/missions", get(get_missions))
+        .route("/s/:body/clock", get(body_get_clock))
+        .route("/s/:body/clock", post(body_post_clock))
+        .route("/s/cadre/clock", get(cadre_get_clock))
+        .route("/s/cadre/clock", post(cadre_post_clock))
+        .route("/s/moon/eclipses", get(moon_get_eclipses))
+        .route("/s/moon/eclipses", post(moon_post_eclipses))
+        .route("/s/cadre/eclipses", get(cadre_get_eclipses))
+        .route("/s/cadre/eclipses", post(cadre_post_eclipses))
+        .route("/s/moon/map", get(get_map))
+        .route("/s/moon/map", post(post_map))
+        .route("/s/horizon", post(post_horizon_new))
+        .route("/s/horizon/:id", get(get_horizon))
+        .route("/s/horizon/:id", post(post_horizon))
+        .route("/s/horizon/:id/visibility", get(get_horizon_visibility))
+        .route("/s/horizon/:id/visibility", post(post_horizon_visibility))
+        .route("/s/cadre/live", get(cadre_get_live))
+        .route("/s/moon/live", get(moon_get_live));
+    #[cfg(feature = "ws")]
+    let app = app
+        .route("/s/cadre/live/ws", get(cadre_get_live_ws))
+        .route("/s/moon/live/ws", get(moon_get_live_ws));
+    let app: Router = app.with_state(state);
 
     if in_lambda {
         println!("Running in AWS Lambda");
@@ -128,282 +359,494 @@ async fn main() -> Result<(), Error> {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct StatusQuery {
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+}
+
+async fn get_status(
+    State(worker): State<SpiceWorker>,
+    Query(StatusQuery { f }): Query<StatusQuery>,
+) -> Result<String, (StatusCode, String)> {
+    render(worker.status(), f, "status")
+}
+
+async fn get_cache_stats(
+    State(cache): State<Arc<ResponseCache>>,
+    Query(StatusQuery { f }): Query<StatusQuery>,
+) -> Result<String, (StatusCode, String)> {
+    render(cache.stats(), f, "cache")
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct GetEtTime {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
 }
 
 async fn get_et_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Query(GetEtTime { t, f }): Query<GetEtTime>,
-) -> Result<String, ()> {
+) -> Result<String, (StatusCode, String)> {
     println!("t: {:?}, f: {:?}", t, f);
-    let res = moontime::get_et(sl_mutex, t);
-    Ok(moontime::format_as(res, f, Some("et")))
+    let key = t.map(|t| CacheKey::new("et", t).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.get_et(t).await.map_err(worker_error)?;
+            render(res, f, "et")
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct EtBody {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    pub t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    pub t: Option<DateTime>,
     #[serde(default = "default_format")]
     pub f: FormatSpecifier,
 }
 
 async fn post_et_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Json(EtBody { t, f }): Json<EtBody>,
-) -> Result<String, ()> {
+) -> Result<String, (StatusCode, String)> {
     println!("t: {:?}, f: {:?}", t, f);
-    let res = moontime::get_et(sl_mutex, t);
-    Ok(moontime::format_as(res, f, Some("et")))
+    let key = t.map(|t| CacheKey::new("et", t).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.get_et(t).await.map_err(worker_error)?;
+            render(res, f, "et")
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct MoonSolarTime {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     p: Position,
 }
-async fn moon_post_solar_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+async fn body_post_solar_time(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path(body): Path<Body>,
     Json(MoonSolarTime { f, t, p }): Json<MoonSolarTime>,
-) -> Result<String, ()> {
-    let result = moontime::solar_time(sl_mutex, t, p.to_radians()).unwrap();
-    Ok(moontime::format_as(result, f, Some("solar time")))
+) -> Result<String, (StatusCode, String)> {
+    let p = p.on(body);
+    let endpoint = format!("{}/solartime", body);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let result = worker
+                .solar_time(t, p.to_radians())
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(result, f, "solar time")
+        })
+        .await
 }
 
-async fn moon_get_solar_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+async fn body_get_solar_time(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path(body): Path<Body>,
     Query(MoonSolarTime { t, f, p }): Query<MoonSolarTime>,
-) -> Result<String, ()> {
+) -> Result<String, (StatusCode, String)> {
     println!("t: {:?}, f: {:?}, p: {:?}", t, f, p);
-    let result = moontime::solar_time(sl_mutex, t, p).unwrap();
-    Ok(moontime::format_as(result, f, Some("solar time")))
+    let p = p.on(body);
+    let endpoint = format!("{}/solartime", body);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let result = worker
+                .solar_time(t, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(result, f, "solar time")
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct MoonPostSolarAzel {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
     u: UnitSpecifier,
     p: Position,
+    #[serde(default)]
+    terrain: bool,
 }
 
-async fn moon_post_sun_azel(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Json(MoonPostSolarAzel { t, f, u, p }): Json<MoonPostSolarAzel>,
-) -> Result<String, ()> {
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("moon_sun"));
-    Ok(res)
+async fn body_post_sun_azel(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path(body): Path<Body>,
+    Json(MoonPostSolarAzel { t, f, u, p, terrain }): Json<MoonPostSolarAzel>,
+) -> Result<String, (StatusCode, String)> {
+    let p = p.on(body);
+    let endpoint = format!("{}/sun", body);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "moon_sun")
+        })
+        .await
 }
 
-async fn moon_post_sun(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Path(coord_format): Path<CoordFormat>,
-    Json(MoonPostSolarAzel { t, f, u, p }): Json<MoonPostSolarAzel>,
-) -> Result<String, ()> {
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("moon_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("moon_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            let azel = moontime::translate_to(res, u);
-            Ok(moontime::format_as(azel, f, Some("moon_sun_azel")))
-        }
-    }
+async fn body_post_sun(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path((body, coord_format)): Path<(Body, CoordFormat)>,
+    Json(MoonPostSolarAzel { t, f, u, p, terrain }): Json<MoonPostSolarAzel>,
+) -> Result<String, (StatusCode, String)> {
+    let p = p.on(body);
+    let endpoint = format!("{}/sun/{:?}", body, coord_format);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "moon_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "moon_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    let azel = moontime::translate_to(res, u);
+                    render(azel, f, "moon_sun_azel")
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct MoonQuerySolarAzel {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
     u: UnitSpecifier,
     p: Position,
+    #[serde(default)]
+    terrain: bool,
 }
 
-async fn moon_get_sun_azel(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Query(MoonQuerySolarAzel { t, f, u, p }): Query<MoonQuerySolarAzel>,
-) -> Result<String, ()> {
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("moon_sun"));
-    Ok(res)
+async fn body_get_sun_azel(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path(body): Path<Body>,
+    Query(MoonQuerySolarAzel { t, f, u, p, terrain }): Query<MoonQuerySolarAzel>,
+) -> Result<String, (StatusCode, String)> {
+    let p = p.on(body);
+    let endpoint = format!("{}/sun", body);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "moon_sun")
+        })
+        .await
 }
 
-async fn moon_get_sun(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Path(coord_format): Path<CoordFormat>,
-    Query(MoonQuerySolarAzel { t, f, u, p }): Query<MoonQuerySolarAzel>,
-) -> Result<String, ()> {
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("moon_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("moon_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            let azel = moontime::translate_to(res, u);
-            Ok(moontime::format_as(azel, f, Some("moon_sun_azel")))
-        }
-    }
+async fn body_get_sun(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Path((body, coord_format)): Path<(Body, CoordFormat)>,
+    Query(MoonQuerySolarAzel { t, f, u, p, terrain }): Query<MoonQuerySolarAzel>,
+) -> Result<String, (StatusCode, String)> {
+    let p = p.on(body);
+    let endpoint = format!("{}/sun/{:?}", body, coord_format);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "moon_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "moon_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    let azel = moontime::translate_to(res, u);
+                    render(azel, f, "moon_sun_azel")
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CADREPostSolarTime {
     #[serde(default = "default_format")]
     f: FormatSpecifier,
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
 }
 async fn cadre_post_solar_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Json(CADREPostSolarTime { f, t }): Json<CADREPostSolarTime>,
-) -> Result<String, ()> {
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
     println!("t: {:?}, f: {:?}, p: {:?}", t, f, p);
-    let result = moontime::solar_time(sl_mutex, t, p.to_radians()).unwrap();
-    Ok(moontime::format_as(result, f, Some("solar time")))
+    let key = t.map(|t| CacheKey::new("cadre/solartime", t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let result = worker
+                .solar_time(t, p.to_radians())
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(result, f, "solar time")
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CADRESolarTimeQuery {
     #[serde(default = "default_format")]
     f: FormatSpecifier,
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
 }
 
 async fn cadre_get_solar_time(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Query(CADRESolarTimeQuery { t, f }): Query<CADRESolarTimeQuery>,
-) -> Result<String, ()> {
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
     println!("t: {:?}, f: {:?}, p: {:?}", t, f, p);
-    let result = moontime::solar_time(sl_mutex, t, p).unwrap();
-    Ok(moontime::format_as(result, f, Some("solar time")))
+    let key = t.map(|t| CacheKey::new("cadre/solartime", t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let result = worker
+                .solar_time(t, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(result, f, "solar time")
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CADREPostSolarAzel {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
     u: UnitSpecifier,
+    #[serde(default)]
+    terrain: bool,
 }
 
 async fn cadre_post_sun_azel(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Json(CADREPostSolarAzel { t, f, u }): Json<CADREPostSolarAzel>,
-) -> Result<String, ()> {
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(CADREPostSolarAzel { t, f, u, terrain }): Json<CADREPostSolarAzel>,
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("cadre_sun"));
-    Ok(res)
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new("cadre/sun", t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "cadre_sun")
+        })
+        .await
 }
 
 async fn cadre_post_sun(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
-    Json(CADREPostSolarAzel { t, f, u }): Json<CADREPostSolarAzel>,
-) -> Result<String, ()> {
+    Json(CADREPostSolarAzel { t, f, u, terrain }): Json<CADREPostSolarAzel>,
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("cadre_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("cadre_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            let azel = moontime::translate_to(res, u);
-            Ok(moontime::format_as(azel, f, Some("cadre_sun_azel")))
-        }
-    }
+    let endpoint = format!("cadre/sun/{:?}", coord_format);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "cadre_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "cadre_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    let azel = moontime::translate_to(res, u);
+                    render(azel, f, "cadre_sun_azel")
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CADREQuerySolarAzel {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
     u: UnitSpecifier,
+    #[serde(default)]
+    terrain: bool,
 }
 
 async fn cadre_get_sun_azel(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
-    Query(CADREQuerySolarAzel { t, f, u }): Query<CADREQuerySolarAzel>,
-) -> Result<String, ()> {
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(CADREQuerySolarAzel { t, f, u, terrain }): Query<CADREQuerySolarAzel>,
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("cadre_sun"));
-    Ok(res)
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new("cadre/sun", t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "cadre_sun")
+        })
+        .await
 }
 
 async fn cadre_get_sun(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
-    Query(CADREQuerySolarAzel { t, f, u }): Query<CADREQuerySolarAzel>,
-) -> Result<String, ()> {
+    Query(CADREQuerySolarAzel { t, f, u, terrain }): Query<CADREQuerySolarAzel>,
+) -> Result<String, (StatusCode, String)> {
     let p = Position::cadre();
-    let res = moontime::solar_azel(sl_mutex, t, p);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("cadre_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("cadre_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            let azel = moontime::translate_to(res, u);
-            Ok(moontime::format_as(azel, f, Some("cadre_sun_azel")))
-        }
-    }
+    let endpoint = format!("cadre/sun/{:?}", coord_format);
+    let surface = Surface::from_terrain(terrain);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = sun_azel(&worker, t, p, surface).await?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "cadre_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "cadre_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    let azel = moontime::translate_to(res, u);
+                    render(azel, f, "cadre_sun_azel")
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct SunEarthQuery {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
@@ -411,41 +854,55 @@ struct SunEarthQuery {
 }
 
 async fn get_sun_earth_full(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Query(SunEarthQuery { t, f, u }): Query<SunEarthQuery>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_from_sun(sl_mutex, t);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("earth_from_sun"));
-    Ok(res)
+) -> Result<String, (StatusCode, String)> {
+    let key = t.map(|t| CacheKey::new("sun/earth", t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_from_sun(t).await.map_err(worker_error)?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "earth_from_sun")
+        })
+        .await
 }
 
 async fn get_sun_earth(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
     Query(SunEarthQuery { t, f, u }): Query<SunEarthQuery>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_from_sun(sl_mutex, t);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("earth_from_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("earth_from_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            Err(())
-        }
-    }
+) -> Result<String, (StatusCode, String)> {
+    let endpoint = format!("sun/earth/{:?}", coord_format);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_from_sun(t).await.map_err(worker_error)?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "earth_from_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "earth_from_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    Err(unsupported_format())
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct SunEarthPost {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
@@ -453,41 +910,55 @@ struct SunEarthPost {
 }
 
 async fn post_sun_earth_full(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Json(SunEarthPost { t, f, u }): Json<SunEarthPost>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_from_sun(sl_mutex, t);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("earth_from_sun"));
-    Ok(res)
+) -> Result<String, (StatusCode, String)> {
+    let key = t.map(|t| CacheKey::new("sun/earth", t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_from_sun(t).await.map_err(worker_error)?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "earth_from_sun")
+        })
+        .await
 }
 
 async fn post_sun_earth(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
     Json(SunEarthPost { t, f, u }): Json<SunEarthPost>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_from_sun(sl_mutex, t);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("earth_from_sun_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("earth_from_sun_spherical")))
-        }
-        CoordFormat::Azel => {
-            Err(())
-        }
-    }
+) -> Result<String, (StatusCode, String)> {
+    let endpoint = format!("sun/earth/{:?}", coord_format);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_from_sun(t).await.map_err(worker_error)?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "earth_from_sun_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "earth_from_sun_spherical")
+                }
+                CoordFormat::Azel => {
+                    Err(unsupported_format())
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct EclipticEarthQuery {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
@@ -495,41 +966,55 @@ struct EclipticEarthQuery {
 }
 
 async fn get_ecliptic_earth_full(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Query(EclipticEarthQuery { t, f, u }): Query<EclipticEarthQuery>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_ecliptic(sl_mutex, t);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("earth_ecliptic"));
-    Ok(res)
+) -> Result<String, (StatusCode, String)> {
+    let key = t.map(|t| CacheKey::new("ecliptic/earth", t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_ecliptic(t).await.map_err(worker_error)?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "earth_ecliptic")
+        })
+        .await
 }
 
 async fn get_ecliptic_earth(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
     Query(EclipticEarthQuery { t, f, u }): Query<EclipticEarthQuery>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_ecliptic(sl_mutex, t);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("earth_ecliptic_xyz")))
-        }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("earth_ecliptic_spherical")))
-        }
-        CoordFormat::Azel => {
-            Err(())
-        }
-    }
+) -> Result<String, (StatusCode, String)> {
+    let endpoint = format!("ecliptic/earth/{:?}", coord_format);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_ecliptic(t).await.map_err(worker_error)?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "earth_ecliptic_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "earth_ecliptic_spherical")
+                }
+                CoordFormat::Azel => {
+                    Err(unsupported_format())
+                }
+            }
+        })
+        .await
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct EclipticEarthPost {
-    #[serde(with = "default_datetime_standard", default = "default_datetime")]
-    t: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
     #[serde(default = "default_format")]
     f: FormatSpecifier,
     #[serde(default = "default_degrees")]
@@ -537,34 +1022,2697 @@ struct EclipticEarthPost {
 }
 
 async fn post_ecliptic_earth_full(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Json(EclipticEarthPost { t, f, u }): Json<EclipticEarthPost>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_ecliptic(sl_mutex, t);
-    let res = moontime::translate_to(res, u);
-    let res = moontime::format_as(res, f, Some("earth_ecliptic"));
-    Ok(res)
+) -> Result<String, (StatusCode, String)> {
+    let key = t.map(|t| CacheKey::new("ecliptic/earth", t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_ecliptic(t).await.map_err(worker_error)?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "earth_ecliptic")
+        })
+        .await
 }
 
 async fn post_ecliptic_earth(
-    State(sl_mutex): State<Arc<Mutex<spice::SpiceLock>>>,
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
     Path(coord_format): Path<CoordFormat>,
     Json(EclipticEarthPost { t, f, u }): Json<EclipticEarthPost>,
-) -> Result<String, ()> {
-    let res = moontime::earth_position_ecliptic(sl_mutex, t);
-    match coord_format {
-        CoordFormat::Xyz => {
-            let xyz: PositionXYZ = res.into();
-            Ok(moontime::format_as(xyz, f, Some("earth_ecliptic_xyz")))
+) -> Result<String, (StatusCode, String)> {
+    let endpoint = format!("ecliptic/earth/{:?}", coord_format);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker.earth_position_ecliptic(t).await.map_err(worker_error)?;
+            match coord_format {
+                CoordFormat::Xyz => {
+                    let xyz: PositionXYZ = res.into();
+                    render(xyz, f, "earth_ecliptic_xyz")
+                }
+                CoordFormat::Spherical => {
+                    let spherical: PositionSpherical = res.into();
+                    let spherical = moontime::translate_to(spherical, u);
+                    render(spherical, f, "earth_ecliptic_spherical")
+                }
+                CoordFormat::Azel => {
+                    Err(unsupported_format())
+                }
+            }
+        })
+        .await
+}
+
+
+fn default_segment() -> f64 {
+    fit::DEFAULT_SEGMENT_S
+}
+
+fn default_degree() -> usize {
+    fit::DEFAULT_DEGREE
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FitRequest {
+    #[serde(with = "default_datetime_standard", default = "default_datetime")]
+    start: DateTime,
+    #[serde(with = "default_datetime_standard::option", default)]
+    end: Option<DateTime>,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    #[serde(default = "default_segment")]
+    segment: f64,
+    #[serde(default = "default_degree")]
+    degree: usize,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn build_fit(
+    worker: SpiceWorker,
+    fits: Arc<FitRegistry>,
+    req: FitRequest,
+) -> Result<String, (StatusCode, String)> {
+    let FitRequest {
+        start,
+        end,
+        lat,
+        lon,
+        alt,
+        segment,
+        degree,
+        f,
+        u,
+    } = req;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let end = end.unwrap_or(start + time::Duration::DAY);
+    let fit = worker
+        .run(move |sl| SiteFit::build(sl, p, start, end, segment, degree))
+        .await
+        .map_err(worker_error)?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (id, fit) = fits.insert(fit);
+    let res = moontime::translate_to(fit.info(id), u);
+    render(res, f, "fit")
+}
+
+async fn get_fit(
+    State(worker): State<SpiceWorker>,
+    State(fits): State<Arc<FitRegistry>>,
+    Query(req): Query<FitRequest>,
+) -> Result<String, (StatusCode, String)> {
+    build_fit(worker, fits, req).await
+}
+
+async fn post_fit(
+    State(worker): State<SpiceWorker>,
+    State(fits): State<Arc<FitRegistry>>,
+    Json(req): Json<FitRequest>,
+) -> Result<String, (StatusCode, String)> {
+    build_fit(worker, fits, req).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FitQuery {
+    #[serde(with = "default_datetime_standard", default = "default_datetime")]
+    t: DateTime,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+fn eval_fit(
+    fits: Arc<FitRegistry>,
+    id: u64,
+    FitQuery { t, f, u }: FitQuery,
+) -> Result<String, (StatusCode, String)> {
+    let fit = fits
+        .get(id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no fit {}", id)))?;
+    let res = fit.evaluate(t).ok_or((
+        StatusCode::BAD_REQUEST,
+        "t is outside the fit window".to_string(),
+    ))?;
+    let res = moontime::translate_to(res, u);
+    render(res, f, "fit")
+}
+
+async fn get_fit_eval(
+    State(fits): State<Arc<FitRegistry>>,
+    Path(id): Path<u64>,
+    Query(query): Query<FitQuery>,
+) -> Result<String, (StatusCode, String)> {
+    eval_fit(fits, id, query)
+}
+
+async fn post_fit_eval(
+    State(fits): State<Arc<FitRegistry>>,
+    Path(id): Path<u64>,
+    Json(query): Json<FitQuery>,
+) -> Result<String, (StatusCode, String)> {
+    eval_fit(fits, id, query)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SubPointQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_moon_frame")]
+    frame: MoonFrame,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn sub_point(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    target: &'static str,
+    SubPointQuery { t, frame, f, u }: SubPointQuery,
+) -> Result<String, (StatusCode, String)> {
+    let endpoint = format!("moon/{}/{:?}", target, frame);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = match target {
+                "subsolar" => worker.subsolar_point(t, frame).await,
+                _ => worker.sub_earth_point(t, frame).await,
+            };
+            let res = res
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, target)
+        })
+        .await
+}
+
+async fn get_subsolar(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<SubPointQuery>,
+) -> Result<String, (StatusCode, String)> {
+    sub_point(worker, cache, "subsolar", query).await
+}
+
+async fn post_subsolar(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<SubPointQuery>,
+) -> Result<String, (StatusCode, String)> {
+    sub_point(worker, cache, "subsolar", query).await
+}
+
+async fn get_sub_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<SubPointQuery>,
+) -> Result<String, (StatusCode, String)> {
+    sub_point(worker, cache, "subearth", query).await
+}
+
+async fn post_sub_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<SubPointQuery>,
+) -> Result<String, (StatusCode, String)> {
+    sub_point(worker, cache, "subearth", query).await
+}
+
+fn default_terminator_points() -> usize {
+    terminator::DEFAULT_POINTS
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TerminatorQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_moon_frame")]
+    frame: MoonFrame,
+    #[serde(default = "terminator::default_terminator_kind")]
+    kind: TerminatorKind,
+    #[serde(default = "default_terminator_points")]
+    n: usize,
+    #[serde(default = "terminator::default_terminator_shape")]
+    shape: TerminatorShape,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn moon_terminator(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: TerminatorQuery,
+) -> Result<String, (StatusCode, String)> {
+    let TerminatorQuery {
+        t,
+        frame,
+        kind,
+        n,
+        shape,
+        f,
+        u,
+    } = query;
+    if !(3..=terminator::MAX_POINTS).contains(&n) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("n must be between 3 and {}", terminator::MAX_POINTS),
+        ));
+    }
+    let endpoint = format!("moon/terminator/{:?}/{:?}/{}/{:?}", frame, kind, n, shape);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .terminator(t, frame, kind, n)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            if f == FormatSpecifier::Geojson {
+                return Ok(res.to_geojson(shape).to_string());
+            }
+            let res = moontime::translate_to(res, u);
+            render(res, f, "terminator")
+        })
+        .await
+}
+
+async fn get_terminator(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<TerminatorQuery>,
+) -> Result<String, (StatusCode, String)> {
+    moon_terminator(worker, cache, query).await
+}
+
+async fn post_terminator(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<TerminatorQuery>,
+) -> Result<String, (StatusCode, String)> {
+    moon_terminator(worker, cache, query).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TerminatorDistanceQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+}
+
+async fn terminator_distance(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: TerminatorDistanceQuery,
+) -> Result<String, (StatusCode, String)> {
+    let TerminatorDistanceQuery {
+        t,
+        f,
+        lat,
+        lon,
+        alt,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let key = t.map(|t| CacheKey::new("terminator/distance", t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .terminator_distance(t, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(res, f, "terminator_distance")
+        })
+        .await
+}
+
+async fn moon_get_terminator_distance(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<TerminatorDistanceQuery>,
+) -> Result<String, (StatusCode, String)> {
+    terminator_distance(worker, cache, query).await
+}
+
+async fn moon_post_terminator_distance(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<TerminatorDistanceQuery>,
+) -> Result<String, (StatusCode, String)> {
+    terminator_distance(worker, cache, query).await
+}
+
+async fn cadre_get_terminator_distance(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<TerminatorDistanceQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = TerminatorDistanceQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    terminator_distance(worker, cache, query).await
+}
+
+async fn cadre_post_terminator_distance(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<TerminatorDistanceQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = TerminatorDistanceQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    terminator_distance(worker, cache, query).await
+}
+
+fn default_observer() -> String {
+    "EARTH".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IlluminationQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// SPICE name or id of the observer; ignored when `olat`/`olon` are given.
+    #[serde(default = "default_observer")]
+    observer: String,
+    /// An observer at another site on the Moon, such as a mast camera, in
+    /// degrees; `oalt` is the camera height, km.
+    olat: Option<f64>,
+    olon: Option<f64>,
+    #[serde(default)]
+    oalt: f64,
+    #[serde(default)]
+    terrain: bool,
+}
+
+async fn illumination(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: IlluminationQuery,
+) -> Result<String, (StatusCode, String)> {
+    let IlluminationQuery {
+        t,
+        f,
+        u,
+        lat,
+        lon,
+        alt,
+        observer,
+        olat,
+        olon,
+        oalt,
+        terrain,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let observer = match (olat, olon) {
+        (Some(olat), Some(olon)) => {
+            Observer::Site(Position::new(olat, olon, oalt, UnitSpecifier::Degrees))
+        }
+        (None, None) => Observer::Body(observer.trim().to_uppercase()),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give both olat and olon for an observer site".to_string(),
+            ))
         }
-        CoordFormat::Spherical => {
-            let spherical: PositionSpherical = res.into();
-            let spherical = moontime::translate_to(spherical, u);
-            Ok(moontime::format_as(spherical, f, Some("earth_ecliptic_spherical")))
+    };
+    let surface = Surface::from_terrain(terrain);
+    let endpoint = format!("illumination/{}", observer);
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .position(p)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .illumination(t, p, observer, surface)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "illumination")
+        })
+        .await
+}
+
+async fn moon_get_illumination(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<IlluminationQuery>,
+) -> Result<String, (StatusCode, String)> {
+    illumination(worker, cache, query).await
+}
+
+async fn moon_post_illumination(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<IlluminationQuery>,
+) -> Result<String, (StatusCode, String)> {
+    illumination(worker, cache, query).await
+}
+
+async fn cadre_get_illumination(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<IlluminationQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = IlluminationQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    illumination(worker, cache, query).await
+}
+
+async fn cadre_post_illumination(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<IlluminationQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = IlluminationQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    illumination(worker, cache, query).await
+}
+
+fn default_power_step() -> f64 {
+    power::DEFAULT_STEP_S
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PowerQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the integration window; without it only the instant is returned.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    /// Integration step, seconds.
+    #[serde(default = "default_power_step")]
+    dt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// The panel, as separate fields so that GET queries can give it.
+    tilt: f64,
+    azimuth: f64,
+    area: f64,
+    /// Units of `tilt` and `azimuth`.
+    #[serde(default = "default_degrees")]
+    units: UnitSpecifier,
+    /// Id of a horizon mask for the site.
+    horizon: Option<u64>,
+}
+
+async fn panel_power(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    masks: Arc<MaskRegistry>,
+    query: PowerQuery,
+) -> Result<String, (StatusCode, String)> {
+    let PowerQuery {
+        t,
+        until,
+        dt,
+        f,
+        lat,
+        lon,
+        alt,
+        tilt,
+        azimuth,
+        area,
+        units,
+        horizon,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let panel = Panel {
+        tilt,
+        azimuth,
+        area,
+        units,
+    };
+    let mask = site_mask(&masks, horizon, p)?;
+    let tilt = panel.to_degrees().tilt;
+    if !(panel.area > 0.0 && (0.0..=180.0).contains(&tilt)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "panel needs a positive area and a tilt of 0 to 180 degrees".to_string(),
+        ));
+    }
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    if let Some(until) = until {
+        let window = (until - start).as_seconds_f64();
+        if window <= 0.0 {
+            return Err((StatusCode::BAD_REQUEST, "until must be after t".to_string()));
         }
-        CoordFormat::Azel => {
-            Err(())
+        if window / dt > power::MAX_SAMPLES as f64 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("window needs more than {} steps of dt", power::MAX_SAMPLES),
+            ));
         }
     }
+
+    let panel_key = panel.to_radians();
+    let endpoint = format!(
+        "power/{}/{}/{}/{:?}/{}/{:?}",
+        panel_key.tilt,
+        panel_key.azimuth,
+        panel.area,
+        until.map(|u| u.unix_timestamp_nanos()),
+        dt,
+        horizon
+    );
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).format(f));
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .panel_power(start, p, panel, until, dt, mask)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(res, f, "power")
+        })
+        .await
+}
+
+async fn moon_get_power(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Query(query): Query<PowerQuery>,
+) -> Result<String, (StatusCode, String)> {
+    panel_power(worker, cache, masks, query).await
+}
+
+async fn moon_post_power(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Json(query): Json<PowerQuery>,
+) -> Result<String, (StatusCode, String)> {
+    panel_power(worker, cache, masks, query).await
+}
+
+async fn cadre_get_power(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Query(query): Query<PowerQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = PowerQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    panel_power(worker, cache, masks, query).await
+}
+
+async fn cadre_post_power(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Json(query): Json<PowerQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = PowerQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    panel_power(worker, cache, masks, query).await
+}
+
+/// Heights as a JSON list, or comma separated as a query string has them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Heights {
+    List(Vec<f64>),
+    One(f64),
+    Text(String),
+}
+
+fn heights<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+    match Heights::deserialize(deserializer)? {
+        Heights::List(h) => Ok(h),
+        Heights::One(h) => Ok(vec![h]),
+        Heights::Text(text) => text
+            .split(',')
+            .map(|h| {
+                h.trim()
+                    .parse()
+                    .map_err(|_| serde::de::Error::custom(format!("bad height '{}'", h)))
+            })
+            .collect(),
+    }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct ShadowQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// Object heights; shadow lengths come back in the same unit.
+    #[serde(deserialize_with = "heights")]
+    h: Vec<f64>,
+    /// Find when each shadow gets shorter than this.
+    below: Option<f64>,
+    /// Id of a horizon mask for the site.
+    horizon: Option<u64>,
+}
+
+async fn shadow(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    masks: Arc<MaskRegistry>,
+    query: ShadowQuery,
+) -> Result<String, (StatusCode, String)> {
+    let ShadowQuery {
+        t,
+        f,
+        u,
+        lat,
+        lon,
+        alt,
+        h,
+        below,
+        horizon,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let mask = site_mask(&masks, horizon, p)?;
+    if h.is_empty() || h.len() > shadow::MAX_OBJECTS || h.iter().any(|h| h.is_nan() || *h < 0.0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("h must be 1 to {} non-negative heights", shadow::MAX_OBJECTS),
+        ));
+    }
+    if below.is_some_and(|below| below.is_nan() || below <= 0.0) {
+        return Err((StatusCode::BAD_REQUEST, "below must be positive".to_string()));
+    }
+    let endpoint = format!("shadow/{:?}/{:?}/{:?}", h, below, horizon);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .shadows(t, p, h, below, mask)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "shadow")
+        })
+        .await
+}
+
+async fn moon_get_shadow(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Query(query): Query<ShadowQuery>,
+) -> Result<String, (StatusCode, String)> {
+    shadow(worker, cache, masks, query).await
+}
+
+async fn moon_post_shadow(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Json(query): Json<ShadowQuery>,
+) -> Result<String, (StatusCode, String)> {
+    shadow(worker, cache, masks, query).await
+}
+
+async fn cadre_get_shadow(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Query(query): Query<ShadowQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = ShadowQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    shadow(worker, cache, masks, query).await
+}
+
+async fn cadre_post_shadow(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Json(query): Json<ShadowQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = ShadowQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    shadow(worker, cache, masks, query).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EclipseQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the search; a year after t by default.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+}
+
+async fn eclipses(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: EclipseQuery,
+) -> Result<String, (StatusCode, String)> {
+    let EclipseQuery {
+        t,
+        until,
+        f,
+        lat,
+        lon,
+        alt,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let start = t.unwrap_or_else(default_datetime);
+    let until = until.unwrap_or(start + time::Duration::days(eclipse::DEFAULT_SPAN_DAYS));
+    if until <= start {
+        return Err((StatusCode::BAD_REQUEST, "until must be after t".to_string()));
+    }
+    if until - start > time::Duration::days(eclipse::MAX_SPAN_DAYS) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("search at most {} days at once", eclipse::MAX_SPAN_DAYS),
+        ));
+    }
+    let endpoint = format!("eclipses/{}", until.unix_timestamp_nanos());
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).format(f));
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .eclipses(start, until, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            render(res, f, "eclipses")
+        })
+        .await
+}
+
+async fn moon_get_eclipses(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EclipseQuery>,
+) -> Result<String, (StatusCode, String)> {
+    eclipses(worker, cache, query).await
+}
+
+async fn moon_post_eclipses(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EclipseQuery>,
+) -> Result<String, (StatusCode, String)> {
+    eclipses(worker, cache, query).await
+}
+
+async fn cadre_get_eclipses(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EclipseQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EclipseQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    eclipses(worker, cache, query).await
+}
+
+async fn cadre_post_eclipses(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EclipseQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EclipseQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    eclipses(worker, cache, query).await
+}
+
+fn default_map_step() -> f64 {
+    maps::DEFAULT_STEP_S
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MapQuery {
+    /// The region, as separate fields so that GET queries can give it.
+    lat_min: f64,
+    lat_max: f64,
+    lon_min: f64,
+    lon_max: f64,
+    spacing: f64,
+    /// Units of the region's angles.
+    #[serde(default = "default_degrees")]
+    units: UnitSpecifier,
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the window.
+    #[serde(with = "default_datetime_standard")]
+    until: DateTime,
+    /// Sampling step, seconds.
+    #[serde(default = "default_map_step")]
+    dt: f64,
+    #[serde(default)]
+    terrain: bool,
+    /// Percentage shown in the raster.
+    #[serde(default)]
+    layer: MapLayer,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+/// Runs a map `per_job` cells at a time, keeping every pool process busy,
+/// or one job at a time on the local worker.
+async fn map_rows(
+    worker: &SpiceWorker,
+    rows: Vec<Vec<Position>>,
+    per_job: usize,
+    t: DateTime,
+    until: DateTime,
+    dt: f64,
+    surface: Surface,
+) -> Result<Vec<Vec<MapCell>>, (StatusCode, String)> {
+    let parallel = worker.status().processes.max(1);
+    // each job's sites, and the row they belong to
+    let (owners, parts): (Vec<usize>, Vec<Vec<Position>>) = rows
+        .iter()
+        .enumerate()
+        .flat_map(|(row, sites)| sites.chunks(per_job).map(move |c| (row, c.to_vec())))
+        .unzip();
+    let mut done = vec![Vec::new(); parts.len()];
+    let mut jobs = tokio::task::JoinSet::new();
+    let mut pending = parts.into_iter().enumerate();
+    loop {
+        while jobs.len() < parallel {
+            let Some((i, sites)) = pending.next() else {
+                break;
+            };
+            let worker = worker.clone();
+            jobs.spawn(async move { (i, worker.map_cells(sites, t, until, dt, surface).await) });
+        }
+        let Some(joined) = jobs.join_next().await else {
+            break;
+        };
+        let (i, part) = joined.map_err(|_| worker_error(WorkerError::Failed))?;
+        done[i] = part
+            .map_err(worker_error)?
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    // parts are in row order, so each row is rebuilt west to east
+    let mut cells = vec![Vec::new(); rows.len()];
+    for (row, part) in owners.into_iter().zip(done) {
+        cells[row].extend(part);
+    }
+    Ok(cells)
+}
+
+async fn illumination_map(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: MapQuery,
+) -> Result<String, (StatusCode, String)> {
+    let MapQuery {
+        lat_min,
+        lat_max,
+        lon_min,
+        lon_max,
+        spacing,
+        units,
+        t,
+        until,
+        dt,
+        terrain,
+        layer,
+        f,
+        u,
+    } = query;
+    let region = Region {
+        lat_min,
+        lat_max,
+        lon_min,
+        lon_max,
+        spacing,
+        units,
+    };
+    region.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    check_format(f, &[FormatSpecifier::Csv, FormatSpecifier::Pgm])?;
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    let window = (until - start).as_seconds_f64();
+    if window < 0.0 {
+        return Err((StatusCode::BAD_REQUEST, "until must not be before t".to_string()));
+    }
+    let cells = region.cells();
+    let samples = maps::samples(0.0, window, dt);
+    if cells * samples > maps::MAX_EVALUATIONS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "cells times samples exceeds {}; use a coarser grid or larger dt",
+                maps::MAX_EVALUATIONS
+            ),
+        ));
+    }
+    if terrain && cells > maps::MAX_TERRAIN_CELLS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("terrain maps allow at most {} cells", maps::MAX_TERRAIN_CELLS),
+        ));
+    }
+    let surface = Surface::from_terrain(terrain);
+    let Some(per_job) = maps::cells_per_job(samples, surface) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "too many samples per cell for one job; use a larger dt or a shorter window"
+                .to_string(),
+        ));
+    };
+
+    let degrees = region.to_degrees();
+    let endpoint = format!(
+        "moon/map/{}/{}/{}/{}/{}/{}/{}/{}",
+        degrees.lat_min,
+        degrees.lat_max,
+        degrees.lon_min,
+        degrees.lon_max,
+        degrees.spacing,
+        until.unix_timestamp_nanos(),
+        dt,
+        layer
+    );
+    let key = t.map(|t| {
+        CacheKey::new(&endpoint, t)
+            .units(u)
+            .format(f)
+            .surface(surface)
+    });
+    cache
+        .get_or_compute(key, || async move {
+            let rows = degrees
+                .lats()
+                .into_iter()
+                .map(|lat| {
+                    degrees
+                        .lons()
+                        .into_iter()
+                        .map(|lon| Position::new(lat, lon, 0.0, UnitSpecifier::Degrees))
+                        .collect()
+                })
+                .collect();
+            let cells = map_rows(&worker, rows, per_job, start, until, dt, surface).await?;
+            let map = IlluminationMap {
+                region: degrees,
+                lat: degrees.lats(),
+                lon: degrees.lons(),
+                t: start,
+                until,
+                step: dt,
+                samples,
+                surface,
+                cells,
+                units: UnitSpecifier::Degrees,
+            };
+            let map = moontime::translate_to(map, u);
+            Ok(match f {
+                FormatSpecifier::Csv => map.to_csv(),
+                FormatSpecifier::Pgm => map.to_pgm(layer),
+                f => render(map, f, "map")?,
+            })
+        })
+        .await
+}
+
+async fn get_map(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<MapQuery>,
+) -> Result<String, (StatusCode, String)> {
+    illumination_map(worker, cache, query).await
+}
+
+async fn post_map(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<MapQuery>,
+) -> Result<String, (StatusCode, String)> {
+    illumination_map(worker, cache, query).await
+}
+
+/// The mask `id` for the site at `p`, if one was asked for.
+fn site_mask(
+    masks: &MaskRegistry,
+    id: Option<u64>,
+    p: Position,
+) -> Result<Option<HorizonMask>, (StatusCode, String)> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    let mask = masks
+        .get(id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no horizon mask {}", id)))?;
+    if !mask.is_for(p) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("horizon mask {} belongs to another site", id),
+        ));
+    }
+    Ok(Some((*mask).clone()))
+}
+
+fn default_az_step() -> f64 {
+    horizon::DEFAULT_AZ_STEP
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct HorizonRequest {
+    #[serde(default)]
+    p: Position,
+    /// `az,el` lines in degrees; without it the mask is traced from the DSK.
+    csv: Option<String>,
+    /// Azimuth step for DSK masks, degrees.
+    #[serde(default = "default_az_step")]
+    step: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn build_horizon(
+    worker: SpiceWorker,
+    masks: Arc<MaskRegistry>,
+    req: HorizonRequest,
+) -> Result<String, (StatusCode, String)> {
+    let HorizonRequest { p, csv, step, f, u } = req;
+    on_moon(&p)?;
+    let mask = match csv {
+        Some(csv) => HorizonMask::from_csv(p, &csv),
+        None => {
+            if !(horizon::MIN_AZ_STEP..=horizon::MAX_AZ_STEP).contains(&step) {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "step must be between {} and {} degrees",
+                        horizon::MIN_AZ_STEP,
+                        horizon::MAX_AZ_STEP
+                    ),
+                ));
+            }
+            worker
+                .run(move |sl| horizon::from_dsk(sl, p, step))
+                .await
+                .map_err(worker_error)?
+        }
+    }
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let (id, mask) = masks.insert(mask);
+    let res = moontime::translate_to(mask.info(id), u);
+    render(res, f, "horizon")
+}
+
+async fn post_horizon_new(
+    State(worker): State<SpiceWorker>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Json(req): Json<HorizonRequest>,
+) -> Result<String, (StatusCode, String)> {
+    build_horizon(worker, masks, req).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct HorizonQuery {
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+fn horizon_mask(
+    masks: Arc<MaskRegistry>,
+    id: u64,
+    HorizonQuery { f, u }: HorizonQuery,
+) -> Result<String, (StatusCode, String)> {
+    let mask = masks
+        .get(id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no horizon mask {}", id)))?;
+    if f == FormatSpecifier::Svg {
+        return Ok(mask.to_svg());
+    }
+    let res = moontime::translate_to((*mask).clone(), u);
+    render(res, f, "horizon")
+}
+
+async fn get_horizon(
+    State(masks): State<Arc<MaskRegistry>>,
+    Path(id): Path<u64>,
+    Query(query): Query<HorizonQuery>,
+) -> Result<String, (StatusCode, String)> {
+    horizon_mask(masks, id, query)
+}
+
+async fn post_horizon(
+    State(masks): State<Arc<MaskRegistry>>,
+    Path(id): Path<u64>,
+    Json(query): Json<HorizonQuery>,
+) -> Result<String, (StatusCode, String)> {
+    horizon_mask(masks, id, query)
+}
+
+fn default_sun() -> String {
+    "SUN".to_string()
+}
+
+fn default_visibility_step() -> f64 {
+    horizon::DEFAULT_STEP_S
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct VisibilityQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the event search; without it only the current state is returned.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    /// Search step, seconds.
+    #[serde(default = "default_visibility_step")]
+    dt: f64,
+    /// SUN or EARTH.
+    #[serde(default = "default_sun")]
+    target: String,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn horizon_visibility(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    masks: Arc<MaskRegistry>,
+    id: u64,
+    query: VisibilityQuery,
+) -> Result<String, (StatusCode, String)> {
+    let VisibilityQuery {
+        t,
+        until,
+        dt,
+        target,
+        f,
+        u,
+    } = query;
+    let mask = masks
+        .get(id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no horizon mask {}", id)))?;
+    let target = target.trim().to_uppercase();
+    if target != "SUN" && target != "EARTH" {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "target must be SUN or EARTH".to_string(),
+        ));
+    }
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    if let Some(until) = until {
+        let window = (until - start).as_seconds_f64();
+        if window <= 0.0 {
+            return Err((StatusCode::BAD_REQUEST, "until must be after t".to_string()));
+        }
+        if window / dt > horizon::MAX_SAMPLES as f64 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("window needs more than {} steps of dt", horizon::MAX_SAMPLES),
+            ));
+        }
+    }
+
+    let endpoint = format!(
+        "horizon/{}/visibility/{}/{:?}/{}",
+        id,
+        target,
+        until.map(|u| u.unix_timestamp_nanos()),
+        dt
+    );
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .visibility(start, (*mask).clone(), &target, until, dt)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "visibility")
+        })
+        .await
+}
+
+async fn get_horizon_visibility(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Path(id): Path<u64>,
+    Query(query): Query<VisibilityQuery>,
+) -> Result<String, (StatusCode, String)> {
+    horizon_visibility(worker, cache, masks, id, query).await
+}
+
+async fn post_horizon_visibility(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(masks): State<Arc<MaskRegistry>>,
+    Path(id): Path<u64>,
+    Json(query): Json<VisibilityQuery>,
+) -> Result<String, (StatusCode, String)> {
+    horizon_visibility(worker, cache, masks, id, query).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EarthViewQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+}
+
+async fn earth_view(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: EarthViewQuery,
+) -> Result<String, (StatusCode, String)> {
+    let EarthViewQuery {
+        t,
+        f,
+        u,
+        lat,
+        lon,
+        alt,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let key = t.map(|t| CacheKey::new("earth", t).position(p).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .earth_view(t, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "earth")
+        })
+        .await
+}
+
+async fn moon_get_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EarthViewQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_view(worker, cache, query).await
+}
+
+async fn moon_post_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EarthViewQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_view(worker, cache, query).await
+}
+
+async fn cadre_get_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EarthViewQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EarthViewQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    earth_view(worker, cache, query).await
+}
+
+async fn cadre_post_earth(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EarthViewQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EarthViewQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    earth_view(worker, cache, query).await
+}
+
+fn default_track_step() -> f64 {
+    earth::DEFAULT_TRACK_STEP_S
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EarthTrackQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the track; four weeks after t by default.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    /// Step between points, seconds.
+    #[serde(default = "default_track_step")]
+    dt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+}
+
+async fn earth_track(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: EarthTrackQuery,
+) -> Result<String, (StatusCode, String)> {
+    let EarthTrackQuery {
+        t,
+        until,
+        dt,
+        f,
+        u,
+        lat,
+        lon,
+        alt,
+    } = query;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    check_format(f, &[FormatSpecifier::Csv, FormatSpecifier::Svg])?;
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    let until = until.unwrap_or(start + time::Duration::days(earth::DEFAULT_TRACK_DAYS));
+    let window = (until - start).as_seconds_f64();
+    if window < 0.0 {
+        return Err((StatusCode::BAD_REQUEST, "until must not be before t".to_string()));
+    }
+    if window / dt >= earth::MAX_TRACK_POINTS as f64 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("track would have more than {} points", earth::MAX_TRACK_POINTS),
+        ));
+    }
+    let endpoint = format!("earth/track/{}/{}", until.unix_timestamp_nanos(), dt);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).units(u).format(f));
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .earth_track(start, until, dt, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            Ok(match f {
+                FormatSpecifier::Csv => res.to_csv(),
+                FormatSpecifier::Svg => res.to_svg(),
+                f => render(res, f, "track")?,
+            })
+        })
+        .await
+}
+
+async fn moon_get_earth_track(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EarthTrackQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_track(worker, cache, query).await
+}
+
+async fn moon_post_earth_track(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EarthTrackQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_track(worker, cache, query).await
+}
+
+async fn cadre_get_earth_track(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EarthTrackQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EarthTrackQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    earth_track(worker, cache, query).await
+}
+
+async fn cadre_post_earth_track(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EarthTrackQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = EarthTrackQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    earth_track(worker, cache, query).await
+}
+
+fn default_dsn_step() -> f64 {
+    dsn::DEFAULT_STEP_S
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Stations as JSON, or `;` separated as a query string has them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stations {
+    List(Vec<Station>),
+    One(Station),
+    Text(String),
+}
+
+fn stations<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<Station>, D::Error> {
+    match Stations::deserialize(deserializer)? {
+        Stations::List(stations) => Ok(stations),
+        Stations::One(station) => Ok(vec![station]),
+        Stations::Text(text) => text
+            .split(';')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.parse().map_err(serde::de::Error::custom))
+            .collect(),
+    }
+}
+
+fn station<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Station>, D::Error> {
+    let mut stations = stations(deserializer)?;
+    if stations.len() > 1 {
+        return Err(serde::de::Error::custom("give one station"));
+    }
+    Ok(stations.pop())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DsnQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the search; a day after t by default.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    /// Search step, seconds.
+    #[serde(default = "default_dsn_step")]
+    dt: f64,
+    /// A lunar site in degrees; the Moon's center without one.
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// Include Goldstone, Madrid and Canberra.
+    #[serde(default = "default_true")]
+    dsn: bool,
+    /// Extra stations.
+    #[serde(default, deserialize_with = "stations")]
+    stations: Vec<Station>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn dsn_visibility(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: DsnQuery,
+) -> Result<String, (StatusCode, String)> {
+    let DsnQuery {
+        t,
+        until,
+        dt,
+        lat,
+        lon,
+        alt,
+        dsn,
+        stations,
+        f,
+        u,
+    } = query;
+    let p = match (lat, lon) {
+        (Some(lat), Some(lon)) => Some(Position::new(lat, lon, alt, UnitSpecifier::Degrees)),
+        (None, None) => None,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give both lat and lon for a site".to_string(),
+            ))
+        }
+    };
+    if stations.len() > dsn::MAX_STATIONS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("at most {} stations", dsn::MAX_STATIONS),
+        ));
+    }
+    for station in &stations {
+        station.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    let stations: Vec<Station> = dsn
+        .then(Station::dsn_complexes)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(stations)
+        .collect();
+    if stations.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "no stations".to_string()));
+    }
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    let until = until.unwrap_or(start + time::Duration::days(dsn::DEFAULT_SPAN_DAYS));
+    let window = (until - start).as_seconds_f64();
+    if window <= 0.0 {
+        return Err((StatusCode::BAD_REQUEST, "until must be after t".to_string()));
+    }
+    if window / dt > dsn::MAX_SAMPLES as f64 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("window needs more than {} steps of dt", dsn::MAX_SAMPLES),
+        ));
+    }
+
+    let station_key: Vec<String> = stations
+        .iter()
+        .map(|s| {
+            let s = s.to_degrees();
+            format!("{}:{}:{}:{}:{}", s.name, s.lat, s.lon, s.alt, s.mask)
+        })
+        .collect();
+    let endpoint = format!(
+        "dsn/{}/{}/{:?}",
+        until.unix_timestamp_nanos(),
+        dt,
+        station_key
+    );
+    let key = t.map(|t| {
+        let key = CacheKey::new(&endpoint, t).units(u).format(f);
+        match p {
+            Some(p) => key.position(p),
+            None => key,
+        }
+    });
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .dsn(start, until, dt, p, stations)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "dsn")
+        })
+        .await
+}
+
+async fn moon_get_dsn(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<DsnQuery>,
+) -> Result<String, (StatusCode, String)> {
+    dsn_visibility(worker, cache, query).await
+}
+
+async fn moon_post_dsn(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<DsnQuery>,
+) -> Result<String, (StatusCode, String)> {
+    dsn_visibility(worker, cache, query).await
+}
+
+async fn cadre_get_dsn(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<DsnQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = DsnQuery {
+        lat: Some(default_lat()),
+        lon: Some(default_lon()),
+        alt: default_alt(),
+        ..query
+    };
+    dsn_visibility(worker, cache, query).await
+}
+
+async fn cadre_post_dsn(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<DsnQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = DsnQuery {
+        lat: Some(default_lat()),
+        lon: Some(default_lon()),
+        alt: default_alt(),
+        ..query
+    };
+    dsn_visibility(worker, cache, query).await
+}
+
+fn default_station() -> String {
+    "GOLDSTONE".to_string()
+}
+
+fn default_frequency() -> f64 {
+    dsn::DEFAULT_FREQUENCY_HZ
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CommsQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// A DSN complex; ignored when `s` is given.
+    #[serde(default = "default_station")]
+    station: String,
+    /// A user-defined station.
+    #[serde(default, deserialize_with = "station")]
+    s: Option<Station>,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// SPICE name or id of a spacecraft; overrides the site.
+    target: Option<String>,
+    /// Carrier frequency, Hz.
+    #[serde(default = "default_frequency")]
+    freq: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn comms(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: CommsQuery,
+) -> Result<String, (StatusCode, String)> {
+    let CommsQuery {
+        t,
+        station,
+        s,
+        lat,
+        lon,
+        alt,
+        target,
+        freq,
+        f,
+        u,
+    } = query;
+    let station = match s {
+        Some(s) => {
+            s.check().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            s
+        }
+        None => Station::named(&station).ok_or((
+            StatusCode::BAD_REQUEST,
+            "station must be GOLDSTONE, MADRID or CANBERRA, or give s".to_string(),
+        ))?,
+    };
+    if !(freq > 0.0 && freq.is_finite()) {
+        return Err((StatusCode::BAD_REQUEST, "freq must be positive".to_string()));
+    }
+    let target = match target {
+        Some(name) => CommsTarget::Body(name.trim().to_uppercase()),
+        None => CommsTarget::Site(Position::new(lat, lon, alt, UnitSpecifier::Degrees)),
+    };
+
+    let s = station.to_degrees();
+    let endpoint = format!(
+        "comms/{}:{}:{}:{}/{}/{}",
+        s.name, s.lat, s.lon, s.alt, target, freq
+    );
+    let key = t.map(|t| CacheKey::new(&endpoint, t).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .comms(t, station, target, freq)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "comms")
+        })
+        .await
+}
+
+async fn moon_get_comms(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<CommsQuery>,
+) -> Result<String, (StatusCode, String)> {
+    comms(worker, cache, query).await
+}
+
+async fn moon_post_comms(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<CommsQuery>,
+) -> Result<String, (StatusCode, String)> {
+    comms(worker, cache, query).await
+}
+
+async fn cadre_get_comms(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<CommsQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = CommsQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        target: None,
+        ..query
+    };
+    comms(worker, cache, query).await
+}
+
+async fn cadre_post_comms(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<CommsQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = CommsQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        target: None,
+        ..query
+    };
+    comms(worker, cache, query).await
+}
+
+/// The observer on Earth at geodetic `olat`, `olon` (degrees) and `oalt` km.
+fn on_earth(olat: f64, olon: f64, oalt: f64) -> Result<Position, (StatusCode, String)> {
+    if !(-90.0..=90.0).contains(&olat) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "olat must be within -90 to 90 degrees".to_string(),
+        ));
+    }
+    Ok(Position::new(olat, olon, oalt, UnitSpecifier::Degrees).on(Body::Earth))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EarthMoonQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// Observer on Earth, in degrees: a query string can't carry a nested `o`.
+    olat: f64,
+    olon: f64,
+    #[serde(default)]
+    oalt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn earth_moon(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: EarthMoonQuery,
+) -> Result<String, (StatusCode, String)> {
+    let EarthMoonQuery {
+        t,
+        olat,
+        olon,
+        oalt,
+        f,
+        u,
+    } = query;
+    let o = on_earth(olat, olon, oalt)?;
+    let key = t.map(|t| CacheKey::new("earth/moon", t).position(o).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .moon_sky(t, o)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "moon")
+        })
+        .await
+}
+
+async fn earth_get_moon(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<EarthMoonQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_moon(worker, cache, query).await
+}
+
+async fn earth_post_moon(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<EarthMoonQuery>,
+) -> Result<String, (StatusCode, String)> {
+    earth_moon(worker, cache, query).await
+}
+
+fn default_rise_set_step() -> f64 {
+    observing::DEFAULT_STEP_S
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MoonRiseSetQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// End of the search; a day after t by default.
+    #[serde(with = "default_datetime_standard::option", default)]
+    until: Option<DateTime>,
+    /// Search step, seconds.
+    #[serde(default = "default_rise_set_step")]
+    dt: f64,
+    /// Observer on Earth, in degrees: a query string can't carry a nested `o`.
+    olat: f64,
+    olon: f64,
+    #[serde(default)]
+    oalt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn moon_rise_set(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: MoonRiseSetQuery,
+) -> Result<String, (StatusCode, String)> {
+    let MoonRiseSetQuery {
+        t,
+        until,
+        dt,
+        olat,
+        olon,
+        oalt,
+        f,
+        u,
+    } = query;
+    let o = on_earth(olat, olon, oalt)?;
+    if !(dt > 0.0 && dt.is_finite()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        ));
+    }
+    let start = t.unwrap_or_else(default_datetime);
+    let until = until.unwrap_or(start + time::Duration::days(observing::DEFAULT_SPAN_DAYS));
+    if until <= start {
+        return Err((StatusCode::BAD_REQUEST, "until must be after t".to_string()));
+    }
+    if (until - start).as_seconds_f64() / dt > observing::MAX_SAMPLES as f64 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("at most {} samples; raise dt or shorten the window", observing::MAX_SAMPLES),
+        ));
+    }
+    let endpoint = format!("earth/moon/riseset/{}/{}", until.unix_timestamp_nanos(), dt);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(o).units(u).format(f));
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .moon_rise_set(start, until, dt, o)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "riseset")
+        })
+        .await
+}
+
+async fn earth_get_moon_rise_set(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<MoonRiseSetQuery>,
+) -> Result<String, (StatusCode, String)> {
+    moon_rise_set(worker, cache, query).await
+}
+
+async fn earth_post_moon_rise_set(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<MoonRiseSetQuery>,
+) -> Result<String, (StatusCode, String)> {
+    moon_rise_set(worker, cache, query).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SiteFromEarthQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// Observer on Earth, in degrees: a query string can't carry a nested `o`.
+    olat: f64,
+    olon: f64,
+    #[serde(default)]
+    oalt: f64,
+    /// Lunar site, in degrees.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn site_from_earth(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    query: SiteFromEarthQuery,
+) -> Result<String, (StatusCode, String)> {
+    let SiteFromEarthQuery {
+        t,
+        olat,
+        olon,
+        oalt,
+        lat,
+        lon,
+        alt,
+        f,
+        u,
+    } = query;
+    let o = on_earth(olat, olon, oalt)?;
+    let p = Position::new(lat, lon, alt, UnitSpecifier::Degrees);
+    let s = p.to_radians();
+    let endpoint = format!("earth/site/{}:{}:{}", s.lat, s.lon, s.alt);
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(o).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .site_from_earth(t, o, p)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "site")
+        })
+        .await
+}
+
+async fn earth_get_site(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<SiteFromEarthQuery>,
+) -> Result<String, (StatusCode, String)> {
+    site_from_earth(worker, cache, query).await
+}
+
+async fn earth_post_site(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<SiteFromEarthQuery>,
+) -> Result<String, (StatusCode, String)> {
+    site_from_earth(worker, cache, query).await
+}
+
+async fn earth_get_cadre(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Query(query): Query<SiteFromEarthQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = SiteFromEarthQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    site_from_earth(worker, cache, query).await
+}
+
+async fn earth_post_cadre(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    Json(query): Json<SiteFromEarthQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = SiteFromEarthQuery {
+        lat: default_lat(),
+        lon: default_lon(),
+        alt: default_alt(),
+        ..query
+    };
+    site_from_earth(worker, cache, query).await
+}
+
+async fn get_missions(
+    State(missions): State<Arc<MissionRegistry>>,
+    Query(StatusQuery { f }): Query<StatusQuery>,
+) -> Result<String, (StatusCode, String)> {
+    render(&*missions, f, "missions")
+}
+
+/// The registered mission `name`, which must have landed on `body`.
+fn mission_on(
+    missions: &MissionRegistry,
+    name: Option<String>,
+    body: Body,
+) -> Result<Option<Mission>, (StatusCode, String)> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    let mission = missions
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("no mission named {}", name)))?;
+    if mission.site.body != body {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is on {}, not {}", mission.name, mission.site.body, body),
+        ));
+    }
+    Ok(Some(mission))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MarsTimeQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// Site on Mars in degrees; the mission's landing site by default.
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// Mission to count sols for, by name.
+    mission: Option<String>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn mars_time(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    missions: Arc<MissionRegistry>,
+    query: MarsTimeQuery,
+) -> Result<String, (StatusCode, String)> {
+    let MarsTimeQuery {
+        t,
+        lat,
+        lon,
+        alt,
+        mission,
+        f,
+        u,
+    } = query;
+    let mission = mission_on(&missions, mission, Body::Mars)?;
+    let p = match (lat, lon, &mission) {
+        (Some(lat), Some(lon), _) => {
+            Position::new(lat, lon, alt, UnitSpecifier::Degrees).on(Body::Mars)
+        }
+        (None, None, Some(m)) => m.site,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give a site lat and lon, or a mission".to_string(),
+            ))
+        }
+    };
+    let endpoint = format!(
+        "mars/time/{}",
+        mission.as_ref().map_or("", |m| m.name.as_str())
+    );
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).units(u).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .mars_time(t, p, mission)
+                .await
+                .map_err(worker_error)?
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            let res = moontime::translate_to(res, u);
+            render(res, f, "mars")
+        })
+        .await
+}
+
+async fn mars_get_time(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Query(query): Query<MarsTimeQuery>,
+) -> Result<String, (StatusCode, String)> {
+    mars_time(worker, cache, missions, query).await
+}
+
+async fn mars_post_time(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Json(query): Json<MarsTimeQuery>,
+) -> Result<String, (StatusCode, String)> {
+    mars_time(worker, cache, missions, query).await
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ClockQuery {
+    #[serde(with = "default_datetime_standard::option", default)]
+    t: Option<DateTime>,
+    /// Site in degrees; the mission's landing site, or CADRE on the moon,
+    /// by default.
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    /// Mission to count from, by name.
+    mission: Option<String>,
+    /// Which of the mission's epochs to count from.
+    #[serde(default)]
+    since: Since,
+    /// Epoch to count from instead of the mission's.
+    #[serde(with = "default_datetime_standard::option", default)]
+    epoch: Option<DateTime>,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+}
+
+async fn mission_clock(
+    worker: SpiceWorker,
+    cache: Arc<ResponseCache>,
+    missions: Arc<MissionRegistry>,
+    body: Body,
+    query: ClockQuery,
+) -> Result<String, (StatusCode, String)> {
+    let ClockQuery {
+        t,
+        lat,
+        lon,
+        alt,
+        mission,
+        since,
+        epoch,
+        f,
+    } = query;
+    let mission = mission_on(&missions, mission, body)?;
+    let p = match (lat, lon, &mission) {
+        (Some(lat), Some(lon), _) => Position::new(lat, lon, alt, UnitSpecifier::Degrees).on(body),
+        (None, None, Some(m)) => m.site,
+        (None, None, None) if body == Body::Moon => Position::default(),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give a site lat and lon, or a mission".to_string(),
+            ))
+        }
+    };
+    let epoch = match (epoch, &mission) {
+        (Some(epoch), _) => Some(epoch),
+        (None, Some(m)) => Some(m.epoch(since).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("{} has no {} time", m.name, since),
+            )
+        })?),
+        (None, None) => None,
+    };
+    let endpoint = format!(
+        "{}/clock/{}/{}/{}",
+        body,
+        mission.as_ref().map_or("", |m| m.name.as_str()),
+        since,
+        epoch.map_or(String::new(), |e| e.unix_timestamp_nanos().to_string())
+    );
+    let key = t.map(|t| CacheKey::new(&endpoint, t).position(p).format(f));
+    let t = t.unwrap_or_else(default_datetime);
+    cache
+        .get_or_compute(key, || async move {
+            let res = worker
+                .mission_clock(t, p, since, epoch, mission)
+                .await
+                .map_err(worker_error)?;
+            render(res, f, "clock")
+        })
+        .await
+}
+
+async fn body_get_clock(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Path(body): Path<Body>,
+    Query(query): Query<ClockQuery>,
+) -> Result<String, (StatusCode, String)> {
+    mission_clock(worker, cache, missions, body, query).await
+}
+
+async fn body_post_clock(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Path(body): Path<Body>,
+    Json(query): Json<ClockQuery>,
+) -> Result<String, (StatusCode, String)> {
+    mission_clock(worker, cache, missions, body, query).await
+}
+
+/// CADRE's clock counts from the "cadre" mission when one is registered.
+fn cadre_clock_query(missions: &MissionRegistry, query: ClockQuery) -> ClockQuery {
+    let mission = query
+        .mission
+        .or_else(|| missions.get("cadre").map(|m| m.name.clone()));
+    ClockQuery {
+        lat: Some(default_lat()),
+        lon: Some(default_lon()),
+        alt: default_alt(),
+        mission,
+        ..query
+    }
+}
+
+async fn cadre_get_clock(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Query(query): Query<ClockQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = cadre_clock_query(&missions, query);
+    mission_clock(worker, cache, missions, Body::Moon, query).await
+}
+
+async fn cadre_post_clock(
+    State(worker): State<SpiceWorker>,
+    State(cache): State<Arc<ResponseCache>>,
+    State(missions): State<Arc<MissionRegistry>>,
+    Json(query): Json<ClockQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let query = cadre_clock_query(&missions, query);
+    mission_clock(worker, cache, missions, Body::Moon, query).await
+}
+
+fn default_cadence() -> f64 {
+    live::DEFAULT_CADENCE.as_secs_f64()
+}
+
+fn cadence(dt: f64) -> Result<std::time::Duration, (StatusCode, String)> {
+    match std::time::Duration::try_from_secs_f64(dt) {
+        Ok(cadence) if !cadence.is_zero() => Ok(cadence),
+        _ => Err((
+            StatusCode::BAD_REQUEST,
+            "dt must be a positive number of seconds".to_string(),
+        )),
+    }
+}
+
+fn live_message(
+    msg: Result<LiveSample, String>,
+    f: FormatSpecifier,
+    u: UnitSpecifier,
+) -> (&'static str, String) {
+    match msg {
+        Ok(sample) => {
+            let sample = moontime::translate_to(sample, u);
+            match moontime::try_format_as(sample, f, Some("live")) {
+                Ok(data) => ("sample", data),
+                Err(e) => ("error", e),
+            }
+        }
+        Err(e) => ("error", e),
+    }
+}
+
+/// Joins a live stream, or 503 when the server runs as many as it allows.
+fn join_live(
+    hub: &Arc<LiveHub>,
+    p: Position,
+    dt: f64,
+) -> Result<live::LiveReceiver, (StatusCode, String)> {
+    hub.subscribe(p, cadence(dt)?).ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("at most {} live streams at once", live::MAX_STREAMS),
+        )
+    })
+}
+
+fn live_events(
+    hub: Arc<LiveHub>,
+    p: Position,
+    dt: f64,
+    f: FormatSpecifier,
+    u: UnitSpecifier,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    check_format(f, &[])?;
+    let rx = join_live(&hub, p, dt)?;
+    // a lagging client skips the samples it missed
+    let events = BroadcastStream::new(rx).filter_map(move |msg| {
+        let (kind, data) = live_message(msg.ok()?, f, u);
+        Some(Ok(Event::default().event(kind).data(data)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CADRELiveQuery {
+    #[serde(default = "default_cadence")]
+    dt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+}
+
+async fn cadre_get_live(
+    State(hub): State<Arc<LiveHub>>,
+    Query(CADRELiveQuery { dt, f, u }): Query<CADRELiveQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    live_events(hub, Position::cadre(), dt, f, u)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MoonLiveQuery {
+    #[serde(default = "default_cadence")]
+    dt: f64,
+    #[serde(default = "default_format")]
+    f: FormatSpecifier,
+    #[serde(default = "default_degrees")]
+    u: UnitSpecifier,
+    /// The site, in degrees: a query string can't carry a nested `p`.
+    #[serde(default = "default_lat")]
+    lat: f64,
+    #[serde(default = "default_lon")]
+    lon: f64,
+    #[serde(default = "default_alt")]
+    alt: f64,
+    #[serde(default)]
+    body: Body,
+}
+
+impl MoonLiveQuery {
+    fn position(&self) -> Position {
+        Position::new(self.lat, self.lon, self.alt, UnitSpecifier::Degrees).on(self.body)
+    }
+}
+
+async fn moon_get_live(
+    State(hub): State<Arc<LiveHub>>,
+    Query(query): Query<MoonLiveQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    live_events(hub, query.position(), query.dt, query.f, query.u)
+}
+
+#[cfg(feature = "ws")]
+async fn live_socket(
+    mut socket: WebSocket,
+    mut rx: live::LiveReceiver,
+    f: FormatSpecifier,
+    u: UnitSpecifier,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        let msg = match rx.recv().await {
+            Ok(msg) => msg,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let (_, data) = live_message(msg, f, u);
+        if socket.send(Message::Text(data)).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "ws")]
+async fn cadre_get_live_ws(
+    State(hub): State<Arc<LiveHub>>,
+    Query(CADRELiveQuery { dt, f, u }): Query<CADRELiveQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    check_format(f, &[])?;
+    let rx = join_live(&hub, Position::cadre(), dt)?;
+    Ok(ws.on_upgrade(move |socket| live_socket(socket, rx, f, u)))
+}
+
+#[cfg(feature = "ws")]
+async fn moon_get_live_ws(
+    State(hub): State<Arc<LiveHub>>,
+    Query(query): Query<MoonLiveQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let (f, u) = (query.f, query.u);
+    check_format(f, &[])?;
+    let rx = join_live(&hub, query.position(), query.dt)?;
+    Ok(ws.on_upgrade(move |socket| live_socket(socket, rx, f, u)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query<T: serde::de::DeserializeOwned>(uri: &str) -> T {
+        let uri: axum::http::Uri = uri.parse().unwrap();
+        Query::<T>::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_fit_query_site() {
+        let req: FitRequest = query("/s/fit?lat=10.5&lon=-20&alt=0.25");
+        assert_eq!((req.lat, req.lon, req.alt), (10.5, -20.0, 0.25));
+        let req: FitRequest = query("/s/fit");
+        assert_eq!((req.lat, req.lon), (default_lat(), default_lon()));
+    }
+
+    #[test]
+    fn test_terminator_distance_query_site() {
+        let q: TerminatorDistanceQuery = query("/s/moon/terminator/distance?lat=-45&lon=120");
+        assert_eq!((q.lat, q.lon, q.alt), (-45.0, 120.0, 0.0));
+    }
+
+    #[test]
+    fn test_illumination_query_sites() {
+        let q: IlluminationQuery =
+            query("/s/moon/illumination?lat=12&lon=34&olat=12.001&olon=34&oalt=0.002");
+        assert_eq!((q.lat, q.lon), (12.0, 34.0));
+        assert_eq!((q.olat, q.olon, q.oalt), (Some(12.001), Some(34.0), 0.002));
+        let q: IlluminationQuery = query("/s/moon/illumination?lat=12&lon=34&observer=sun");
+        assert_eq!((q.olat, q.observer.as_str()), (None, "sun"));
+    }
+
+    #[test]
+    fn test_power_query_site() {
+        let q: PowerQuery = query("/s/moon/power?lat=-30&lon=60&alt=1&tilt=10&azimuth=90&area=2");
+        assert_eq!((q.lat, q.lon, q.alt), (-30.0, 60.0, 1.0));
+        assert_eq!((q.tilt, q.azimuth, q.area), (10.0, 90.0, 2.0));
+    }
+
+    #[test]
+    fn test_shadow_query_site() {
+        let q: ShadowQuery = query("/s/moon/shadow?lat=-85.5&lon=10&alt=0.5&h=2,3");
+        assert_eq!((q.lat, q.lon, q.alt), (-85.5, 10.0, 0.5));
+        assert_eq!(q.h, vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_eclipse_query_site() {
+        let q: EclipseQuery = query("/s/moon/eclipses?lat=20&lon=-150");
+        assert_eq!((q.lat, q.lon, q.alt), (20.0, -150.0, 0.0));
+    }
+
+    #[test]
+    fn test_earth_view_query_site() {
+        let q: EarthViewQuery = query("/s/moon/earth?lat=5&lon=-60&alt=2&u=radians");
+        assert_eq!((q.lat, q.lon, q.alt), (5.0, -60.0, 2.0));
+    }
+
+    #[test]
+    fn test_earth_track_query_site() {
+        let q: EarthTrackQuery = query("/s/moon/earth/track?lat=-10&lon=80&dt=600&f=csv");
+        assert_eq!((q.lat, q.lon, q.alt, q.dt), (-10.0, 80.0, 0.0, 600.0));
+    }
+
+    #[test]
+    fn test_dsn_query_site_and_stations() {
+        let q: DsnQuery = query("/s/moon/dsn?lat=-60&lon=100&stations=WSGT:32.5:-106.6:1.4");
+        assert_eq!((q.lat, q.lon, q.alt), (Some(-60.0), Some(100.0), 0.0));
+        let s = &q.stations[0];
+        assert_eq!((s.name.as_str(), s.lat, s.lon), ("WSGT", 32.5, -106.6));
+        assert_eq!((s.alt, s.mask), (1.4, dsn::DEFAULT_MASK_DEG));
+        let q: DsnQuery = query("/s/moon/dsn?stations=A:1:2;B:3:4:0:10");
+        assert_eq!(q.lat, None);
+        assert_eq!((q.stations.len(), q.stations[1].mask), (2, 10.0));
+    }
+
+    #[test]
+    fn test_comms_query_site_and_station() {
+        let q: CommsQuery = query("/s/moon/comms?lat=3&lon=-25&alt=0.01&s=WSGT:32.5:-106.6:1.4");
+        assert_eq!((q.lat, q.lon, q.alt), (3.0, -25.0, 0.01));
+        let s = q.s.unwrap();
+        assert_eq!((s.name.as_str(), s.lat, s.lon), ("WSGT", 32.5, -106.6));
+        let q: CommsQuery = query("/s/moon/comms?station=madrid");
+        assert!(q.s.is_none());
+    }
+
+    #[test]
+    fn test_mars_time_query_site() {
+        let q: MarsTimeQuery = query("/s/mars/time?lat=4.5&lon=137.4&alt=-4.5");
+        assert_eq!((q.lat, q.lon, q.alt), (Some(4.5), Some(137.4), -4.5));
+        let q: MarsTimeQuery = query("/s/mars/time?mission=Curiosity");
+        assert_eq!((q.lat, q.mission.as_deref()), (None, Some("Curiosity")));
+    }
+
+    #[test]
+    fn test_clock_query_site() {
+        let q: ClockQuery = query("/s/mercury/clock?lat=-20&lon=200&since=launch");
+        assert_eq!((q.lat, q.lon, q.alt), (Some(-20.0), Some(200.0), 0.0));
+        assert_eq!(q.since, Since::Launch);
+    }
+}
@@ -0,0 +1,331 @@
+//! Illumination percentage maps over a lunar region.
+//!
+//! For each node of a lat/lon grid, the percentage of samples in a time
+//! window with the Sun up, with Earth up, and with both, seen from the
+//! ground. "Up" is the center above the level plane, as in `solar_azel`; on
+//! terrain, it is above the skyline traced from the DSK for that node, which
+//! is what decides polar landing sites. Maps are computed a few cells at a
+//! time, `cells_per_job` of them, so each worker job is short.
+
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_STEP_S: f64 = 3600.0;
+pub const MAX_CELLS: usize = 10_000;
+/// Terrain traces a horizon mask per cell, so allows far fewer of them.
+pub const MAX_TERRAIN_CELLS: usize = 400;
+/// Upper bound on cells times samples in one map.
+pub const MAX_EVALUATIONS: usize = 5_000_000;
+/// Upper bound on samples in one worker job, which keeps each well inside
+/// the worker's timeout.
+pub const MAX_JOB_EVALUATIONS: usize = 50_000;
+/// What tracing one cell's horizon costs, in samples.
+const TRACE_EVALUATIONS: usize = 20_000;
+/// Azimuth step of the per-cell horizon masks, degrees.
+const TERRAIN_AZ_STEP: f64 = 2.0;
+
+/// A lat/lon box sampled every `spacing`, bounds included.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Region {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub lon_min: f64,
+    pub lon_max: f64,
+    pub spacing: f64,
+    #[serde(default = "default_degrees")]
+    pub units: UnitSpecifier,
+}
+
+impl Angular for Region {
+    fn to_degrees(&self) -> Region {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => Region {
+                lat_min: self.lat_min.to_degrees(),
+                lat_max: self.lat_max.to_degrees(),
+                lon_min: self.lon_min.to_degrees(),
+                lon_max: self.lon_max.to_degrees(),
+                spacing: self.spacing.to_degrees(),
+                units: UnitSpecifier::Degrees,
+            },
+        }
+    }
+    fn to_radians(&self) -> Region {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => Region {
+                lat_min: self.lat_min.to_radians(),
+                lat_max: self.lat_max.to_radians(),
+                lon_min: self.lon_min.to_radians(),
+                lon_max: self.lon_max.to_radians(),
+                spacing: self.spacing.to_radians(),
+                units: UnitSpecifier::Radians,
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// `min`, `min + step`, ... up to `max`, allowing for rounding at the end.
+fn nodes(min: f64, max: f64, step: f64) -> Vec<f64> {
+    let n = ((max - min) / step + 1e-9).floor() as usize + 1;
+    (0..n).map(|i| min + i as f64 * step).collect()
+}
+
+impl Region {
+    /// Grid latitudes, south to north, in the region's units.
+    pub fn lats(&self) -> Vec<f64> {
+        nodes(self.lat_min, self.lat_max, self.spacing)
+    }
+
+    /// Grid longitudes, west to east, in the region's units.
+    pub fn lons(&self) -> Vec<f64> {
+        nodes(self.lon_min, self.lon_max, self.spacing)
+    }
+
+    pub fn cells(&self) -> usize {
+        self.lats().len() * self.lons().len()
+    }
+
+    /// Checks the box is on the Moon and the grid is small enough.
+    pub fn check(&self) -> Result<(), String> {
+        let r = self.to_degrees();
+        if !(r.spacing > 0.0 && r.spacing.is_finite()) {
+            return Err("spacing must be positive".to_string());
+        }
+        if !(-90.0 <= r.lat_min && r.lat_min <= r.lat_max && r.lat_max <= 90.0) {
+            return Err("latitudes must be ordered and within -90 to 90".to_string());
+        }
+        if !(-180.0 <= r.lon_min && r.lon_min <= r.lon_max && r.lon_max - r.lon_min <= 360.0) {
+            return Err("longitudes must be ordered, from -180, spanning at most 360".to_string());
+        }
+        // bound the count before building the grid
+        let rows = (r.lat_max - r.lat_min) / r.spacing + 1.0;
+        let cols = (r.lon_max - r.lon_min) / r.spacing + 1.0;
+        if rows * cols > MAX_CELLS as f64 {
+            return Err(format!("region has more than {} cells", MAX_CELLS));
+        }
+        Ok(())
+    }
+}
+
+/// Percentages of samples with the Sun up, Earth up, and both.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct MapCell {
+    pub sun: f64,
+    pub earth: f64,
+    pub both: f64,
+}
+
+/// Which percentage a raster shows.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MapLayer {
+    Sun,
+    Earth,
+    #[default]
+    Both,
+}
+
+impl MapLayer {
+    fn of(&self, cell: &MapCell) -> f64 {
+        match self {
+            MapLayer::Sun => cell.sun,
+            MapLayer::Earth => cell.earth,
+            MapLayer::Both => cell.both,
+        }
+    }
+}
+
+impl std::fmt::Display for MapLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MapLayer::Sun => write!(f, "sun"),
+            MapLayer::Earth => write!(f, "earth"),
+            MapLayer::Both => write!(f, "both"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IlluminationMap {
+    pub region: Region,
+    pub lat: Vec<f64>,
+    pub lon: Vec<f64>,
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub until: DateTime,
+    /// Sampling step, s.
+    pub step: f64,
+    /// Samples per cell.
+    pub samples: usize,
+    pub surface: Surface,
+    /// One row per latitude, south to north, one cell per longitude.
+    pub cells: Vec<Vec<MapCell>>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for IlluminationMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "rows: {}, columns: {}, samples: {}, surface: {}, u: {}",
+            self.lat.len(),
+            self.lon.len(),
+            self.samples,
+            self.surface,
+            self.units
+        )?;
+        for (lat, row) in self.lat.iter().zip(&self.cells) {
+            for (lon, cell) in self.lon.iter().zip(row) {
+                write!(
+                    f,
+                    "\n{} {} {} {} {}",
+                    lat, lon, cell.sun, cell.earth, cell.both
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Angular for IlluminationMap {
+    fn to_degrees(&self) -> IlluminationMap {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => IlluminationMap {
+                region: self.region.to_degrees(),
+                lat: self.lat.iter().map(|l| l.to_degrees()).collect(),
+                lon: self.lon.iter().map(|l| l.to_degrees()).collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> IlluminationMap {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => IlluminationMap {
+                region: self.region.to_radians(),
+                lat: self.lat.iter().map(|l| l.to_radians()).collect(),
+                lon: self.lon.iter().map(|l| l.to_radians()).collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+impl IlluminationMap {
+    /// One `lat,lon,sun,earth,both` line per cell, after a header.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("lat,lon,sun,earth,both\n");
+        for (lat, row) in self.lat.iter().zip(&self.cells) {
+            for (lon, cell) in self.lon.iter().zip(row) {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{},{}",
+                    lat, lon, cell.sun, cell.earth, cell.both
+                );
+            }
+        }
+        csv
+    }
+
+    /// A plain (P2) PGM of `layer`, north up and west left, with gray
+    /// levels equal to the rounded percentage.
+    pub fn to_pgm(&self, layer: MapLayer) -> String {
+        let mut pgm = String::new();
+        let _ = writeln!(pgm, "P2");
+        let _ = writeln!(
+            pgm,
+            "# {} percent, t {} until {}",
+            layer, self.t, self.until
+        );
+        let _ = writeln!(pgm, "{} {}", self.lon.len(), self.lat.len());
+        let _ = writeln!(pgm, "100");
+        for row in self.cells.iter().rev() {
+            let line: Vec<String> = row
+                .iter()
+                .map(|cell| format!("{}", layer.of(cell).round() as u8))
+                .collect();
+            let _ = writeln!(pgm, "{}", line.join(" "));
+        }
+        pgm
+    }
+}
+
+/// Number of samples `step` apart from `et` to `end`, both included.
+pub fn samples(et: f64, end: f64, step: f64) -> usize {
+    ((end - et) / step).floor().max(0.0) as usize + 1
+}
+
+/// How many cells of `samples` samples each one job may take, or None when
+/// even one cell is too much.
+pub fn cells_per_job(samples: usize, surface: Surface) -> Option<usize> {
+    let cost = match surface {
+        Surface::Ellipsoid => samples,
+        Surface::Terrain => samples + TRACE_EVALUATIONS,
+    };
+    match MAX_JOB_EVALUATIONS / cost {
+        0 => None,
+        n => Some(n),
+    }
+}
+
+/// Percentages for each of `sites` over `[t, until]`, sampled every `step`.
+pub fn cells(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    sites: &[Position],
+    t: DateTime,
+    until: DateTime,
+    step: f64,
+    surface: Surface,
+) -> Result<Vec<MapCell>, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    let et = lock.str2et(crate::to_cspice_string(t).as_str());
+    let end = lock.str2et(crate::to_cspice_string(until).as_str());
+    let n = samples(et, end, step);
+
+    sites
+        .iter()
+        .map(|site| {
+            let point = crate::site_point(&lock, et, *site, surface)?;
+            let mask = match surface {
+                Surface::Ellipsoid => None,
+                Surface::Terrain => Some(crate::horizon::trace(&lock, *site, TERRAIN_AZ_STEP)?),
+            };
+            let up = |et: f64, target: &str| {
+                let azel = crate::azel_from(&lock, et, point, target);
+                match &mask {
+                    Some(mask) => mask.clearance(&azel) > 0.0,
+                    None => azel.el > 0.0,
+                }
+            };
+            let (mut sun, mut earth, mut both) = (0, 0, 0);
+            for i in 0..n {
+                let et = et + i as f64 * step;
+                let (s, e) = (up(et, "SUN"), up(et, "EARTH"));
+                sun += s as usize;
+                earth += e as usize;
+                both += (s && e) as usize;
+            }
+            let percent = |count: usize| 100.0 * count as f64 / n as f64;
+            Ok(MapCell {
+                sun: percent(sun),
+                earth: percent(earth),
+                both: percent(both),
+            })
+        })
+        .collect()
+}
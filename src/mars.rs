@@ -0,0 +1,185 @@
+//! Mars solar time at a site, after Allison & McEwen (2000) as used by
+//! Mars24: Mars Sol Date, Coordinated Mars Time, local mean and true solar
+//! time, and mission sols counted from a landing.
+//!
+//! The algorithm needs no kernels. When a Mars ephemeris is loaded (see
+//! `KERNELS_ENV`) the true solar time is also taken from `et2lst_c`, and the
+//! difference reported, as a check.
+
+use crate::missions::Mission;
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::sync::{Arc, Mutex};
+
+/// Mean solar day on Mars in Earth days.
+const SOL_DAYS: f64 = 1.027491252;
+/// Perturbations by the other planets: amplitude (deg), period (Julian
+/// years) and phase (deg).
+const PBS: [(f64, f64, f64); 7] = [
+    (0.0071, 2.2353, 49.409),
+    (0.0057, 2.7543, 168.173),
+    (0.0039, 1.1177, 191.837),
+    (0.0037, 15.7866, 21.736),
+    (0.0021, 2.1354, 15.704),
+    (0.0020, 2.4694, 95.528),
+    (0.0018, 32.8493, 49.095),
+];
+
+/// Mars time at a site, and the mission sol when there is a mission.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarsTime {
+    pub site: Position,
+    /// Mars Sol Date.
+    pub msd: f64,
+    /// Coordinated Mars Time, the mean solar time at the prime meridian.
+    pub mtc: String,
+    /// Local Mean Solar Time.
+    pub lmst: String,
+    /// Local True Solar Time.
+    pub ltst: String,
+    /// Areocentric solar longitude.
+    pub ls: f64,
+    /// Equation of time, true minus mean solar time, as an angle.
+    pub eot: f64,
+    /// True solar time from `et2lst_c`, if the kernels allow it.
+    pub ltst_spice: Option<String>,
+    /// `ltst` minus `ltst_spice`, s.
+    pub ltst_spice_diff: Option<f64>,
+    pub mission: Option<String>,
+    pub sol: Option<i64>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for MarsTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let (Some(mission), Some(sol)) = (&self.mission, self.sol) {
+            write!(f, "{} sol {}, ", mission, sol)?;
+        }
+        write!(
+            f,
+            "lmst: {}, ltst: {}, mtc: {}, msd: {:.5}, ls: {}, eot: {}, u: {}",
+            self.lmst, self.ltst, self.mtc, self.msd, self.ls, self.eot, self.units
+        )?;
+        if let (Some(spice), Some(diff)) = (&self.ltst_spice, self.ltst_spice_diff) {
+            write!(f, ", ltst (spice): {} ({:+.1} s)", spice, diff)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for MarsTime {
+    fn to_degrees(&self) -> MarsTime {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => MarsTime {
+                site: self.site.to_degrees(),
+                ls: self.ls.to_degrees(),
+                eot: self.eot.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> MarsTime {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => MarsTime {
+                site: self.site.to_radians(),
+                ls: self.ls.to_radians(),
+                eot: self.eot.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Mars Sol Date at `et`, taking TDB for TT.
+pub fn msd(et: f64) -> f64 {
+    let days = et / 86400.0;
+    (days - 4.5) / SOL_DAYS + 44796.0 - 0.00096
+}
+
+/// Solar longitude and equation of time at `et`, degrees.
+pub fn ls_eot(et: f64) -> (f64, f64) {
+    let days = et / 86400.0;
+    let m = (19.3871 + 0.52402073 * days).to_radians();
+    let fms = 270.3871 + 0.524038496 * days;
+    let pbs: f64 = PBS
+        .iter()
+        .map(|(a, tau, phi)| a * (0.985626 * days / tau + phi).to_radians().cos())
+        .sum();
+    let center = (10.691 + 3.0e-7 * days) * m.sin()
+        + 0.623 * (2.0 * m).sin()
+        + 0.050 * (3.0 * m).sin()
+        + 0.005 * (4.0 * m).sin()
+        + 0.0005 * (5.0 * m).sin()
+        + pbs;
+    let ls = (fms + center).rem_euclid(360.0);
+    let l = ls.to_radians();
+    let eot = 2.861 * (2.0 * l).sin() - 0.071 * (4.0 * l).sin() + 0.002 * (6.0 * l).sin() - center;
+    (ls, eot)
+}
+
+/// The sol at east longitude `lon` (degrees) containing `msd`, numbered
+/// so that the sol containing `landing_msd` is `first_sol`.
+pub fn mission_sol(msd: f64, lon: f64, landing_msd: f64, first_sol: i64) -> i64 {
+    let local = |msd: f64| (msd + lon / 360.0).floor() as i64;
+    local(msd) - local(landing_msd) + first_sol
+}
+
+/// Hours as HH:MM:SS.
+fn hms(hours: f64) -> String {
+    let s = (hours.rem_euclid(24.0) * 3600.0).round() as i64 % 86400;
+    format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+/// Mars time at `site` at `t`, with the sol of `mission` if given.
+pub fn mars_time(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    site: Position,
+    mission: Option<Mission>,
+) -> Result<MarsTime, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let lon = site.to_degrees().lon;
+
+        let date = msd(et);
+        let mtc = (24.0 * date).rem_euclid(24.0);
+        let lmst = mtc + lon / 15.0;
+        let (ls, eot) = ls_eot(et);
+        let ltst = lmst + eot / 15.0;
+
+        let spice = crate::local_solar_seconds(&lock, et, Body::Mars, lon.to_radians()).ok();
+        let diff = spice.map(|s| {
+            let d = ltst.rem_euclid(24.0) * 3600.0 - s;
+            (d + 43200.0).rem_euclid(86400.0) - 43200.0
+        });
+        let sol = mission.as_ref().map(|m| {
+            let landing = lock.str2et(crate::to_cspice_string(m.landing).as_str());
+            mission_sol(date, lon, msd(landing), m.first_sol)
+        });
+
+        MarsTime {
+            site: site.to_radians(),
+            msd: date,
+            mtc: hms(mtc),
+            lmst: hms(lmst),
+            ltst: hms(ltst),
+            ls: ls.to_radians(),
+            eot: eot.to_radians(),
+            ltst_spice: spice.map(|s| hms(s / 3600.0)),
+            ltst_spice_diff: diff,
+            mission: mission.map(|m| m.name),
+            sol,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
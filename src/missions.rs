@@ -0,0 +1,246 @@
+//! Landed missions and their epochs, for mission time.
+//!
+//! A few well-known landers are built in. More, or corrections, can be
+//! given as a JSON array of missions in the file named by
+//! `MOONTIME_MISSIONS`; an entry there replaces a built-in of the same name.
+
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, Time};
+
+/// Names a JSON file of extra missions.
+pub const MISSIONS_ENV: &str = "MOONTIME_MISSIONS";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Mission {
+    /// Lowercase; lookups ignore case.
+    pub name: String,
+    /// Where it landed; `body` says on what.
+    pub site: Position,
+    /// Touchdown, UTC (spacecraft event time).
+    #[serde(with = "default_datetime_standard")]
+    pub landing: DateTime,
+    /// Number of the sol that contains the landing: 0 for most landers,
+    /// 1 for Pathfinder and the MERs.
+    #[serde(default)]
+    pub first_sol: i64,
+    /// Liftoff, UTC.
+    #[serde(with = "default_datetime_standard::option", default)]
+    pub launch: Option<DateTime>,
+    /// Further milestones to count down to.
+    #[serde(default)]
+    pub events: Vec<MissionEvent>,
+}
+
+/// A named moment in a mission's plan.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MissionEvent {
+    pub name: String,
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+}
+
+/// The epoch mission time counts from.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Since {
+    #[default]
+    Landing,
+    Launch,
+}
+
+impl std::fmt::Display for Since {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Since::Landing => write!(f, "landing"),
+            Since::Launch => write!(f, "launch"),
+        }
+    }
+}
+
+impl Mission {
+    /// The mission's `since` epoch, if known.
+    pub fn epoch(&self, since: Since) -> Option<DateTime> {
+        match since {
+            Since::Landing => Some(self.landing),
+            Since::Launch => self.launch,
+        }
+    }
+}
+
+fn utc(year: i32, month: Month, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    let date = Date::from_calendar_date(year, month, day).unwrap();
+    let time = Time::from_hms(hour, minute, second).unwrap();
+    DateTime::new_utc(date, time)
+}
+
+fn mars_lander(
+    name: &str,
+    lat: f64,
+    lon: f64,
+    launch: DateTime,
+    landing: DateTime,
+    first_sol: i64,
+) -> Mission {
+    Mission {
+        name: name.to_string(),
+        site: Position::new(lat, lon, 0.0, UnitSpecifier::Degrees).on(Body::Mars),
+        landing,
+        first_sol,
+        launch: Some(launch),
+        events: Vec::new(),
+    }
+}
+
+/// Mars landers, at planetocentric east longitudes.
+pub fn builtin_missions() -> Vec<Mission> {
+    vec![
+        mars_lander(
+            "pathfinder",
+            19.13,
+            -33.22,
+            utc(1996, Month::December, 4, 6, 58, 7),
+            utc(1997, Month::July, 4, 16, 56, 55),
+            1,
+        ),
+        mars_lander(
+            "spirit",
+            -14.5684,
+            175.4726,
+            utc(2003, Month::June, 10, 17, 58, 47),
+            utc(2004, Month::January, 4, 4, 35, 0),
+            1,
+        ),
+        mars_lander(
+            "opportunity",
+            -1.9462,
+            -5.5266,
+            utc(2003, Month::July, 8, 3, 18, 15),
+            utc(2004, Month::January, 25, 5, 5, 0),
+            1,
+        ),
+        mars_lander(
+            "phoenix",
+            68.2188,
+            -125.7492,
+            utc(2007, Month::August, 4, 9, 26, 34),
+            utc(2008, Month::May, 25, 23, 38, 24),
+            0,
+        ),
+        mars_lander(
+            "curiosity",
+            -4.5895,
+            137.4417,
+            utc(2011, Month::November, 26, 15, 2, 0),
+            utc(2012, Month::August, 6, 5, 17, 57),
+            0,
+        ),
+        mars_lander(
+            "insight",
+            4.5024,
+            135.6234,
+            utc(2018, Month::May, 5, 11, 5, 1),
+            utc(2018, Month::November, 26, 19, 52, 59),
+            0,
+        ),
+        mars_lander(
+            "perseverance",
+            18.4447,
+            77.4508,
+            utc(2020, Month::July, 30, 11, 50, 0),
+            utc(2021, Month::February, 18, 20, 55, 0),
+            0,
+        ),
+        mars_lander(
+            "zhurong",
+            25.066,
+            109.925,
+            utc(2020, Month::July, 23, 4, 41, 15),
+            utc(2021, Month::May, 14, 23, 18, 0),
+            0,
+        ),
+    ]
+}
+
+impl std::fmt::Display for Mission {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let site = self.site.to_degrees();
+        write!(
+            f,
+            "{}: {} lat: {}, lon: {}, landing: {}, first sol: {}",
+            self.name, site.body, site.lat, site.lon, self.landing, self.first_sol
+        )?;
+        if let Some(launch) = self.launch {
+            write!(f, ", launch: {}", launch)?;
+        }
+        for event in &self.events {
+            write!(f, ", {}: {}", event.name, event.t)?;
+        }
+        Ok(())
+    }
+}
+
+/// Missions by name, fixed at startup.
+#[derive(Serialize, Debug)]
+#[serde(transparent)]
+pub struct MissionRegistry {
+    missions: Vec<Mission>,
+}
+
+impl MissionRegistry {
+    pub fn new(missions: Vec<Mission>) -> MissionRegistry {
+        let mut registry = MissionRegistry {
+            missions: Vec::new(),
+        };
+        for mission in missions {
+            registry.insert(mission);
+        }
+        registry
+    }
+
+    /// The built-in missions, then those in `MOONTIME_MISSIONS`.
+    pub fn load() -> Result<MissionRegistry, String> {
+        let mut registry = MissionRegistry::new(builtin_missions());
+        if let Some(path) = std::env::var_os(MISSIONS_ENV) {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+            let missions: Vec<Mission> = serde_json::from_str(&text)
+                .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+            for mission in missions {
+                registry.insert(mission);
+            }
+        }
+        Ok(registry)
+    }
+
+    fn insert(&mut self, mission: Mission) {
+        let mission = Mission {
+            name: mission.name.trim().to_lowercase(),
+            ..mission
+        };
+        self.missions.retain(|m| m.name != mission.name);
+        self.missions.push(mission);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Mission> {
+        let name = name.trim().to_lowercase();
+        self.missions.iter().find(|m| m.name == name)
+    }
+
+    pub fn missions(&self) -> &[Mission] {
+        &self.missions
+    }
+}
+
+impl std::fmt::Display for MissionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, mission) in self.missions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", mission)?;
+        }
+        Ok(())
+    }
+}
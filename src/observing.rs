@@ -0,0 +1,340 @@
+//! The Moon, and sites on it, seen from an observer on Earth.
+//!
+//! Observers are geodetic positions on Earth's reference ellipsoid. Az/el
+//! are geometric and topocentric, with azimuth counterclockwise from north
+//! as everywhere else here. The Moon rises and sets when its upper limb
+//! crosses the horizon, allowing the standard 34' of refraction. A lunar
+//! site is on the visible disk when it faces the observer.
+
+use crate::earth::{rotation, EarthOrientation, Topo};
+use crate::horizon::VisibilityEvent;
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::sync::{Arc, Mutex};
+
+const MOON_FRAME: &str = "MOON_ME_DE440_ME421";
+/// Refraction at the horizon, radians.
+const REFRACTION: f64 = 34.0 / 60.0 * PI / 180.0;
+pub const DEFAULT_STEP_S: f64 = 600.0;
+pub const DEFAULT_SPAN_DAYS: i64 = 1;
+/// Upper bound on samples in one rise/set search.
+pub const MAX_SAMPLES: usize = 100_000;
+
+/// The Moon's place in an Earth observer's sky.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct MoonSky {
+    /// Az/el of the Moon's center.
+    pub az: f64,
+    pub el: f64,
+    /// Distance to the Moon's center, km.
+    pub r: f64,
+    pub semidiameter: f64,
+    /// Upper limb above the horizon, with refraction.
+    pub up: bool,
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for MoonSky {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "az: {}, el: {}, r: {} km, semidiameter: {}, up: {}, orientation: {}, u: {}",
+            self.az, self.el, self.r, self.semidiameter, self.up, self.orientation, self.units
+        )
+    }
+}
+
+impl Angular for MoonSky {
+    fn to_degrees(&self) -> MoonSky {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => MoonSky {
+                az: self.az.to_degrees(),
+                el: self.el.to_degrees(),
+                semidiameter: self.semidiameter.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> MoonSky {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => MoonSky {
+                az: self.az.to_radians(),
+                el: self.el.to_radians(),
+                semidiameter: self.semidiameter.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Moonrises and moonsets over a window.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MoonRiseSet {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    #[serde(with = "default_datetime_standard")]
+    pub until: DateTime,
+    /// Whether the Moon is up at `t`.
+    pub up: bool,
+    /// In order; `az` is where the Moon crosses the horizon.
+    pub events: Vec<VisibilityEvent>,
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for MoonRiseSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "up: {}, events: {}, orientation: {}, u: {}",
+            self.up,
+            self.events.len(),
+            self.orientation,
+            self.units
+        )?;
+        for event in &self.events {
+            let kind = if event.rising { "rise" } else { "set" };
+            write!(f, "\n{} {} az: {}", kind, event.t, event.az)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for MoonRiseSet {
+    fn to_degrees(&self) -> MoonRiseSet {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => MoonRiseSet {
+                events: self
+                    .events
+                    .iter()
+                    .map(|e| VisibilityEvent {
+                        az: e.az.to_degrees(),
+                        ..*e
+                    })
+                    .collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> MoonRiseSet {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => MoonRiseSet {
+                events: self
+                    .events
+                    .iter()
+                    .map(|e| VisibilityEvent {
+                        az: e.az.to_radians(),
+                        ..*e
+                    })
+                    .collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// A lunar site seen from Earth.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct SiteFromEarth {
+    pub site: Position,
+    pub az: f64,
+    pub el: f64,
+    /// Distance to the site, km.
+    pub r: f64,
+    /// Angle between the site's zenith and the observer; the site is on the
+    /// near side of the disk below 90°, and foreshortened toward it.
+    pub emission: f64,
+    pub on_disk: bool,
+    /// Sun above the site's horizon, so the site is lit.
+    pub sunlit: bool,
+    /// On the disk and above the observer's horizon.
+    pub visible: bool,
+    pub orientation: EarthOrientation,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for SiteFromEarth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "az: {}, el: {}, r: {} km, emission: {}, on disk: {}, sunlit: {}, visible: {}, \
+             orientation: {}, u: {}",
+            self.az,
+            self.el,
+            self.r,
+            self.emission,
+            self.on_disk,
+            self.sunlit,
+            self.visible,
+            self.orientation,
+            self.units
+        )
+    }
+}
+
+impl Angular for SiteFromEarth {
+    fn to_degrees(&self) -> SiteFromEarth {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => SiteFromEarth {
+                site: self.site.to_degrees(),
+                az: self.az.to_degrees(),
+                el: self.el.to_degrees(),
+                emission: self.emission.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> SiteFromEarth {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => SiteFromEarth {
+                site: self.site.to_radians(),
+                az: self.az.to_radians(),
+                el: self.el.to_radians(),
+                emission: self.emission.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+fn moon_from_earth(lock: &SpiceLock, et: f64, frame: &str) -> Vec3 {
+    lock.spkpos("MOON", et, frame, "NONE", "EARTH").0
+}
+
+/// The Moon in the sky of `observer` on Earth at `t`.
+pub fn moon_sky(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    observer: Position,
+) -> Result<MoonSky, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let orientation = EarthOrientation::at(&lock, &[et]);
+        let topo = Topo::new(&lock, observer);
+        let moon = moon_from_earth(&lock, et, orientation.frame());
+        let (az, el) = topo.azel(moon);
+        let r = norm(sub(moon, topo.point));
+        let semidiameter = (crate::body_radii(&lock, "MOON")[0] / r).asin();
+
+        MoonSky {
+            az,
+            el,
+            r,
+            semidiameter,
+            up: el + semidiameter + REFRACTION > 0.0,
+            orientation,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
+
+/// Moonrises and moonsets for `observer` between `t` and `until`, sampled
+/// every `step` seconds and refined to a second.
+pub fn moon_rise_set(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    until: DateTime,
+    step: f64,
+    observer: Position,
+) -> Result<MoonRiseSet, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let end = lock.str2et(crate::to_cspice_string(until).as_str());
+        let orientation = EarthOrientation::at(&lock, &[et, end]);
+        let frame = orientation.frame();
+        let topo = Topo::new(&lock, observer);
+        let radius = crate::body_radii(&lock, "MOON")[0];
+        // upper limb height above the refracted horizon, and azimuth
+        let limb = |et: f64| {
+            let moon = moon_from_earth(&lock, et, frame);
+            let (az, el) = topo.azel(moon);
+            let semidiameter = (radius / norm(sub(moon, topo.point))).asin();
+            (el + semidiameter + REFRACTION, az)
+        };
+
+        let up = limb(et).0 > 0.0;
+        let events = crate::search::changes(et, end, step, |et| limb(et).0 > 0.0)
+            .map(|(at, rising)| VisibilityEvent {
+                t: t + time::Duration::seconds_f64(at - et),
+                rising,
+                az: limb(at).1,
+            })
+            .collect();
+
+        MoonRiseSet {
+            t,
+            until,
+            up,
+            events,
+            orientation,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
+
+/// The lunar `site` as seen by `observer` on Earth at `t`.
+pub fn site_from_earth(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    observer: Position,
+    site: Position,
+) -> Result<SiteFromEarth, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let orientation = EarthOrientation::at(&lock, &[et]);
+        let frame = orientation.frame();
+        let topo = Topo::new(&lock, observer);
+
+        let site = site.on(Body::Moon);
+        let on_moon = crate::ellipsoid_point(&lock, site);
+        let to_frame = rotation(&lock, MOON_FRAME, frame, et);
+        let point = add(moon_from_earth(&lock, et, frame), mxv(to_frame, on_moon));
+        let (az, el) = topo.azel(point);
+        let s = site.to_radians();
+        let zenith = mxv(to_frame, unit(s.lat, s.lon));
+        let emission = angle(zenith, sub(topo.point, point));
+        let on_disk = emission < FRAC_PI_2;
+
+        SiteFromEarth {
+            site,
+            az,
+            el,
+            r: norm(sub(point, topo.point)),
+            emission,
+            on_disk,
+            sunlit: crate::azel_from(&lock, et, on_moon, "SUN").el > 0.0,
+            visible: on_disk && el > 0.0,
+            orientation,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
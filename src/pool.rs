@@ -0,0 +1,557 @@
+//! Optional multi-process worker pool.
+//!
+//! A process can only run one CSPICE computation at a time, so the in-process
+//! worker serializes every request. In pool mode the server instead spawns
+//! copies of itself in worker mode, each loading the same kernels, and spreads
+//! requests across them as JSON lines over their stdin and stdout.
+
+use crate::clock::MissionClock;
+use crate::dsn::{Comms, CommsTarget, DsnVisibility, Station};
+use crate::earth::{EarthTrack, EarthView};
+use crate::eclipse::Eclipses;
+use crate::horizon::{HorizonMask, Visibility};
+use crate::illumination::{Illumination, Observer};
+use crate::live::LiveSample;
+use crate::maps::MapCell;
+use crate::mars::MarsTime;
+use crate::missions::{Mission, Since};
+use crate::observing::{MoonRiseSet, MoonSky, SiteFromEarth};
+use crate::power::{Panel, PanelPower};
+use crate::shadow::Shadows;
+use crate::subpoint::SubPoint;
+use crate::terminator::{Terminator, TerminatorDistance, TerminatorKind};
+use crate::types::*;
+use crate::worker::WorkerError;
+
+use spice::SpiceLock;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+/// Set in the environment of spawned workers.
+pub const WORKER_ENV: &str = "MOONTIME_WORKER";
+
+/// A computation that can be shipped to another process.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SpiceRequest {
+    Et {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+    },
+    SolarTime {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+    },
+    SolarAzel {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+    },
+    EarthFromSun {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+    },
+    EarthEcliptic {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+    },
+    Live {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+    },
+    Subsolar {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        frame: MoonFrame,
+    },
+    SubEarth {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        frame: MoonFrame,
+    },
+    Terminator {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        frame: MoonFrame,
+        kind: TerminatorKind,
+        n: usize,
+    },
+    TerminatorDistance {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+    },
+    Illumination {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        observer: Observer,
+        #[serde(default)]
+        surface: Surface,
+    },
+    PanelPower {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        panel: Panel,
+        #[serde(with = "default_datetime_standard::option", default)]
+        until: Option<DateTime>,
+        step: f64,
+        #[serde(default)]
+        mask: Option<HorizonMask>,
+    },
+    TargetAzel {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        target: String,
+        #[serde(default)]
+        surface: Surface,
+    },
+    Shadows {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        heights: Vec<f64>,
+        below: Option<f64>,
+        #[serde(default)]
+        mask: Option<HorizonMask>,
+    },
+    Visibility {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        mask: HorizonMask,
+        target: String,
+        #[serde(with = "default_datetime_standard::option", default)]
+        until: Option<DateTime>,
+        step: f64,
+    },
+    EarthView {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+    },
+    Comms {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        station: Station,
+        target: CommsTarget,
+        frequency: f64,
+    },
+    Dsn {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        #[serde(with = "default_datetime_standard")]
+        until: DateTime,
+        step: f64,
+        p: Option<Position>,
+        stations: Vec<Station>,
+    },
+    MoonSky {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        o: Position,
+    },
+    MoonRiseSet {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        #[serde(with = "default_datetime_standard")]
+        until: DateTime,
+        step: f64,
+        o: Position,
+    },
+    SiteFromEarth {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        o: Position,
+        p: Position,
+    },
+    EarthTrack {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        #[serde(with = "default_datetime_standard")]
+        until: DateTime,
+        step: f64,
+        p: Position,
+    },
+    Eclipses {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        #[serde(with = "default_datetime_standard")]
+        until: DateTime,
+        p: Position,
+    },
+    MarsTime {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        mission: Option<Mission>,
+    },
+    MissionClock {
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        p: Position,
+        since: Since,
+        #[serde(with = "default_datetime_standard::option", default)]
+        epoch: Option<DateTime>,
+        mission: Option<Mission>,
+    },
+    MapCells {
+        sites: Vec<Position>,
+        #[serde(with = "default_datetime_standard")]
+        t: DateTime,
+        #[serde(with = "default_datetime_standard")]
+        until: DateTime,
+        step: f64,
+        #[serde(default)]
+        surface: Surface,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SpiceResponse {
+    Et(f64),
+    SolarTime(Result<String, String>),
+    RAzEl(RAzEl),
+    PositionFull(PositionFull),
+    Live(Result<LiveSample, String>),
+    SubPoint(Result<SubPoint, String>),
+    Terminator(Result<Terminator, String>),
+    TerminatorDistance(Result<TerminatorDistance, String>),
+    /// Errors here come from SPICE rejecting the request, e.g. an unknown observer.
+    Illumination(Result<Illumination, String>),
+    PanelPower(Result<PanelPower, String>),
+    TargetAzel(Result<RAzEl, String>),
+    Shadows(Result<Shadows, String>),
+    Visibility(Result<Visibility, String>),
+    MapCells(Result<Vec<MapCell>, String>),
+    Eclipses(Result<Eclipses, String>),
+    EarthView(Result<EarthView, String>),
+    EarthTrack(Result<EarthTrack, String>),
+    Dsn(Result<DsnVisibility, String>),
+    MoonSky(Result<MoonSky, String>),
+    MoonRiseSet(Result<MoonRiseSet, String>),
+    SiteFromEarth(Result<SiteFromEarth, String>),
+    Comms(Result<Comms, String>),
+    MarsTime(Result<MarsTime, String>),
+    MissionClock(MissionClock),
+}
+
+impl SpiceRequest {
+    pub fn execute(self, sl_mutex: Arc<Mutex<SpiceLock>>) -> SpiceResponse {
+        match self {
+            SpiceRequest::Et { t } => SpiceResponse::Et(crate::get_et(sl_mutex, t)),
+            SpiceRequest::SolarTime { t, p } => {
+                SpiceResponse::SolarTime(crate::solar_time(sl_mutex, t, p))
+            }
+            SpiceRequest::SolarAzel { t, p } => {
+                SpiceResponse::RAzEl(crate::solar_azel(sl_mutex, t, p))
+            }
+            SpiceRequest::EarthFromSun { t } => {
+                SpiceResponse::PositionFull(crate::earth_position_from_sun(sl_mutex, t))
+            }
+            SpiceRequest::EarthEcliptic { t } => {
+                SpiceResponse::PositionFull(crate::earth_position_ecliptic(sl_mutex, t))
+            }
+            SpiceRequest::Live { t, p } => SpiceResponse::Live(crate::live::sample(sl_mutex, t, p)),
+            SpiceRequest::Subsolar { t, frame } => {
+                SpiceResponse::SubPoint(crate::subpoint::subsolar_point(sl_mutex, t, frame))
+            }
+            SpiceRequest::SubEarth { t, frame } => {
+                SpiceResponse::SubPoint(crate::subpoint::sub_earth_point(sl_mutex, t, frame))
+            }
+            SpiceRequest::Terminator { t, frame, kind, n } => SpiceResponse::Terminator(
+                crate::terminator::terminator(sl_mutex, t, frame, kind, n),
+            ),
+            SpiceRequest::TerminatorDistance { t, p } => SpiceResponse::TerminatorDistance(
+                crate::terminator::terminator_distance(sl_mutex, t, p),
+            ),
+            SpiceRequest::Illumination {
+                t,
+                p,
+                observer,
+                surface,
+            } => SpiceResponse::Illumination(crate::illumination::illumination(
+                sl_mutex, t, p, observer, surface,
+            )),
+            SpiceRequest::PanelPower {
+                t,
+                p,
+                panel,
+                until,
+                step,
+                mask,
+            } => SpiceResponse::PanelPower(crate::power::panel_power(
+                sl_mutex,
+                t,
+                p,
+                panel,
+                until,
+                step,
+                mask.as_ref(),
+            )),
+            SpiceRequest::TargetAzel {
+                t,
+                p,
+                target,
+                surface,
+            } => SpiceResponse::TargetAzel(crate::target_azel_surface(
+                sl_mutex, t, p, &target, surface,
+            )),
+            SpiceRequest::Shadows {
+                t,
+                p,
+                heights,
+                below,
+                mask,
+            } => SpiceResponse::Shadows(crate::shadow::shadows(
+                sl_mutex,
+                t,
+                p,
+                &heights,
+                below,
+                mask.as_ref(),
+            )),
+            SpiceRequest::Visibility {
+                t,
+                mask,
+                target,
+                until,
+                step,
+            } => SpiceResponse::Visibility(crate::horizon::visibility(
+                sl_mutex, &mask, &target, t, until, step,
+            )),
+            SpiceRequest::EarthView { t, p } => {
+                SpiceResponse::EarthView(crate::earth::earth_view(sl_mutex, t, p))
+            }
+            SpiceRequest::Comms {
+                t,
+                station,
+                target,
+                frequency,
+            } => SpiceResponse::Comms(crate::dsn::comms(sl_mutex, t, &station, &target, frequency)),
+            SpiceRequest::Dsn {
+                t,
+                until,
+                step,
+                p,
+                stations,
+            } => SpiceResponse::Dsn(crate::dsn::dsn_visibility(
+                sl_mutex, t, until, step, p, &stations,
+            )),
+            SpiceRequest::MoonSky { t, o } => {
+                SpiceResponse::MoonSky(crate::observing::moon_sky(sl_mutex, t, o))
+            }
+            SpiceRequest::MoonRiseSet { t, until, step, o } => SpiceResponse::MoonRiseSet(
+                crate::observing::moon_rise_set(sl_mutex, t, until, step, o),
+            ),
+            SpiceRequest::SiteFromEarth { t, o, p } => {
+                SpiceResponse::SiteFromEarth(crate::observing::site_from_earth(sl_mutex, t, o, p))
+            }
+            SpiceRequest::EarthTrack { t, until, step, p } => {
+                SpiceResponse::EarthTrack(crate::earth::earth_track(sl_mutex, t, until, step, p))
+            }
+            SpiceRequest::Eclipses { t, until, p } => {
+                SpiceResponse::Eclipses(crate::eclipse::eclipses(sl_mutex, t, until, p))
+            }
+            SpiceRequest::MarsTime { t, p, mission } => {
+                SpiceResponse::MarsTime(crate::mars::mars_time(sl_mutex, t, p, mission))
+            }
+            SpiceRequest::MissionClock {
+                t,
+                p,
+                since,
+                epoch,
+                mission,
+            } => SpiceResponse::MissionClock(crate::clock::mission_clock(
+                sl_mutex, t, p, since, epoch, mission,
+            )),
+            SpiceRequest::MapCells {
+                sites,
+                t,
+                until,
+                step,
+                surface,
+            } => SpiceResponse::MapCells(crate::maps::cells(
+                sl_mutex, &sites, t, until, step, surface,
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Envelope<T> {
+    id: u64,
+    body: T,
+}
+
+/// Worker mode: answers requests read from stdin until it is closed.
+///
+/// Responses are the only thing written to stdout, one envelope per line;
+/// diagnostics go to stderr. The parent still skips any line that isn't a
+/// response envelope, in case a kernel or CSPICE itself prints there.
+pub fn serve_stdio(sl_mutex: Arc<Mutex<SpiceLock>>) {
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let req: Envelope<SpiceRequest> = match serde_json::from_str(&line) {
+            Ok(req) => req,
+            Err(e) => {
+                eprintln!("bad request: {}", e);
+                continue;
+            }
+        };
+        let res = Envelope {
+            id: req.id,
+            body: req.body.execute(sl_mutex.clone()),
+        };
+        println!("{}", serde_json::to_string(&res).unwrap());
+    }
+}
+
+struct ChildWorker {
+    // held so the process is killed when the worker is replaced
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl ChildWorker {
+    fn spawn(exe: &PathBuf) -> std::io::Result<ChildWorker> {
+        let mut child = Command::new(exe)
+            .env(WORKER_ENV, "1")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+        Ok(ChildWorker {
+            _child: child,
+            stdin,
+            stdout,
+        })
+    }
+
+    async fn call(&mut self, id: u64, req: SpiceRequest) -> std::io::Result<SpiceResponse> {
+        let mut line = serde_json::to_string(&Envelope { id, body: req })?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        while let Some(line) = self.stdout.next_line().await? {
+            match serde_json::from_str::<Envelope<SpiceResponse>>(&line) {
+                Ok(res) if res.id == id => return Ok(res.body),
+                _ => tracing::debug!("worker: {}", line),
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "worker exited",
+        ))
+    }
+}
+
+/// Holds a slot in `WorkerPool::pending` until dropped, so a call whose
+/// future is dropped (the client went away) still gives its slot back.
+struct Pending<'a>(&'a AtomicUsize);
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct WorkerPool {
+    exe: PathBuf,
+    workers: Vec<tokio::sync::Mutex<ChildWorker>>,
+    next: AtomicUsize,
+    next_id: AtomicU64,
+    pending: AtomicUsize,
+    capacity: usize,
+    timeout: Duration,
+}
+
+impl WorkerPool {
+    /// Spawns `size` copies of the running executable in worker mode.
+    pub fn new(size: usize, capacity: usize, timeout: Duration) -> std::io::Result<WorkerPool> {
+        let exe = std::env::current_exe()?;
+        let mut workers = Vec::with_capacity(size);
+        for _ in 0..size {
+            workers.push(tokio::sync::Mutex::new(ChildWorker::spawn(&exe)?));
+        }
+        Ok(WorkerPool {
+            exe,
+            workers,
+            next: AtomicUsize::new(0),
+            next_id: AtomicU64::new(0),
+            pending: AtomicUsize::new(0),
+            capacity,
+            timeout,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Runs `req` on an idle worker, or waits its turn on the next one.
+    pub async fn call(&self, req: SpiceRequest) -> Result<SpiceResponse, WorkerError> {
+        if self.pending.fetch_add(1, Ordering::SeqCst) >= self.capacity {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(WorkerError::Busy);
+        }
+        let _pending = Pending(&self.pending);
+        self.dispatch(req).await
+    }
+
+    /// The timeout covers waiting for a busy worker as well as the call, so
+    /// a hung process can't hold up the requests queued behind it forever.
+    async fn dispatch(&self, req: SpiceRequest) -> Result<SpiceResponse, WorkerError> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let n = self.workers.len();
+        let start = self.next.fetch_add(1, Ordering::SeqCst) % n;
+        let idle = (0..n).find_map(|i| self.workers[(start + i) % n].try_lock().ok());
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => tokio::time::timeout_at(deadline, self.workers[start].lock())
+                .await
+                .map_err(|_| WorkerError::Timeout)?,
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let res = tokio::time::timeout_at(deadline, worker.call(id, req)).await;
+        let err = match res {
+            Ok(Ok(res)) => return Ok(res),
+            Ok(Err(e)) => {
+                tracing::error!("worker failed: {}", e);
+                WorkerError::Failed
+            }
+            Err(_) => WorkerError::Timeout,
+        };
+        // the process is dead or still busy with the old request, replace it
+        match ChildWorker::spawn(&self.exe) {
+            Ok(fresh) => *worker = fresh,
+            Err(e) => tracing::error!("could not respawn worker: {}", e),
+        }
+        Err(err)
+    }
+}
@@ -0,0 +1,165 @@
+//! Solar power geometry for a flat panel at a lunar site.
+//!
+//! Irradiance is the solar constant scaled by the actual Sun range, reduced
+//! by the fraction of the solar disk above the horizon and projected onto
+//! the panel normal. Terrain, dust and cell efficiency are left to the
+//! caller: `power` is the sunlight reaching the panel, not electrical output.
+//! With a horizon mask, the disk fraction is measured above the skyline.
+
+use crate::horizon::HorizonMask;
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+/// Total solar irradiance at 1 AU, W/m².
+pub const SOLAR_CONSTANT: f64 = 1361.0;
+pub const AU_KM: f64 = 149_597_870.7;
+pub const SUN_RADIUS_KM: f64 = 695_700.0;
+pub const DEFAULT_STEP_S: f64 = 600.0;
+/// Upper bound on samples in one integration window.
+pub const MAX_SAMPLES: usize = 100_000;
+
+/// A flat panel. `tilt` is from horizontal (0 faces the zenith) and
+/// `azimuth` is the direction the panel faces, measured like the azimuths
+/// returned by `solar_azel`. `area` is in m².
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Panel {
+    pub tilt: f64,
+    pub azimuth: f64,
+    pub area: f64,
+    #[serde(default = "default_degrees")]
+    pub units: UnitSpecifier,
+}
+
+impl Angular for Panel {
+    fn to_degrees(&self) -> Panel {
+        match self.units {
+            UnitSpecifier::Degrees => *self,
+            UnitSpecifier::Radians => Panel {
+                tilt: self.tilt.to_degrees(),
+                azimuth: self.azimuth.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..*self
+            },
+        }
+    }
+    fn to_radians(&self) -> Panel {
+        match self.units {
+            UnitSpecifier::Radians => *self,
+            UnitSpecifier::Degrees => Panel {
+                tilt: self.tilt.to_radians(),
+                azimuth: self.azimuth.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..*self
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PanelPower {
+    /// Cosine of the angle between the Sun and the panel normal.
+    pub cos_incidence: f64,
+    /// Fraction of the solar disk above the horizon, 0 to 1.
+    pub sun_fraction: f64,
+    /// Distance to the Sun, km.
+    pub sun_range: f64,
+    /// Sunlight falling on the panel, W/m².
+    pub irradiance: f64,
+    /// `irradiance` times the panel area, W.
+    pub power: f64,
+    /// Energy between `t` and `until`, Wh, when a window was given.
+    pub energy: Option<f64>,
+    #[serde(with = "default_datetime_standard::option", default)]
+    pub until: Option<DateTime>,
+}
+
+impl std::fmt::Display for PanelPower {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "irradiance: {} W/m2, power: {} W, cos incidence: {}, sun fraction: {}, \
+             sun range: {} km",
+            self.irradiance, self.power, self.cos_incidence, self.sun_fraction, self.sun_range
+        )?;
+        if let Some(energy) = self.energy {
+            write!(f, ", energy: {} Wh", energy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fraction of a disk of angular radius `radius` whose center is at
+/// elevation `el` that lies above a flat horizon.
+fn disk_fraction(el: f64, radius: f64) -> f64 {
+    let h = (el / radius).clamp(-1.0, 1.0);
+    1.0 - (h.acos() - h * (1.0 - h * h).sqrt()) / PI
+}
+
+/// (cos incidence, sun fraction, sun range, W/m²) at `et`.
+fn sample(
+    lock: &SpiceLock,
+    et: f64,
+    pos: Position,
+    frame: &str,
+    panel: Panel,
+    mask: Option<&HorizonMask>,
+) -> [f64; 4] {
+    let sun = crate::target_azel_et(lock, et, pos, frame, "SUN");
+    let horizon = mask.map_or(0.0, |mask| mask.elevation_at(sun.az));
+    let panel = panel.to_radians();
+    let cos_incidence = sun.el.sin() * panel.tilt.cos()
+        + sun.el.cos() * panel.tilt.sin() * (sun.az - panel.azimuth).cos();
+    let fraction = disk_fraction(sun.el - horizon, (SUN_RADIUS_KM / sun.r).asin());
+    let irradiance = SOLAR_CONSTANT * (AU_KM / sun.r).powi(2) * fraction * cos_incidence.max(0.0);
+    [cos_incidence, fraction, sun.r, irradiance]
+}
+
+/// Sunlight on `panel` at `pos` at `t`, and optionally the energy collected
+/// until `until`, integrated with the trapezoid rule every `step` seconds.
+pub fn panel_power(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+    panel: Panel,
+    until: Option<DateTime>,
+    step: f64,
+    mask: Option<&HorizonMask>,
+) -> Result<PanelPower, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let end = until.map(|until| lock.str2et(crate::to_cspice_string(until).as_str()));
+        let frame = crate::body_frame(&lock, pos.body, &[et, end.unwrap_or(et)]);
+        let [cos_incidence, sun_fraction, sun_range, irradiance] =
+            sample(&lock, et, pos, frame, panel, mask);
+
+        let energy = end.map(|end| {
+            let mut joules = 0.0;
+            let (mut a, mut wa) = (et, irradiance);
+            while a < end {
+                let b = (a + step).min(end);
+                let wb = sample(&lock, b, pos, frame, panel, mask)[3];
+                joules += 0.5 * (wa + wb) * (b - a);
+                (a, wa) = (b, wb);
+            }
+            joules * panel.area / 3600.0
+        });
+
+        PanelPower {
+            cos_incidence,
+            sun_fraction,
+            sun_range,
+            irradiance,
+            power: irradiance * panel.area,
+            energy,
+            until,
+        }
+    })
+}
@@ -0,0 +1,597 @@
+//! A pure-Rust backend for the read-only ephemeris lookups.
+//!
+//! Covers what `get_et`, `earth_position_from_sun`, `earth_position_ecliptic`
+//! and `solar_azel` need without linking CSPICE: UTC to ET through a
+//! leapseconds kernel, SPK positions, text PCK (IAU_*) and binary PCK body
+//! frames, and TK frames from frame kernels. Aberration corrections are not
+//! supported; everything is geometric, as in the CSPICE path.
+
+use crate::daf::{Daf, DafKind, Segment};
+use crate::types::*;
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+type Mat = [[f64; 3]; 3];
+
+/// Seconds from the Unix epoch to J2000 (2000-01-01T12:00:00).
+const J2000_UNIX: i64 = 946728000;
+/// IAU 1976 obliquity of the ecliptic at J2000, which defines ECLIPJ2000.
+const ECLIPJ2000_OBLIQUITY: f64 = 84381.448 / 3600.0 * PI / 180.0;
+
+const BODIES: &[(&str, i32)] = &[
+    ("SSB", 0),
+    ("SOLAR SYSTEM BARYCENTER", 0),
+    ("MERCURY BARYCENTER", 1),
+    ("VENUS BARYCENTER", 2),
+    ("EARTH BARYCENTER", 3),
+    ("EMB", 3),
+    ("MARS BARYCENTER", 4),
+    ("JUPITER BARYCENTER", 5),
+    ("SATURN BARYCENTER", 6),
+    ("URANUS BARYCENTER", 7),
+    ("NEPTUNE BARYCENTER", 8),
+    ("PLUTO BARYCENTER", 9),
+    ("SUN", 10),
+    ("MERCURY", 199),
+    ("VENUS", 299),
+    ("EARTH", 399),
+    ("MOON", 301),
+    ("MARS", 499),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Num(f64),
+    Str(String),
+}
+
+/// Kernels loaded for the pure backend. Read-only once loaded, so it can be
+/// shared between threads without a lock.
+#[derive(Default)]
+pub struct Ephemeris {
+    /// Later files take priority, as with `furnsh`.
+    dafs: Vec<Daf>,
+    pool: HashMap<String, Vec<Value>>,
+}
+
+impl Ephemeris {
+    /// Loads kernels by content: DAF files by their ID word, anything else is
+    /// parsed as a text kernel.
+    pub fn load(paths: &[&str]) -> Result<Ephemeris, String> {
+        let mut eph = Ephemeris::default();
+        for path in paths {
+            let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            if data.starts_with(b"DAF/") || data.starts_with(b"NAIF/DAF") {
+                eph.dafs
+                    .push(Daf::parse(data).map_err(|e| format!("{}: {}", path, e))?);
+            } else {
+                let text = String::from_utf8_lossy(&data);
+                eph.parse_text(&text)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+        Ok(eph)
+    }
+
+    fn parse_text(&mut self, text: &str) -> Result<(), String> {
+        let mut data = false;
+        let mut tokens = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("\\begindata") {
+                data = true;
+            } else if trimmed.starts_with("\\begintext") {
+                data = false;
+            } else if data {
+                tokenize(line, &mut tokens)?;
+            }
+        }
+
+        let mut tokens = tokens.into_iter().peekable();
+        while let Some(name) = tokens.next() {
+            let append = match tokens.next().as_deref() {
+                Some("=") => false,
+                Some("+=") => true,
+                _ => return Err(format!("expected assignment after {}", name)),
+            };
+            let mut values = Vec::new();
+            if tokens.peek().map(|t| t.as_str()) == Some("(") {
+                tokens.next();
+                for token in tokens.by_ref() {
+                    if token == ")" {
+                        break;
+                    }
+                    values.push(parse_value(&token)?);
+                }
+            } else if let Some(token) = tokens.next() {
+                values.push(parse_value(&token)?);
+            }
+            if append {
+                self.pool.entry(name).or_default().extend(values);
+            } else {
+                self.pool.insert(name, values);
+            }
+        }
+        Ok(())
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        self.pool.get(name).map(|values| {
+            values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Num(n) => Some(*n),
+                    Value::Str(_) => None,
+                })
+                .collect()
+        })
+    }
+
+    fn number(&self, name: &str) -> Result<f64, String> {
+        self.numbers(name)
+            .and_then(|n| n.first().copied())
+            .ok_or(format!("{} is not in the kernel pool", name))
+    }
+
+    fn string(&self, name: &str) -> Option<String> {
+        match self.pool.get(name)?.first()? {
+            Value::Str(s) => Some(s.clone()),
+            Value::Num(_) => None,
+        }
+    }
+
+    /// UTC to ephemeris time (TDB seconds past J2000), as `str2et` does.
+    pub fn utc_to_et(&self, t: DateTime) -> Result<f64, String> {
+        let utc = (t.unix_timestamp() - J2000_UNIX) as f64 + t.nanosecond() as f64 * 1e-9;
+        let table = self
+            .numbers("DELTET/DELTA_AT")
+            .ok_or("no leapseconds kernel loaded")?;
+        let delta_at = table
+            .chunks(2)
+            .take_while(|pair| pair.len() == 2 && pair[1] <= utc)
+            .last()
+            .map_or(table[0], |pair| pair[0]);
+
+        let tt = utc + delta_at + self.number("DELTET/DELTA_T_A")?;
+        let m = self
+            .numbers("DELTET/M")
+            .ok_or("DELTET/M is not in the kernel pool")?;
+        let k = self.number("DELTET/K")?;
+        let eb = self.number("DELTET/EB")?;
+        let m = m[0] + m[1] * tt;
+        Ok(tt + k * (m + eb * m.sin()).sin())
+    }
+
+    fn body_id(&self, name: &str) -> Result<i32, String> {
+        let name = name.trim().to_uppercase();
+        if let Ok(id) = name.parse() {
+            return Ok(id);
+        }
+        BODIES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, id)| *id)
+            .ok_or(format!("unknown body {}", name))
+    }
+
+    fn spk_segment(&self, body: i32, et: f64) -> Result<(&Daf, &Segment), String> {
+        self.dafs
+            .iter()
+            .rev()
+            .filter(|daf| daf.kind == DafKind::Spk)
+            .flat_map(|daf| daf.segments.iter().rev().map(move |seg| (daf, seg)))
+            .find(|(_, seg)| seg.body == body && seg.start_et <= et && et <= seg.end_et)
+            .ok_or(format!("no SPK data for body {} at et {}", body, et))
+    }
+
+    /// Position of `body` relative to the solar system barycenter, J2000.
+    fn ssb_position(&self, body: i32, et: f64) -> Result<[f64; 3], String> {
+        let mut pos = [0.0; 3];
+        let mut body = body;
+        while body != 0 {
+            let (daf, seg) = self.spk_segment(body, et)?;
+            let p = daf.spk_position(seg, et)?;
+            let p = if seg.frame == 1 {
+                p
+            } else {
+                mtxv(&self.frame_rotation_by_id(seg.frame, et)?, &p)
+            };
+            for i in 0..3 {
+                pos[i] += p[i];
+            }
+            body = seg.center;
+        }
+        Ok(pos)
+    }
+
+    /// Geometric position of `target` relative to `observer` in `frame`, km.
+    pub fn spkpos(
+        &self,
+        target: &str,
+        et: f64,
+        frame: &str,
+        observer: &str,
+    ) -> Result<[f64; 3], String> {
+        let t = self.ssb_position(self.body_id(target)?, et)?;
+        let o = self.ssb_position(self.body_id(observer)?, et)?;
+        let rel = [t[0] - o[0], t[1] - o[1], t[2] - o[2]];
+        Ok(mxv(&self.frame_rotation(frame, et)?, &rel))
+    }
+
+    fn frame_name(&self, id: i32) -> Result<String, String> {
+        match id {
+            1 => Ok("J2000".to_string()),
+            17 => Ok("ECLIPJ2000".to_string()),
+            _ => self
+                .string(&format!("FRAME_{}_NAME", id))
+                .ok_or(format!("unknown frame id {}", id)),
+        }
+    }
+
+    fn frame_rotation_by_id(&self, id: i32, et: f64) -> Result<Mat, String> {
+        self.frame_rotation(&self.frame_name(id)?, et)
+    }
+
+    /// Rotation from J2000 to `frame` at `et`.
+    pub fn frame_rotation(&self, frame: &str, et: f64) -> Result<Mat, String> {
+        let frame = frame.trim().to_uppercase();
+        match frame.as_str() {
+            "J2000" => return Ok(rot(3, 0.0)),
+            "ECLIPJ2000" => return Ok(rot(1, ECLIPJ2000_OBLIQUITY)),
+            _ => {}
+        }
+
+        let id = self.number(&format!("FRAME_{}", frame)).map(|id| id as i32);
+        let id = match (id, frame.strip_prefix("IAU_")) {
+            (Ok(id), _) => id,
+            (Err(_), Some(body)) => return self.body_rotation(self.body_id(body)?, et),
+            (Err(e), None) => return Err(e),
+        };
+        let class = self.number(&format!("FRAME_{}_CLASS", id))? as i32;
+        let class_id = self.number(&format!("FRAME_{}_CLASS_ID", id))? as i32;
+        match class {
+            2 => self.body_rotation(class_id, et),
+            4 => {
+                let relative = self
+                    .string(&format!("TKFRAME_{}_RELATIVE", id))
+                    .ok_or(format!("TK frame {} has no relative frame", id))?;
+                let to_relative = self.frame_rotation(&relative, et)?;
+                Ok(mxm(&self.tk_rotation(class_id)?, &to_relative))
+            }
+            c => Err(format!("frame class {} is not supported", c)),
+        }
+    }
+
+    /// Rotation from the relative frame to TK frame `id`.
+    fn tk_rotation(&self, id: i32) -> Result<Mat, String> {
+        let spec = self
+            .string(&format!("TKFRAME_{}_SPEC", id))
+            .unwrap_or_default()
+            .to_uppercase();
+        match spec.as_str() {
+            "ANGLES" => {
+                let angles = self
+                    .numbers(&format!("TKFRAME_{}_ANGLES", id))
+                    .ok_or(format!("TK frame {} has no angles", id))?;
+                let axes = self
+                    .numbers(&format!("TKFRAME_{}_AXES", id))
+                    .ok_or(format!("TK frame {} has no axes", id))?;
+                let units = self
+                    .string(&format!("TKFRAME_{}_UNITS", id))
+                    .unwrap_or_default()
+                    .to_uppercase();
+                let scale = match units.as_str() {
+                    "RADIANS" => 1.0,
+                    "DEGREES" => PI / 180.0,
+                    "ARCMINUTES" => PI / 180.0 / 60.0,
+                    "ARCSECONDS" => PI / 180.0 / 3600.0,
+                    u => return Err(format!("TK frame units {} are not supported", u)),
+                };
+                let r = |i: usize| rot(axes[i] as usize, angles[i] * scale);
+                Ok(mxm(&r(2), &mxm(&r(1), &r(0))))
+            }
+            "MATRIX" => {
+                let m = self
+                    .numbers(&format!("TKFRAME_{}_MATRIX", id))
+                    .ok_or(format!("TK frame {} has no matrix", id))?;
+                // stored column-major, TK to relative; we want the transpose
+                Ok([[m[0], m[1], m[2]], [m[3], m[4], m[5]], [m[6], m[7], m[8]]])
+            }
+            s => Err(format!("TK frame spec {} is not supported", s)),
+        }
+    }
+
+    /// Rotation from J2000 to a body-fixed frame, from binary PCK data when
+    /// loaded and the text PCK rotation model otherwise.
+    fn body_rotation(&self, class_id: i32, et: f64) -> Result<Mat, String> {
+        let binary = self
+            .dafs
+            .iter()
+            .rev()
+            .filter(|daf| daf.kind == DafKind::Pck)
+            .flat_map(|daf| daf.segments.iter().rev().map(move |seg| (daf, seg)))
+            .find(|(_, seg)| seg.body == class_id && seg.start_et <= et && et <= seg.end_et);
+        if let Some((daf, seg)) = binary {
+            let [phi, delta, w] = daf.pck_angles(seg, et)?;
+            let m = mxm(&rot(3, w), &mxm(&rot(1, delta), &rot(3, phi)));
+            return if seg.frame == 1 {
+                Ok(m)
+            } else {
+                Ok(mxm(&m, &self.frame_rotation_by_id(seg.frame, et)?))
+            };
+        }
+
+        let d = et / 86400.0;
+        let t = d / 36525.0;
+        let poly = |name: &str, x: f64| -> Result<f64, String> {
+            let c = self
+                .numbers(&format!("BODY{}_{}", class_id, name))
+                .ok_or(format!("no rotation model for body {}", class_id))?;
+            Ok(c.iter().rev().fold(0.0, |acc, c| acc * x + c))
+        };
+        let mut ra = poly("POLE_RA", t)?;
+        let mut dec = poly("POLE_DEC", t)?;
+        let mut w = poly("PM", d)?;
+
+        let barycenter = if class_id > 100 {
+            class_id / 100
+        } else {
+            class_id
+        };
+        if let Some(angles) = self.numbers(&format!("BODY{}_NUT_PREC_ANGLES", barycenter)) {
+            let theta: Vec<f64> = angles
+                .chunks(2)
+                .map(|a| (a[0] + a[1] * t).to_radians())
+                .collect();
+            let terms = |name: &str| self.numbers(&format!("BODY{}_NUT_PREC_{}", class_id, name));
+            if let Some(c) = terms("RA") {
+                ra += c
+                    .iter()
+                    .zip(&theta)
+                    .map(|(c, th)| c * th.sin())
+                    .sum::<f64>();
+            }
+            if let Some(c) = terms("DEC") {
+                dec += c
+                    .iter()
+                    .zip(&theta)
+                    .map(|(c, th)| c * th.cos())
+                    .sum::<f64>();
+            }
+            if let Some(c) = terms("PM") {
+                w += c
+                    .iter()
+                    .zip(&theta)
+                    .map(|(c, th)| c * th.sin())
+                    .sum::<f64>();
+            }
+        }
+
+        let (ra, dec, w) = (ra.to_radians(), dec.to_radians(), w.to_radians());
+        Ok(mxm(
+            &rot(3, w.rem_euclid(2.0 * PI)),
+            &mxm(&rot(1, PI / 2.0 - dec), &rot(3, PI / 2.0 + ra)),
+        ))
+    }
+
+    fn radii(&self, body: i32) -> Result<[f64; 3], String> {
+        let r = self
+            .numbers(&format!("BODY{}_RADII", body))
+            .ok_or(format!("no radii for body {}", body))?;
+        Ok([r[0], r[1], r[2]])
+    }
+}
+
+fn tokenize(line: &str, tokens: &mut Vec<String>) -> Result<(), String> {
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '(' || c == ')' || c == '=' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c == '+' && chars.clone().nth(1) == Some('=') {
+            tokens.push("+=".to_string());
+            chars.next();
+            chars.next();
+        } else if c == '\'' {
+            chars.next();
+            let mut s = String::from("'");
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        s.push('\'');
+                    }
+                    Some('\'') => break,
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(s);
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == '(' || c == ')' || c == '=' {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(s);
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(token: &str) -> Result<Value, String> {
+    if let Some(s) = token.strip_prefix('\'') {
+        return Ok(Value::Str(s.to_string()));
+    }
+    if let Some(date) = token.strip_prefix('@') {
+        return parse_date(date).map(Value::Num);
+    }
+    token
+        .replace(['D', 'd'], "E")
+        .parse()
+        .map(Value::Num)
+        .map_err(|_| format!("bad value {}", token))
+}
+
+/// `@1972-JAN-1` style dates, as UTC seconds past J2000.
+fn parse_date(date: &str) -> Result<f64, String> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let bad = || format!("bad date {}", date);
+    if parts.len() != 3 {
+        return Err(bad());
+    }
+    let year: i32 = parts[0].parse().map_err(|_| bad())?;
+    let month = match parts[1].to_uppercase().as_str() {
+        "JAN" => time::Month::January,
+        "FEB" => time::Month::February,
+        "MAR" => time::Month::March,
+        "APR" => time::Month::April,
+        "MAY" => time::Month::May,
+        "JUN" => time::Month::June,
+        "JUL" => time::Month::July,
+        "AUG" => time::Month::August,
+        "SEP" => time::Month::September,
+        "OCT" => time::Month::October,
+        "NOV" => time::Month::November,
+        "DEC" => time::Month::December,
+        _ => return Err(bad()),
+    };
+    let day: u8 = parts[2].parse().map_err(|_| bad())?;
+    let date = time::Date::from_calendar_date(year, month, day).map_err(|_| bad())?;
+    Ok((date.midnight().assume_utc().unix_timestamp() - J2000_UNIX) as f64)
+}
+
+/// Frame rotation about `axis` (1, 2 or 3) by `angle`.
+fn rot(axis: usize, angle: f64) -> Mat {
+    let (s, c) = angle.sin_cos();
+    match axis {
+        1 => [[1.0, 0.0, 0.0], [0.0, c, s], [0.0, -s, c]],
+        2 => [[c, 0.0, -s], [0.0, 1.0, 0.0], [s, 0.0, c]],
+        _ => [[c, s, 0.0], [-s, c, 0.0], [0.0, 0.0, 1.0]],
+    }
+}
+
+fn mxm(a: &Mat, b: &Mat) -> Mat {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn mxv(m: &Mat, v: &[f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn mtxv(m: &Mat, v: &[f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[1][0] * v[1] + m[2][0] * v[2],
+        m[0][1] * v[0] + m[1][1] * v[1] + m[2][1] * v[2],
+        m[0][2] * v[0] + m[1][2] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+fn reclat(v: &[f64; 3]) -> (f64, f64, f64) {
+    let r = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let lon = v[1].atan2(v[0]);
+    let lat = v[2].atan2(v[0].hypot(v[1]));
+    (r, lon, lat)
+}
+
+fn position_full(pos: [f64; 3]) -> PositionFull {
+    let (r, lon, lat) = reclat(&pos);
+    PositionFull {
+        x: pos[0],
+        y: pos[1],
+        z: pos[2],
+        r,
+        lon,
+        lat,
+        units: UnitSpecifier::Radians,
+    }
+}
+
+pub fn get_et(eph: &Ephemeris, t: DateTime) -> Result<f64, String> {
+    eph.utc_to_et(t)
+}
+
+pub fn earth_position_from_sun(eph: &Ephemeris, time: DateTime) -> Result<PositionFull, String> {
+    let et = eph.utc_to_et(time)?;
+    Ok(position_full(eph.spkpos("EARTH", et, "IAU_SUN", "SUN")?))
+}
+
+pub fn earth_position_ecliptic(eph: &Ephemeris, time: DateTime) -> Result<PositionFull, String> {
+    let et = eph.utc_to_et(time)?;
+    Ok(position_full(eph.spkpos(
+        "EARTH",
+        et,
+        "ECLIPJ2000",
+        "SUN",
+    )?))
+}
+
+pub fn solar_azel(eph: &Ephemeris, time: DateTime, pos: Position) -> Result<RAzEl, String> {
+    target_azel(eph, time, pos, "SUN")
+}
+
+/// As the CSPICE `target_azel`: `azlcpo` with the ELLIPSOID method, no
+/// aberration correction, azimuth counterclockwise from north and elevation
+/// positive up. Only sites on the Moon are supported.
+pub fn target_azel(
+    eph: &Ephemeris,
+    time: DateTime,
+    pos: Position,
+    target: &str,
+) -> Result<RAzEl, String> {
+    if pos.body != Body::Moon {
+        return Err(format!("sites must be on the moon, not {}", pos.body));
+    }
+    let et = eph.utc_to_et(time)?;
+    let radii = eph.radii(301)?;
+    let re = radii[0];
+    let flat = (radii[0] - radii[2]) / radii[0];
+    let pos = pos.to_radians();
+
+    // georec
+    let rp = re * (1.0 - flat);
+    let (slat, clat) = pos.lat.sin_cos();
+    let (slon, clon) = pos.lon.sin_cos();
+    let g = (re * re * clat * clat + rp * rp * slat * slat).sqrt();
+    let rho = re * re * clat / g + pos.alt * clat;
+    let z = rp * rp * slat / g + pos.alt * slat;
+    let obs = [rho * clon, rho * slon, z];
+
+    let rel = eph.spkpos(target, et, "MOON_ME_DE440_ME421", "MOON")?;
+    let rel = [rel[0] - obs[0], rel[1] - obs[1], rel[2] - obs[2]];
+
+    // topocentric frame: +Z along the surface normal, +X north, +Y west
+    let up = [clat * clon, clat * slon, slat];
+    let north = [-slat * clon, -slat * slon, clat];
+    let west = [
+        up[1] * north[2] - up[2] * north[1],
+        up[2] * north[0] - up[0] * north[2],
+        up[0] * north[1] - up[1] * north[0],
+    ];
+    let local = mxv(&[north, west, up], &rel);
+    let (r, az, el) = reclat(&local);
+
+    Ok(RAzEl {
+        az: az.rem_euclid(2.0 * PI),
+        el,
+        r,
+        units: UnitSpecifier::Radians,
+        horizon_el: None,
+    })
+}
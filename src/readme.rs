@@ -34,9 +34,13 @@ pub const README: &str = "https://api.jodavaho.io/s
         * t = optional time.
         * f = optional format of the response.
         * u = optional 'units' specification.
+        * terrain = optional, true to place the site on the
+          loaded DSK terrain instead of the ellipsoid.
 
-    /moon/solartime - returns the solar time at present,
-        given a position on the moon's surface.
+    /<body>/solartime - returns the solar time at present,
+        given a position on the surface of <body>: moon,
+        earth, mars, mercury or europa. /moon/solartime is
+        the lunar instance.
 
         OUPUT example: '02:48 AM'
 
@@ -44,8 +48,9 @@ pub const README: &str = "https://api.jodavaho.io/s
         * t = optional time.
         * f = optional format of the response.
 
-    /moon/sun/* - returns pointing information to the sun,
-        where '*' is a return type.
+    /<body>/sun/* - returns pointing information to the sun
+        from a site on <body> (as for /<body>/solartime), where
+        '*' is a return type.
 
         Currently, only 'azel' is supported.
 
@@ -57,6 +62,22 @@ pub const README: &str = "https://api.jodavaho.io/s
         * t = optional time.
         * f = optional format of the response.
         * u = optional 'units' specification.
+        * terrain = optional, true to place the site on the
+          loaded DSK terrain instead of the ellipsoid.
+
+        With terrain, the site sits on the DSK surface with its
+        altitude above it, and elevation is measured from the
+        level plane there. The response adds the elevation of
+        the DSK skyline toward the Sun, out to 150 km ('horizon
+        el', horizon_el in JSON): the Sun is hidden while it is
+        below that. Without a DSK loaded, terrain requests
+        return 400. Terrain is only for the moon.
+
+        Sites use the body's IAU frame (MOON_ME for the moon,
+        ITRF93 or IAU_EARTH for earth). Mercury is in the
+        default ephemeris; mars and europa need their SPKs
+        (e.g. mar097.bsp, jup365.bsp) listed in the server's
+        MOONTIME_KERNELS, and return 400 without them.
 
     /sun/earth - returns Earth's position from Sun's
         rotating reference frame (IAU_SUN). Returns both
@@ -80,6 +101,529 @@ pub const README: &str = "https://api.jodavaho.io/s
         * f = optional format of the response.
         * u = optional 'units' specification for angles.
 
+    /fit - precomputes Chebyshev fits of sun and Earth
+        pointing for a site over a time window, for clients
+        that need many queries per second. The fit is checked
+        against SPICE between its nodes, and the worst errors
+        are returned as its error bound.
+
+        OUTPUT example: 'id: 1, segments: 1, degree: 12,
+        sun error: 0.0000000001 (0.0003 km), earth error: ...'
+
+        * start = optional start of the window (default now).
+        * end = optional end of the window (default start + 1 day).
+        * lat, lon = optional site in degrees (default CADRE).
+        * alt = optional altitude in km (default 0).
+        * segment = optional segment length in seconds (default 86400).
+        * degree = optional polynomial degree (default 12).
+        * f = optional format of the response.
+        * u = optional 'units' specification for the error bound.
+
+    /fit/<id> - evaluates fit <id> at time t, returning sun and
+        Earth az/el without waiting on SPICE.
+
+        OUTPUT example: 'sun: az: 93.6, el: 35.0, r: 151559808.5,
+        u: degrees; earth: az: ...'
+
+        * t = optional time, inside the fit window.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /status - returns the state of the SPICE worker: how
+        many requests are queued, the queue capacity, the
+        per-request timeout, how many computations have
+        panicked (the server recovers from each), and the
+        number of worker processes if the server runs a pool.
+
+        OUTPUT example: 'queue: 0/64, timeout: 10000 ms,
+        panics: 0, processes: 0 (0 pending)'
+
+        * f = optional format of the response.
+
+        Requests are queued for a single SPICE thread. When
+        the queue is full, endpoints return 503; when a
+        computation takes too long, they return 504, though
+        it runs on to the end and requests behind it wait.
+
+    /cache - returns response cache statistics: hits,
+        misses, requests that waited on an identical
+        in-flight request, and the cache size.
+
+        OUTPUT example: 'hits: 12, misses: 3, coalesced: 1,
+        entries: 3/1024, ttl: 3600 s, kernels: 5c1f...'
+
+        * f = optional format of the response.
+
+        Only requests with an explicit t are cached, since
+        those are deterministic for a given set of kernels.
+
+    /moon/subsolar, /moon/subearth - returns the point on
+        the Moon's surface with the Sun (or Earth) directly
+        overhead, as planetocentric lat/lon, with the local
+        solar time at that point.
+
+        OUTPUT example: 'lat: 1.2, lon: -23.4, frame: MOON_ME,
+        solar time: 12:00 PM, u: degrees'
+
+        * t = optional time.
+        * frame = optional lunar frame, 'me' (mean Earth, the
+          default) or 'pa' (principal axes).
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /moon/terminator - returns the day/night terminator on
+        the Moon as planetocentric lon/lat points, sorted by
+        longitude. With f=geojson, returns a GeoJSON Feature
+        in degrees: the terminator as a LineString running from
+        the antimeridian round to it again, or the lit hemisphere
+        as a Polygon, for overlaying on basemaps.
+
+        OUTPUT example (f=geojson): '{\"type\":\"Feature\",
+        \"geometry\":{\"type\":\"LineString\",\"coordinates\":
+        [[-180.0,12.3],...]},\"properties\":{...}}'
+
+        * t = optional time.
+        * frame = optional lunar frame, 'me' (default) or 'pa'.
+        * kind = optional 'umbral' (edge of total shadow, the
+          default) or 'penumbral' (edge of full sunlight).
+        * n = optional number of points (default 360, max 3600).
+        * shape = optional GeoJSON shape, 'line' (default) or
+          'polygon'.
+        * f = optional format of the response.
+        * u = optional 'units' specification (txt and json only).
+
+    /moon/terminator/distance, /cadre/terminator/distance -
+        returns the great-circle distance from a site to the
+        nearest sunrise and sunset terminator, the terminator's
+        ground speed along the site's parallel, and estimated
+        seconds until each arrives ('never' in polar day or
+        night). The terminator is where the Sun's center is on
+        the horizon.
+
+        OUTPUT example: 'sun up: true, sunrise: 2101.3 km
+        (1812345.2 s), sunset: 631.0 km (509012.7 s), ground
+        speed: 15.2 km/h'
+
+        * lat, lon = optional site in degrees
+          (/moon/terminator/distance only, default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+
+    /moon/illumination, /cadre/illumination - returns the
+        incidence (Sun from the surface normal), emission
+        (observer from the normal) and phase (Sun to observer)
+        angles at a site, with its local true solar time. The
+        observer is a SPICE body, or a camera at another site.
+
+        OUTPUT example: 'incidence: 71.2, emission: 18.4, phase:
+        88.9, solar time: 02:48 PM, observer: EARTH, u: degrees'
+
+        * lat, lon = optional site in degrees
+          (/moon/illumination only, default CADRE).
+        * alt = optional altitude in km (default 0).
+        * observer = optional SPICE body name or id (default
+          EARTH), e.g. a spacecraft with a loaded SPK.
+        * olat, olon = optional observer site on the Moon in
+          degrees; overrides observer.
+        * oalt = optional camera height in km (default 0).
+        * terrain = optional, true to use the DSK terrain: the
+          angles are then from the local facet's normal.
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /moon/power, /cadre/power - returns the sunlight on a flat
+        panel: the cosine of the Sun's incidence on the panel,
+        the fraction of the solar disk above the horizon, the
+        Sun's range, irradiance in W/m2 (1361 W/m2 scaled by
+        range) and power in W. Given 'until', also the energy in
+        Wh collected between t and until. Terrain is ignored.
+
+        OUTPUT example: 'irradiance: 1052.3 W/m2, power: 526.1 W,
+        cos incidence: 0.77, sun fraction: 1, sun range:
+        151633297.2 km, energy: 3120.4 Wh'
+
+        * tilt = required panel tilt from horizontal.
+        * azimuth = required direction the panel faces,
+          measured like /sun azimuths.
+        * area = required panel area in m2.
+        * units = optional units of tilt and azimuth (default
+          degrees).
+        * lat, lon = optional site in degrees (/moon/power only,
+          default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional time, the start of the window.
+        * until = optional end of the window.
+        * dt = optional integration step in seconds (default
+          600).
+        * horizon = optional horizon mask id for the site; the
+          disk fraction is then above the skyline.
+        * f = optional format of the response.
+
+    /moon/shadow, /cadre/shadow - returns the length and
+        direction of the shadows of upright objects (a mast, a
+        rover, a boulder) on flat ground at a site, from the
+        Sun's elevation and azimuth. Given 'below', also the
+        first time each shadow gets shorter than that length,
+        searching up to a month ahead.
+
+        OUTPUT example: 'sun az: 93.6, sun el: 35.0, direction:
+        273.6, u: degrees
+        height: 2.5, length: 3.57, shorter at: ...'
+
+        * h = required list of object heights (at most 16),
+          comma separated in a query string: h=1.5,2,0.3.
+          Lengths are returned in the same unit.
+        * below = optional shadow length to wait for.
+        * horizon = optional horizon mask id for the site; there
+          is no shadow while the Sun is behind the skyline.
+        * lat, lon = optional site in degrees (/moon/shadow only,
+          default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /moon/earth, /cadre/earth - returns how Earth looks from
+        a site: the lit fraction of its disk, its phase angle
+        (Sun-Earth-site, 0 at full Earth), its angular diameter,
+        the position angle of its north pole (from the local
+        zenith, positive to the left facing Earth), and the
+        planetocentric lon/lat on Earth with the site overhead,
+        i.e. which side of Earth faces the site.
+
+        OUTPUT example: 'illuminated: 0.31, phase: 112.6,
+        diameter: 1.87, north pa: 341.2, sub lon: -71.4, sub
+        lat: 3.1, range: 390123.4 km, orientation: ITRF93,
+        u: degrees'
+
+        * lat, lon = optional site in degrees (/moon/earth only,
+          default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /moon/earth/track, /cadre/earth/track - returns Earth's
+        az/el from a site at each step over a window: the small
+        monthly loop libration draws in the sky. Also returns
+        the az/el box the loop stays in, for sizing gimbal
+        range. f=csv returns 't,az,el' lines, and f=svg a plot
+        of the loop, azimuth increasing to the left as seen
+        facing Earth, with dates marked along it.
+
+        OUTPUT example: 'points: 673, az: 262.1 to 276.8, el:
+        22.4 to 36.0, u: degrees ...'
+
+        * lat, lon = optional site in degrees
+          (/moon/earth/track only, default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional start of the track.
+        * until = optional end of the track (default four weeks
+          after t).
+        * dt = optional step in seconds (default 3600).
+        * f = optional format: txt, json, csv or svg.
+        * u = optional 'units' specification.
+
+    /moon/dsn, /cadre/dsn - returns the passes of the Moon,
+        or a lunar site, over Deep Space Network stations: when
+        each station has it above its elevation mask, and the
+        highest elevation reached. Also a handover schedule
+        that stays on each station until it sets, then moves
+        to the station in view that stays up longest.
+
+        OUTPUT example: 'target: MOON, passes: 3, orientation:
+        ITRF93, u: degrees
+        MADRID: ... to ..., max el 61.2 at ...
+        schedule:
+        MADRID: ... to ...
+        GOLDSTONE: ... to ...'
+
+        * lat, lon = optional lunar site in degrees (/moon/dsn
+          only; without it, the Moon's center).
+        * alt = optional altitude in km (default 0).
+        * t = optional start of the search.
+        * until = optional end of the search (default a day
+          after t).
+        * dt = optional search step in seconds (default 300).
+        * dsn = optional, false to leave out the built-in
+          Goldstone, Madrid and Canberra complexes.
+        * stations = optional list of extra stations, each
+          {name, lat, lon, alt, mask, units}: geodetic lat/lon,
+          alt in km, and the elevation mask (default 6 degrees).
+          A query string gives them as name:lat:lon[:alt[:mask]]
+          in degrees, separated by ';'.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /moon/comms, /cadre/comms - returns the link geometry
+        between an Earth station and a lunar site or spacecraft:
+        range, range rate (positive when opening), one-way and
+        round-trip light time, the Doppler shift of a carrier
+        one-way and two-way (coherent turnaround), free-space
+        path loss, and the target's elevation at the station.
+        Geometric, from the station's and target's states.
+
+        OUTPUT example: 'station: GOLDSTONE, target: site (7.5,
+        -59, 0 km), range: 372011.6 km, range rate: 0.241 km/s,
+        light time: 1.2409 s, round trip: 2.4818 s, doppler:
+        -6752.1 Hz one-way, -13504.2 Hz two-way at 8400000000
+        Hz, path loss: 222.3 dB, el: 41.7, orientation: ITRF93,
+        u: degrees'
+
+        * station = optional DSN complex, GOLDSTONE (default),
+          MADRID or CANBERRA.
+        * s = optional user-defined station {name, lat, lon,
+          alt, units}, or name:lat:lon[:alt] in a query string,
+          as for /moon/dsn; overrides station.
+        * lat, lon = optional lunar site in degrees (/moon/comms
+          only, default CADRE).
+        * alt = optional antenna height in km (default 0).
+        * target = optional SPICE body name or id of a
+          spacecraft with a loaded SPK; overrides lat, lon.
+        * freq = optional carrier in Hz (default 8.4e9).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /earth/moon - returns the Moon's az/el, distance and
+        semidiameter for an observer on Earth, and whether it
+        is up (upper limb above the horizon, allowing 34' of
+        refraction). Az/el are topocentric and geometric, az
+        counterclockwise from north as elsewhere.
+
+        OUTPUT example: 'az: 241.7, el: 38.2, r: 371204.5 km,
+        semidiameter: 0.268, up: true, orientation: ITRF93,
+        u: degrees'
+
+        * olat, olon = observer on Earth: geodetic, in degrees.
+        * oalt = optional observer altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /earth/moon/riseset - returns moonrises and moonsets for
+        an observer on Earth over a window, with the azimuth
+        of each, and whether the Moon is up at the start.
+
+        OUTPUT example: 'up: false, events: 2, orientation:
+        ITRF93, u: degrees
+        rise ... az: 71.3
+        set ... az: 285.0'
+
+        * olat, olon, oalt = observer on Earth, as for
+          /earth/moon.
+        * t = optional start of the search.
+        * until = optional end of the search (default a day
+          after t).
+        * dt = optional search step in seconds (default 600).
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /earth/site, /earth/cadre - returns where a lunar site
+        is in an Earth observer's sky: its az/el and distance,
+        the emission angle (site zenith to observer), whether
+        it is on the visible disk, whether the Sun is up there,
+        and whether it is visible (on the disk and above the
+        observer's horizon).
+
+        OUTPUT example: 'az: 241.9, el: 38.0, r: 370011.2 km,
+        emission: 59.7, on disk: true, sunlit: true, visible:
+        true, orientation: ITRF93, u: degrees'
+
+        * olat, olon, oalt = observer on Earth, as for
+          /earth/moon.
+        * lat, lon = optional lunar site in degrees
+          (/earth/site only, default CADRE).
+        * alt = optional site altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /mars/time - returns Mars time at a site: Local Mean
+        and Local True Solar Time, Coordinated Mars Time, the
+        Mars Sol Date, Ls and the equation of time (Allison &
+        McEwen, as in Mars24), and with a mission the sol
+        number counted from its landing. When a Mars SPK is
+        loaded (MOONTIME_KERNELS), the true solar time from
+        SPICE's et2lst and its difference are included.
+
+        OUTPUT example: 'curiosity sol 1001, lmst: 09:27:32,
+        ltst: 08:41:25, mtc: 00:17:46, msd: 50270.01234,
+        ls: 350.9, eot: -11.53, u: degrees'
+
+        * mission = optional mission name (see /missions).
+        * lat, lon = optional site in degrees, planetocentric,
+          east longitude; the mission's landing site by
+          default. One of lat/lon and mission is required.
+        * alt = optional altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /missions - returns the known missions: their site,
+        launch and landing times, the number of the landing
+        sol and any planned events. The Mars landers are
+        built in; the server's MOONTIME_MISSIONS names a JSON
+        file of more, as [{name, site, landing, first_sol,
+        launch, events: [{name, t}]}], which replace built-ins
+        of the same name.
+
+        * f = optional format of the response.
+
+    /<body>/clock, /cadre/clock - returns mission elapsed
+        time since landing or launch, the local day since
+        landing (lunar days on the moon, sols on mars), and
+        countdowns to what is next: the mission's planned
+        events and, on the moon, sunrise, sunset, earthrise,
+        earthset (over the geometric horizon) and eclipses
+        with the Sun up, all within about a month.
+
+        OUTPUT example: 'Lunar day 3, 02:14:00 until sunset
+        sunset: 02:14:00 (...)
+        earthset: 9d 11:02:40 (...)
+        sunrise: 16d 20:31:05 (...)'
+
+        * mission = optional mission name (see /missions);
+          /cadre/clock uses a mission named cadre if there
+          is one.
+        * since = optional epoch, 'landing' (default) or
+          'launch'.
+        * epoch = optional time to count from instead of the
+          mission's. Without a mission or an epoch, only the
+          countdowns are returned.
+        * lat, lon = optional site in degrees (/<body>/clock
+          only); the mission's landing site by default, or
+          CADRE on the moon.
+        * alt = optional altitude in km (default 0).
+        * t = optional time.
+        * f = optional format of the response.
+
+        Days are numbered from the one containing the landing,
+        which is the mission's first sol, or 1 without a
+        mission.
+
+    /moon/eclipses, /cadre/eclipses - returns lunar eclipses
+        as seen from a site: windows when Earth covers part
+        (penumbral) or all (umbral) of the Sun, with the time
+        and fraction of the solar disk covered at greatest
+        eclipse, and whether the Sun is up then. Terrain is
+        ignored.
+
+        OUTPUT example: 'eclipses: 1
+        2025-03-14 03:57:12.0 +00:00:00 to ..., max 1 at
+        2025-03-14 06:58:40.0 +00:00:00, sun up: true
+          penumbral: ... to ...
+          umbral: ... to ...'
+
+        * lat, lon = optional site in degrees (/moon/eclipses
+          only, default CADRE).
+        * alt = optional altitude in km (default 0).
+        * t = optional start of the search.
+        * until = optional end of the search (default a year
+          after t, at most about 5 years).
+        * f = optional format of the response.
+
+    /moon/map - returns, for each node of a lat/lon grid, the
+        percentage of the window with the Sun up, with Earth up,
+        and with both, as seen from the ground. With terrain,
+        'up' means above the skyline traced from the DSK for
+        each node. f=csv returns 'lat,lon,sun,earth,both' lines,
+        and f=pgm a plain PGM raster of one layer, north up and
+        west left, gray level = percentage (0 to 100).
+
+        OUTPUT example (f=csv): 'lat,lon,sun,earth,both
+        -89.5,0,82.1,41.0,33.6 ...'
+
+        * lat_min, lat_max, lon_min, lon_max = required bounds
+          of the region.
+        * spacing = required grid spacing, at most 10000 nodes
+          in all.
+        * units = optional units of the region (default
+          degrees).
+        * until = required end of the window.
+        * t = optional start of the window.
+        * dt = optional sampling step in seconds (default 3600).
+        * terrain = optional, true to use DSK horizon masks (at
+          most 400 nodes).
+        * layer = optional raster layer, 'sun', 'earth' or
+          'both' (default).
+        * f = optional format: txt, json, csv or pgm.
+        * u = optional 'units' specification.
+
+        The grid is computed a few nodes per job, so long
+        windows take more jobs rather than longer ones; jobs
+        are spread across worker processes when the server runs
+        a pool.
+
+    /horizon - creates a horizon mask for a site: the
+        elevation of the local skyline at each azimuth, read
+        from CSV lines 'az,el' in degrees, or traced once from
+        the loaded DSK out to 150 km. Returns the mask id for
+        use with the endpoints below and with 'horizon' on
+        /power and /shadow. POST only, as it creates the mask.
+
+        OUTPUT example: 'id: 1, source: dsk, points: 360,
+        max el: 4.2, u: degrees'
+
+        * p = optional position (default CADRE).
+        * csv = optional mask as CSV text; without it the mask
+          is computed from the DSK.
+        * step = optional azimuth step for DSK masks in degrees
+          (default 1, 0.5 to 10).
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+        Masks, like fits, are kept by the process that made
+        them: on Lambda, or behind several servers, another
+        instance may answer 404 for the id.
+
+    /horizon/<id> - returns mask <id> as azimuth and elevation
+        lists, or with f=svg as an SVG polar plot (north up,
+        zenith at the center).
+
+        * f = optional format: txt, json or svg.
+        * u = optional 'units' specification.
+
+    /horizon/<id>/visibility - returns whether the Sun or Earth
+        is above the mask at t, and with 'until' the times it
+        rises above or sets behind the skyline in between.
+
+        OUTPUT example: 'target: SUN, visible: true, az: 93.6,
+        el: 5.1, mask el: 2.3, u: degrees
+        set 2024-06-10 04:12:55.0 +00:00:00 az: 268.1'
+
+        * target = optional SUN (default) or EARTH.
+        * t = optional time, the start of the window.
+        * until = optional end of the window.
+        * dt = optional search step in seconds (default 600).
+        * f = optional format of the response.
+        * u = optional 'units' specification.
+
+    /cadre/live, /moon/live - streams sun and Earth az/el,
+        local solar time and ET for a site as Server-Sent
+        Events, one 'sample' event every dt seconds. Clients
+        watching the same site at the same cadence share one
+        computation; at most 16 different streams run at once,
+        and new ones past that get 503. GET only, and not
+        available on Lambda.
+
+        OUTPUT example: 'event: sample
+        data: t: 2024-06-01 12:00:00.0 +00:00:00, et: 770515269.18,
+        solar time: 02:48 PM, sun: az: 93.6, ...; earth: az: ...'
+
+        * lat, lon = optional site in degrees (/moon/live only,
+          default CADRE).
+        * alt = optional altitude in km (default 0).
+        * body = optional body the site is on (default moon).
+        * dt = optional cadence in seconds (default 1, minimum 0.1).
+        * f = optional format of each sample.
+        * u = optional 'units' specification.
+
+        Servers built with the 'ws' feature also serve the same
+        samples over WebSocket at /cadre/live/ws and /moon/live/ws.
+
     Path variants for Earth position endpoints:
         /sun/earth, /sun/earth/xyz, /sun/earth/spherical
         /ecliptic/earth, /ecliptic/earth/xyz, /ecliptic/earth/spherical
@@ -104,6 +648,13 @@ pub const README: &str = "https://api.jodavaho.io/s
 
     should both return '686361669.1823467'
 
+    Earth-fixed results (/moon/earth, /moon/dsn, /moon/comms,
+    /earth/*) report the Earth frame used as 'orientation':
+    ITRF93 when the high-precision Earth PCK
+    (earth_latest_high_prec.bpc) is loaded and covers the
+    time, for arcsecond pointing and Doppler, or the
+    low-precision IAU_EARTH model otherwise.
+
 § Input Parameter Information:
 
     * t = [ <iso8601> | None]
@@ -113,15 +664,28 @@ pub const README: &str = "https://api.jodavaho.io/s
           * 2021-10-01T12:00:00.00Z
 
     * pos = { \"lat\":double, \"lon\":double,
-              \"alt\":double, \"units\": <units specifier> }
+              \"alt\":double, \"units\": <units specifier>,
+              \"body\": <body> }
 
             <units specifier> = [\"degrees\" | \"radians\" ]
+            <body> = [\"moon\" | \"earth\" | \"mars\" |
+                      \"mercury\" | \"europa\" ], default moon.
+            lat/lon are geodetic on the body's ellipsoid, which
+            on the moon is a sphere, so planetocentric. The
+            /<body>/... routes take the body from the path;
+            endpoints that only model lunar sites reject other
+            bodies.
 
 § Output Parameter Information:
 
-    * f = ['json'| None] is the format of the response.
+    * f = ['json'| 'geojson' | 'svg' | 'csv' | 'pgm' | None] is the format
+      of the response.
       json may return extra information. If not specified,
       the response is a string w/ just the most important payload.
+      geojson is for routes that return geometry, such as
+      /moon/terminator, svg for plots such as /horizon/<id>,
+      and csv and pgm for grids such as /moon/map; elsewhere
+      they return 400.
 
     * u = ['radians'|'degrees'| None] is the units of the response.
       If not specified, the response is in degrees.
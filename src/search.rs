@@ -0,0 +1,85 @@
+//! Searches over time shared by the event finders.
+//!
+//! Times are ET seconds. A test or function is sampled at a fixed step, so
+//! anything that starts and ends between two samples is missed; callers
+//! pick steps shorter than the events they look for.
+
+/// Precision of the times found, s.
+pub(crate) const TOLERANCE_S: f64 = 1.0;
+
+/// Times in `[a, b]` where `test` changes, with the value it changes to.
+/// Samples every `step` seconds and bisects each change to the first
+/// time with the new value. Lazy, so a caller can stop at the first one.
+pub(crate) fn changes(
+    a: f64,
+    b: f64,
+    step: f64,
+    mut test: impl FnMut(f64) -> bool,
+) -> impl Iterator<Item = (f64, bool)> {
+    let (mut lo, mut at_lo) = (a, test(a));
+    std::iter::from_fn(move || {
+        while lo < b {
+            let hi = (lo + step).min(b);
+            let at_hi = test(hi);
+            let (start, was) = (lo, at_lo);
+            (lo, at_lo) = (hi, at_hi);
+            if at_hi != was {
+                let (mut l, mut h) = (start, hi);
+                while h - l > TOLERANCE_S {
+                    let mid = 0.5 * (l + h);
+                    if test(mid) == was {
+                        l = mid;
+                    } else {
+                        h = mid;
+                    }
+                }
+                return Some((h, at_hi));
+            }
+        }
+        None
+    })
+}
+
+/// Pairs up the `changes` of a test that is `first` at `a` into the spans
+/// in `[a, b]` where it holds.
+pub(crate) fn spans(
+    first: bool,
+    a: f64,
+    b: f64,
+    changes: impl Iterator<Item = f64>,
+) -> Vec<(f64, f64)> {
+    let mut edges = Vec::new();
+    if first {
+        edges.push(a);
+    }
+    edges.extend(changes);
+    if edges.len() % 2 == 1 {
+        edges.push(b);
+    }
+    edges.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// Time in `[a, b]` where `f` is greatest: the best of samples every
+/// `step` seconds, refined by golden-section search a step either side.
+pub(crate) fn greatest(a: f64, b: f64, step: f64, mut f: impl FnMut(f64) -> f64) -> f64 {
+    let (mut best, mut top) = (a, f(a));
+    let mut at = a + step;
+    while at <= b {
+        let v = f(at);
+        if v > top {
+            (best, top) = (at, v);
+        }
+        at += step;
+    }
+    let (mut lo, mut hi) = ((best - step).max(a), (best + step).min(b));
+    let g = 0.5 * (5f64.sqrt() - 1.0);
+    while hi - lo > TOLERANCE_S {
+        let (x1, x2) = (hi - g * (hi - lo), lo + g * (hi - lo));
+        if f(x1) > f(x2) {
+            hi = x2;
+        } else {
+            lo = x1;
+        }
+    }
+    0.5 * (lo + hi)
+}
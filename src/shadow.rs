@@ -0,0 +1,165 @@
+//! Shadows cast by upright objects at a site.
+//!
+//! Objects stand on a flat local plane and are lit by the Sun's center, so
+//! a shadow of height `h` is `h / tan(el)` long and points away from the
+//! Sun. Lengths are in the same unit as the heights. With a horizon mask, an
+//! object has no shadow of its own while the Sun is behind the skyline.
+
+use crate::horizon::HorizonMask;
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::SpiceLock;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+/// Most objects in one request; each may need a month-long search.
+pub const MAX_OBJECTS: usize = 16;
+/// How far ahead to look for a shadow to shrink, s: a little over a synodic month.
+const SEARCH_SPAN_S: f64 = 31.0 * 86400.0;
+const SEARCH_STEP_S: f64 = 600.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectShadow {
+    pub height: f64,
+    /// `None` while the Sun is down or behind the skyline.
+    pub length: Option<f64>,
+    /// First time from `t` the shadow is shorter than the requested length,
+    /// `None` if no length was given or it doesn't happen within a month.
+    #[serde(with = "default_datetime_standard::option", default)]
+    pub shorter_at: Option<DateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Shadows {
+    pub sun_az: f64,
+    pub sun_el: f64,
+    /// Azimuth the shadows point along, measured like `sun_az`.
+    pub direction: f64,
+    pub shadows: Vec<ObjectShadow>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for Shadows {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "sun az: {}, sun el: {}, direction: {}, u: {}",
+            self.sun_az, self.sun_el, self.direction, self.units
+        )?;
+        for shadow in &self.shadows {
+            let length = shadow.length.map_or("none".to_string(), |l| l.to_string());
+            write!(f, "\nheight: {}, length: {}", shadow.height, length)?;
+            if let Some(at) = shadow.shorter_at {
+                write!(f, ", shorter at: {}", at)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Angular for Shadows {
+    fn to_degrees(&self) -> Shadows {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Shadows {
+                sun_az: self.sun_az.to_degrees(),
+                sun_el: self.sun_el.to_degrees(),
+                direction: self.direction.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Shadows {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Shadows {
+                sun_az: self.sun_az.to_radians(),
+                sun_el: self.sun_el.to_radians(),
+                direction: self.direction.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Whether the Sun is at or above `el` and clear of the skyline.
+fn sun_above(
+    lock: &SpiceLock,
+    et: f64,
+    pos: Position,
+    frame: &str,
+    el: f64,
+    mask: Option<&HorizonMask>,
+) -> bool {
+    let sun = crate::target_azel_et(lock, et, pos, frame, "SUN");
+    sun.el >= el && mask.is_none_or(|mask| mask.clearance(&sun) > 0.0)
+}
+
+/// First ET in `[et, et + SEARCH_SPAN_S]` with the Sun at or above `el`.
+fn first_above(
+    lock: &SpiceLock,
+    et: f64,
+    pos: Position,
+    frame: &str,
+    el: f64,
+    mask: Option<&HorizonMask>,
+) -> Option<f64> {
+    let above = |et: f64| sun_above(lock, et, pos, frame, el, mask);
+    if above(et) {
+        return Some(et);
+    }
+    crate::search::changes(et, et + SEARCH_SPAN_S, SEARCH_STEP_S, above)
+        .next()
+        .map(|(at, _)| at)
+}
+
+/// Shadows of objects `heights` tall at `pos` at `t`, and when each first
+/// gets shorter than `below`.
+pub fn shadows(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+    heights: &[f64],
+    below: Option<f64>,
+    mask: Option<&HorizonMask>,
+) -> Result<Shadows, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    crate::spice_try(&lock, || {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        let frame = crate::body_frame(&lock, pos.body, &[et, et + SEARCH_SPAN_S]);
+        let sun = crate::target_azel_et(&lock, et, pos, frame, "SUN");
+        let lit = sun.el > 0.0 && mask.is_none_or(|mask| mask.clearance(&sun) > 0.0);
+
+        let shadows = heights
+            .iter()
+            .map(|&height| {
+                let length = lit.then(|| height / sun.el.tan());
+                let shorter_at = below.and_then(|below| {
+                    // the elevation at which the shadow is exactly `below` long
+                    let el = height.atan2(below);
+                    let found = first_above(&lock, et, pos, frame, el, mask)?;
+                    Some(t + time::Duration::seconds_f64(found - et))
+                });
+                ObjectShadow {
+                    height,
+                    length,
+                    shorter_at,
+                }
+            })
+            .collect();
+
+        Shadows {
+            sun_az: sun.az,
+            sun_el: sun.el,
+            direction: (sun.az + PI).rem_euclid(2.0 * PI),
+            shadows,
+            units: UnitSpecifier::Radians,
+        }
+    })
+}
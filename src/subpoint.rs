@@ -0,0 +1,144 @@
+//! Subsolar and sub-Earth points on the Moon.
+//!
+//! The observer-independent counterpart to `solar_azel`: where on the surface
+//! the Sun or Earth is overhead. Points are on the reference ellipsoid, found
+//! with the NEAR POINT method, geometric (no aberration correction).
+
+use crate::types::*;
+
+use serde::{Deserialize, Serialize};
+use spice::{cstr, SpiceLock};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubPoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub frame: MoonFrame,
+    /// Local solar time at the point.
+    pub solar_time: String,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for SubPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "lat: {}, lon: {}, frame: {}, solar time: {}, u: {}",
+            self.lat, self.lon, self.frame, self.solar_time, self.units
+        )
+    }
+}
+
+impl Angular for SubPoint {
+    fn to_degrees(&self) -> SubPoint {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => SubPoint {
+                lat: self.lat.to_degrees(),
+                lon: self.lon.to_degrees(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> SubPoint {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => SubPoint {
+                lat: self.lat.to_radians(),
+                lon: self.lon.to_radians(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+/// Planetocentric (lat, lon) in radians of the point below `target`, which
+/// is "SUN" (subsolar) or "EARTH" (sub-observer).
+fn surface_point(
+    lock: &SpiceLock,
+    t: DateTime,
+    frame: MoonFrame,
+    target: &str,
+) -> Result<(f64, f64), String> {
+    let et = lock.str2et(crate::to_cspice_string(t).as_str());
+    let mut spoint = [0.0; 3];
+    let mut trgepc = 0.0;
+    let mut srfvec = [0.0; 3];
+    let (mut r, mut lon, mut lat) = (0.0, 0.0, 0.0);
+    crate::spice_try(lock, || unsafe {
+        if target == "SUN" {
+            spice::c::subslr_c(
+                cstr!("NEAR POINT/ELLIPSOID"),
+                cstr!("MOON"),
+                et,
+                cstr!(frame.spice_name()),
+                cstr!("NONE"),
+                cstr!("EARTH"),
+                spoint.as_mut_ptr(),
+                &mut trgepc,
+                srfvec.as_mut_ptr(),
+            );
+        } else {
+            spice::c::subpnt_c(
+                cstr!("NEAR POINT/ELLIPSOID"),
+                cstr!("MOON"),
+                et,
+                cstr!(frame.spice_name()),
+                cstr!("NONE"),
+                cstr!(target),
+                spoint.as_mut_ptr(),
+                &mut trgepc,
+                srfvec.as_mut_ptr(),
+            );
+        }
+        spice::c::reclat_c(spoint.as_mut_ptr(), &mut r, &mut lon, &mut lat);
+    })?;
+    Ok((lat, lon))
+}
+
+fn sub_point(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    frame: MoonFrame,
+    target: &str,
+) -> Result<SubPoint, String> {
+    let (lat, lon) = surface_point(&crate::lock_spice(&sl_mutex), t, frame, target)?;
+    let pos = Position {
+        lat,
+        lon,
+        alt: 0.0,
+        units: UnitSpecifier::Radians,
+        body: Body::Moon,
+    };
+    Ok(SubPoint {
+        lat,
+        lon,
+        frame,
+        solar_time: crate::solar_time(sl_mutex, t, pos)?,
+        units: UnitSpecifier::Radians,
+    })
+}
+
+/// The point on the Moon with the Sun at the zenith.
+pub fn subsolar_point(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    frame: MoonFrame,
+) -> Result<SubPoint, String> {
+    sub_point(sl_mutex, t, frame, "SUN")
+}
+
+/// The point on the Moon with the Earth at the zenith.
+pub fn sub_earth_point(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    frame: MoonFrame,
+) -> Result<SubPoint, String> {
+    sub_point(sl_mutex, t, frame, "EARTH")
+}
@@ -0,0 +1,334 @@
+//! The lunar day/night terminator.
+//!
+//! Points come from `edterm_c` on the reference ellipsoid and are returned as
+//! planetocentric lon/lat, ordered by longitude. For map overlays the line can
+//! also be closed into a polygon of the lit hemisphere. For a single site,
+//! `terminator_distance` gives the distance to either half of the terminator
+//! and when it will arrive.
+
+use crate::types::*;
+use crate::vector::*;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use spice::{cstr, SpiceLock};
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_POINTS: usize = 360;
+pub const MAX_POINTS: usize = 3600;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminatorKind {
+    /// Boundary of total shadow: beyond it the Sun is entirely below the horizon.
+    Umbral,
+    /// Boundary of full sunlight: inside it the Sun is entirely above the horizon.
+    Penumbral,
+}
+
+impl TerminatorKind {
+    fn spice_name(&self) -> &'static str {
+        match self {
+            TerminatorKind::Umbral => "UMBRAL",
+            TerminatorKind::Penumbral => "PENUMBRAL",
+        }
+    }
+}
+
+pub fn default_terminator_kind() -> TerminatorKind {
+    TerminatorKind::Umbral
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminatorShape {
+    /// The terminator itself, as a closed LineString.
+    Line,
+    /// The lit hemisphere, bounded by the terminator, the antimeridian and
+    /// the sunward pole.
+    Polygon,
+}
+
+pub fn default_terminator_shape() -> TerminatorShape {
+    TerminatorShape::Line
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Terminator {
+    #[serde(with = "default_datetime_standard")]
+    pub t: DateTime,
+    pub frame: MoonFrame,
+    pub kind: TerminatorKind,
+    /// Whether the north pole is on the lit side.
+    pub north_lit: bool,
+    /// `[lon, lat]` pairs in increasing longitude.
+    pub points: Vec<[f64; 2]>,
+    pub units: UnitSpecifier,
+}
+
+impl std::fmt::Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "frame: {}, points: {}, north lit: {}, u: {}",
+            self.frame,
+            self.points.len(),
+            self.north_lit,
+            self.units
+        )?;
+        for [lon, lat] in &self.points {
+            write!(f, "\n{} {}", lon, lat)?;
+        }
+        Ok(())
+    }
+}
+
+impl Angular for Terminator {
+    fn to_degrees(&self) -> Terminator {
+        match self.units {
+            UnitSpecifier::Degrees => self.clone(),
+            UnitSpecifier::Radians => Terminator {
+                points: self
+                    .points
+                    .iter()
+                    .map(|[lon, lat]| [lon.to_degrees(), lat.to_degrees()])
+                    .collect(),
+                units: UnitSpecifier::Degrees,
+                ..self.clone()
+            },
+        }
+    }
+    fn to_radians(&self) -> Terminator {
+        match self.units {
+            UnitSpecifier::Radians => self.clone(),
+            UnitSpecifier::Degrees => Terminator {
+                points: self
+                    .points
+                    .iter()
+                    .map(|[lon, lat]| [lon.to_radians(), lat.to_radians()])
+                    .collect(),
+                units: UnitSpecifier::Radians,
+                ..self.clone()
+            },
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.units
+    }
+}
+
+impl Terminator {
+    /// A GeoJSON Feature in degrees, as RFC 7946 requires.
+    pub fn to_geojson(&self, shape: TerminatorShape) -> Value {
+        let points = self.to_degrees().points;
+        let geometry = match shape {
+            TerminatorShape::Line => {
+                json!({"type": "LineString", "coordinates": open_line(&points)})
+            }
+            TerminatorShape::Polygon => {
+                json!({"type": "Polygon", "coordinates": [lit_ring(&points, self.north_lit)]})
+            }
+        };
+        json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": {
+                "t": crate::to_cspice_string(self.t),
+                "frame": self.frame.to_string(),
+                "kind": self.kind,
+            },
+        })
+    }
+}
+
+/// Latitude where the terminator (degrees, sorted by longitude) crosses
+/// the antimeridian.
+fn antimeridian_lat(points: &[[f64; 2]]) -> Option<f64> {
+    let (first, last) = (points.first()?, points.last()?);
+    let gap = first[0] + 360.0 - last[0];
+    if gap > 0.0 {
+        Some(last[1] + (first[1] - last[1]) * (180.0 - last[0]) / gap)
+    } else {
+        Some(first[1])
+    }
+}
+
+/// The terminator (degrees, sorted by longitude) as a line from the
+/// antimeridian round to it again, rather than closed across the map.
+fn open_line(points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let Some(edge) = antimeridian_lat(points) else {
+        return Vec::new();
+    };
+    let mut line = vec![[-180.0, edge]];
+    line.extend_from_slice(points);
+    line.push([180.0, edge]);
+    line
+}
+
+/// Closes the terminator (degrees, sorted by longitude) into a
+/// counterclockwise ring around the lit hemisphere, cut at the antimeridian.
+fn lit_ring(points: &[[f64; 2]], north_lit: bool) -> Vec<[f64; 2]> {
+    let Some(edge) = antimeridian_lat(points) else {
+        return Vec::new();
+    };
+    let pole = if north_lit { 90.0 } else { -90.0 };
+
+    let mut ring = vec![[-180.0, edge]];
+    ring.extend_from_slice(points);
+    ring.extend([[180.0, edge], [180.0, pole], [-180.0, pole], [-180.0, edge]]);
+    if !north_lit {
+        ring.reverse();
+    }
+    ring
+}
+
+/// The terminator on the Moon at `t` as `n` points in `frame`.
+pub fn terminator(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    frame: MoonFrame,
+    kind: TerminatorKind,
+    n: usize,
+) -> Result<Terminator, String> {
+    let lock = crate::lock_spice(&sl_mutex);
+    let mut trgepc = 0.0;
+    let mut obspos = [0.0; 3];
+    let mut trmvcs = vec![[0.0; 3]; n];
+    let sun = crate::spice_try(&lock, || unsafe {
+        let et = lock.str2et(crate::to_cspice_string(t).as_str());
+        spice::c::edterm_c(
+            cstr!(kind.spice_name()),
+            cstr!("SUN"),
+            cstr!("MOON"),
+            et,
+            cstr!(frame.spice_name()),
+            cstr!("NONE"),
+            cstr!("EARTH"),
+            n as i32,
+            &mut trgepc,
+            obspos.as_mut_ptr(),
+            trmvcs.as_mut_ptr(),
+        );
+        lock.spkpos("SUN", et, frame.spice_name(), "NONE", "MOON").0
+    })?;
+
+    let mut points: Vec<[f64; 2]> = trmvcs
+        .iter()
+        .map(|v| {
+            // terminator vectors are from the observer; shift to the Moon's center
+            let mut p = [obspos[0] + v[0], obspos[1] + v[1], obspos[2] + v[2]];
+            let (mut r, mut lon, mut lat) = (0.0, 0.0, 0.0);
+            unsafe {
+                spice::c::reclat_c(p.as_mut_ptr(), &mut r, &mut lon, &mut lat);
+            }
+            [lon, lat]
+        })
+        .collect();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    Ok(Terminator {
+        t,
+        frame,
+        kind,
+        north_lit: sun[2] >= 0.0,
+        points,
+        units: UnitSpecifier::Radians,
+    })
+}
+
+/// How far a site is from the terminator and when it gets there.
+///
+/// The terminator is taken as the great circle 90° from the subsolar point
+/// (the Sun's center on the horizon). The sunset half is the one east of the
+/// subsolar point, which the Sun's westward drift carries toward a site; the
+/// sunrise half is the one to the west.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct TerminatorDistance {
+    pub sun_up: bool,
+    /// Great-circle distance to the nearest point of the sunrise half, km.
+    pub sunrise_distance: f64,
+    /// Great-circle distance to the nearest point of the sunset half, km.
+    pub sunset_distance: f64,
+    /// Speed of the terminator along the site's parallel, km/h.
+    pub ground_speed: f64,
+    /// Seconds until the next sunrise, or `None` in polar day or night.
+    pub sunrise_in: Option<f64>,
+    /// Seconds until the next sunset, or `None` in polar day or night.
+    pub sunset_in: Option<f64>,
+}
+
+impl std::fmt::Display for TerminatorDistance {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let secs = |s: Option<f64>| s.map_or("never".to_string(), |s| format!("{} s", s));
+        write!(
+            f,
+            "sun up: {}, sunrise: {} km ({}), sunset: {} km ({}), ground speed: {} km/h",
+            self.sun_up,
+            self.sunrise_distance,
+            secs(self.sunrise_in),
+            self.sunset_distance,
+            secs(self.sunset_in),
+            self.ground_speed
+        )
+    }
+}
+
+/// Angle from `p` to the half of the terminator circle around `s` on the
+/// `side` of the subsolar meridian, given the subsolar east direction `e`.
+fn half_circle_angle(p: [f64; 3], s: [f64; 3], e: [f64; 3], side: f64) -> f64 {
+    let along = dot(p, s);
+    let q = sub(p, scale(s, along));
+    if dot(q, e) * side >= 0.0 && dot(q, q) > 0.0 {
+        return angle(p, normalize(q));
+    }
+    // nearest point is an end of the half circle
+    let n = cross(s, e);
+    angle(p, n).min(angle(p, scale(n, -1.0)))
+}
+
+/// Distances and times to the terminator from `pos`, in the MOON_ME frame.
+pub fn terminator_distance(
+    sl_mutex: Arc<Mutex<SpiceLock>>,
+    t: DateTime,
+    pos: Position,
+) -> Result<TerminatorDistance, String> {
+    const STEP_S: f64 = 3600.0;
+    let now = crate::subpoint::subsolar_point(sl_mutex.clone(), t, MoonFrame::Me)?;
+    let later = crate::subpoint::subsolar_point(
+        sl_mutex.clone(),
+        t + time::Duration::seconds_f64(STEP_S),
+        MoonFrame::Me,
+    )?;
+    let radius = crate::body_radii(&crate::lock_spice(&sl_mutex), "MOON")[0];
+
+    let pos = pos.to_radians();
+    let p = unit(pos.lat, pos.lon);
+    let s = unit(now.lat, now.lon);
+    let e = normalize(cross([0.0, 0.0, 1.0], s));
+
+    // subsolar longitude rate, rad/s; negative as the Sun drifts west
+    let rate = (later.lon - now.lon + PI).rem_euclid(2.0 * PI) - PI;
+    let rate = rate / STEP_S;
+
+    // hour angle of the site east of the subsolar meridian, and of the
+    // terminator crossings on the site's parallel
+    let hour = (pos.lon - now.lon).rem_euclid(2.0 * PI);
+    let cos_h0 = -pos.lat.tan() * now.lat.tan();
+    let (sunrise_in, sunset_in) = if cos_h0.abs() <= 1.0 && rate != 0.0 {
+        let h0 = cos_h0.acos();
+        let until = |h: f64| (h - hour).rem_euclid(2.0 * PI) / rate.abs();
+        (Some(until(2.0 * PI - h0)), Some(until(h0)))
+    } else {
+        (None, None)
+    };
+
+    Ok(TerminatorDistance {
+        sun_up: dot(p, s) > 0.0,
+        sunrise_distance: radius * half_circle_angle(p, s, e, -1.0),
+        sunset_distance: radius * half_circle_angle(p, s, e, 1.0),
+        ground_speed: radius * pos.lat.cos() * rate.abs() * 3600.0,
+        sunrise_in,
+        sunset_in,
+    })
+}
@@ -18,12 +18,20 @@ pub fn default_format() -> FormatSpecifier {
     FormatSpecifier::Txt
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum FormatSpecifier {
     #[serde(rename = "json")]
     Json,
     #[serde(rename = "txt")]
     Txt,
+    #[serde(rename = "geojson")]
+    Geojson,
+    #[serde(rename = "svg")]
+    Svg,
+    #[serde(rename = "csv")]
+    Csv,
+    #[serde(rename = "pgm")]
+    Pgm,
 }
 
 /////////// POSITION
@@ -48,7 +56,7 @@ impl Default for UnitSpecifier {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum UnitSpecifier {
     #[serde(rename = "radians")]
     Radians,
@@ -65,6 +73,58 @@ impl std::fmt::Display for UnitSpecifier {
     }
 }
 
+/// The body an observer stands on. Bodies other than the Moon and Earth
+/// need their ephemeris loaded (see `KERNELS_ENV`).
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Body {
+    #[default]
+    Moon,
+    Earth,
+    Mars,
+    Mercury,
+    Europa,
+}
+
+impl Body {
+    /// The SPICE body name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Body::Moon => "MOON",
+            Body::Earth => "EARTH",
+            Body::Mars => "MARS",
+            Body::Mercury => "MERCURY",
+            Body::Europa => "EUROPA",
+        }
+    }
+
+    /// The NAIF ID code.
+    pub fn id(&self) -> i32 {
+        match self {
+            Body::Moon => 301,
+            Body::Earth => 399,
+            Body::Mars => 499,
+            Body::Mercury => 199,
+            Body::Europa => 502,
+        }
+    }
+}
+
+impl std::fmt::Display for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Body::Moon => write!(f, "moon"),
+            Body::Earth => write!(f, "earth"),
+            Body::Mars => write!(f, "mars"),
+            Body::Mercury => write!(f, "mercury"),
+            Body::Europa => write!(f, "europa"),
+        }
+    }
+}
+
+/// A site on `body`: geodetic lat/lon (east longitude) and altitude in km
+/// on the body's reference ellipsoid. The Moon's is a sphere, so there
+/// lat/lon are planetocentric.
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub struct Position {
     #[serde(default = "default_lat")]
@@ -75,6 +135,8 @@ pub struct Position {
     pub alt: f64,
     #[serde(default = "default_degrees")]
     pub units: UnitSpecifier,
+    #[serde(default)]
+    pub body: Body,
 }
 
 impl Position {
@@ -84,6 +146,7 @@ impl Position {
             lon,
             alt,
             units,
+            body: Body::Moon,
         }
     }
     pub fn cadre() -> Position {
@@ -92,8 +155,13 @@ impl Position {
             lon: CADRE_LON,
             alt: 0.0,
             units: UnitSpecifier::Degrees,
+            body: Body::Moon,
         }
     }
+    /// The same lat/lon/alt on `body`.
+    pub fn on(self, body: Body) -> Position {
+        Position { body, ..self }
+    }
 }
 
 impl Default for Position {
@@ -102,15 +170,15 @@ impl Default for Position {
     }
 }
 
-fn default_lat() -> f64 {
+pub fn default_lat() -> f64 {
     CADRE_LAT
 }
 
-fn default_lon() -> f64 {
+pub fn default_lon() -> f64 {
     CADRE_LON
 }
 
-fn default_alt() -> f64 {
+pub fn default_alt() -> f64 {
     0.0
 }
 
@@ -120,6 +188,76 @@ pub fn default_position() -> Position {
         lon: default_lon(),
         alt: default_alt(),
         units: default_degrees(),
+        body: Body::Moon,
+    }
+}
+
+/////////// FRAMES
+/// Body-fixed lunar frames for the mapping endpoints.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum MoonFrame {
+    /// Mean Earth / polar axis, the frame of lunar maps.
+    Me,
+    /// Principal axes.
+    Pa,
+}
+
+impl MoonFrame {
+    pub fn spice_name(&self) -> &'static str {
+        match self {
+            MoonFrame::Me => "MOON_ME_DE440_ME421",
+            MoonFrame::Pa => "MOON_PA_DE440",
+        }
+    }
+}
+
+impl std::fmt::Display for MoonFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MoonFrame::Me => write!(f, "MOON_ME"),
+            MoonFrame::Pa => write!(f, "MOON_PA"),
+        }
+    }
+}
+
+pub fn default_moon_frame() -> MoonFrame {
+    MoonFrame::Me
+}
+
+/// How the lunar surface is modeled when placing a site.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Surface {
+    /// The reference ellipsoid from the PCK.
+    #[default]
+    Ellipsoid,
+    /// The loaded DSK shape models, highest resolution wherever they overlap.
+    Terrain,
+}
+
+impl Surface {
+    pub fn from_terrain(terrain: bool) -> Surface {
+        if terrain {
+            Surface::Terrain
+        } else {
+            Surface::Ellipsoid
+        }
+    }
+    pub fn spice_method(&self) -> &'static str {
+        match self {
+            Surface::Ellipsoid => "ELLIPSOID",
+            Surface::Terrain => "DSK/UNPRIORITIZED",
+        }
+    }
+}
+
+impl std::fmt::Display for Surface {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Surface::Ellipsoid => write!(f, "ellipsoid"),
+            Surface::Terrain => write!(f, "terrain"),
+        }
     }
 }
 
@@ -136,6 +274,7 @@ impl Angular for Position {
                     lon,
                     alt,
                     units: UnitSpecifier::Degrees,
+                    body: self.body,
                 }
             }
         }
@@ -152,6 +291,7 @@ impl Angular for Position {
                     lon,
                     alt,
                     units: UnitSpecifier::Radians,
+                    body: self.body,
                 }
             }
         }
@@ -167,6 +307,17 @@ pub struct RAzEl {
     pub el: f64,
     pub r: f64,
     pub units: UnitSpecifier,
+    /// Over terrain, the skyline's elevation at `az`: the target is hidden
+    /// when `el` is below it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub horizon_el: Option<f64>,
+}
+
+impl RAzEl {
+    /// Clear of the skyline, or above the level horizon without terrain.
+    pub fn visible(&self) -> bool {
+        self.el > self.horizon_el.unwrap_or(0.0)
+    }
 }
 
 impl std::fmt::Display for RAzEl {
@@ -175,7 +326,16 @@ impl std::fmt::Display for RAzEl {
             f,
             "az: {}, el: {}, r: {}, u: {}",
             self.az, self.el, self.r, self.units
-        )
+        )?;
+        if let Some(horizon_el) = self.horizon_el {
+            write!(
+                f,
+                ", horizon el: {}, visible: {}",
+                horizon_el,
+                self.visible()
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -192,6 +352,7 @@ impl Angular for RAzEl {
                     el,
                     r,
                     units: UnitSpecifier::Degrees,
+                    horizon_el: self.horizon_el.map(f64::to_degrees),
                 }
             }
         }
@@ -208,6 +369,7 @@ impl Angular for RAzEl {
                     el,
                     r,
                     units: UnitSpecifier::Radians,
+                    horizon_el: self.horizon_el.map(f64::to_radians),
                 }
             }
         }
@@ -217,6 +379,37 @@ impl Angular for RAzEl {
     }
 }
 
+/// Sun and Earth pointing from one site at one time.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct SiteGeometry {
+    pub sun: RAzEl,
+    pub earth: RAzEl,
+}
+
+impl std::fmt::Display for SiteGeometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "sun: {}; earth: {}", self.sun, self.earth)
+    }
+}
+
+impl Angular for SiteGeometry {
+    fn to_degrees(&self) -> SiteGeometry {
+        SiteGeometry {
+            sun: self.sun.to_degrees(),
+            earth: self.earth.to_degrees(),
+        }
+    }
+    fn to_radians(&self) -> SiteGeometry {
+        SiteGeometry {
+            sun: self.sun.to_radians(),
+            earth: self.earth.to_radians(),
+        }
+    }
+    fn units(&self) -> UnitSpecifier {
+        self.sun.units
+    }
+}
+
 pub fn translate_to<T: Serialize + Angular>(res: T, u: UnitSpecifier) -> T {
     match u {
         UnitSpecifier::Degrees => res.to_degrees(),
@@ -366,17 +559,23 @@ impl From<PositionFull> for RAzEl {
             el: p.lat,
             r: p.r,
             units: p.units,
+            horizon_el: None,
         }
     }
 }
 
+fn latrec(r: f64, lon: f64, lat: f64) -> [f64; 3] {
+    [
+        r * lat.cos() * lon.cos(),
+        r * lat.cos() * lon.sin(),
+        r * lat.sin(),
+    ]
+}
+
 impl From<RAzEl> for PositionXYZ {
     fn from(azel: RAzEl) -> Self {
         let azel_rad = azel.to_radians();
-        let mut rect = [0.0; 3];
-        unsafe {
-            spice::c::latrec_c(azel_rad.r, azel_rad.az, azel_rad.el, rect.as_mut_ptr());
-        }
+        let rect = latrec(azel_rad.r, azel_rad.az, azel_rad.el);
         PositionXYZ {
             x: rect[0],
             y: rect[1],
@@ -399,10 +598,7 @@ impl From<RAzEl> for PositionSpherical {
 impl From<RAzEl> for PositionFull {
     fn from(azel: RAzEl) -> Self {
         let azel_rad = azel.to_radians();
-        let mut rect = [0.0; 3];
-        unsafe {
-            spice::c::latrec_c(azel_rad.r, azel_rad.az, azel_rad.el, rect.as_mut_ptr());
-        }
+        let rect = latrec(azel_rad.r, azel_rad.az, azel_rad.el);
         PositionFull {
             x: rect[0],
             y: rect[1],
@@ -415,14 +611,30 @@ impl From<RAzEl> for PositionFull {
     }
 }
 
+/// `res` as JSON or text. Formats with their own renderers (GeoJSON, SVG,
+/// CSV, PGM) come out as JSON here; use `try_format_as` to refuse them.
 pub fn format_as<T: Serialize + std::fmt::Display>(
     res: T,
     f: FormatSpecifier,
     hint: Option<&str>,
 ) -> String {
+    match f {
+        FormatSpecifier::Txt => format!("{}", res),
+        _ => try_format_as(res, FormatSpecifier::Json, hint).unwrap_or_default(),
+    }
+}
+
+/// As `format_as`, but an error for formats only some endpoints render.
+pub fn try_format_as<T: Serialize + std::fmt::Display>(
+    res: T,
+    f: FormatSpecifier,
+    hint: Option<&str>,
+) -> Result<String, String> {
     match (f, hint) {
-        (FormatSpecifier::Json, None) => json!(res).to_string(),
-        (FormatSpecifier::Json, Some(hint)) => json!({hint: res}).to_string(),
-        (FormatSpecifier::Txt, _) => format!("{}", res),
+        (FormatSpecifier::Json, None) => Ok(json!(res).to_string()),
+        (FormatSpecifier::Json, Some(hint)) => Ok(json!({hint: res}).to_string()),
+        (FormatSpecifier::Txt, _) => Ok(format!("{}", res)),
+        // geometry, plots and grids are rendered by the routes that have them
+        _ => Err("format not supported for this endpoint".to_string()),
     }
 }
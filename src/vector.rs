@@ -0,0 +1,50 @@
+//! Small 3-vector helpers for geometry done outside CSPICE.
+
+pub(crate) type Vec3 = [f64; 3];
+
+/// Unit vector at planetocentric `lat`, `lon` (radians).
+pub(crate) fn unit(lat: f64, lon: f64) -> Vec3 {
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+pub(crate) fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scale(a: Vec3, k: f64) -> Vec3 {
+    [a[0] * k, a[1] * k, a[2] * k]
+}
+
+pub(crate) fn norm(a: Vec3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize(a: Vec3) -> Vec3 {
+    scale(a, 1.0 / norm(a))
+}
+
+/// Angle between `a` and `b`, accurate near 0 and π (like `vsep_c`).
+pub(crate) fn angle(a: Vec3, b: Vec3) -> f64 {
+    norm(cross(a, b)).atan2(dot(a, b))
+}
+
+/// `m` times `v`, for rotation matrices from `pxform`.
+pub(crate) fn mxv(m: [[f64; 3]; 3], v: Vec3) -> Vec3 {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
@@ -0,0 +1,578 @@
+//! A dedicated SPICE worker thread.
+//!
+//! CSPICE is not thread safe and every computation holds the `SpiceLock` for
+//! its whole duration, so running it on the tokio worker threads stalls the
+//! runtime. Handlers instead submit jobs here: a single OS thread drains a
+//! bounded queue and runs each job against the lock, and callers await the
+//! reply with a timeout.
+
+use spice::SpiceLock;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::MissionClock;
+use crate::dsn::{Comms, CommsTarget, DsnVisibility, Station};
+use crate::earth::{EarthTrack, EarthView};
+use crate::eclipse::Eclipses;
+use crate::horizon::{HorizonMask, Visibility};
+use crate::illumination::{Illumination, Observer};
+use crate::live::LiveSample;
+use crate::maps::MapCell;
+use crate::mars::MarsTime;
+use crate::missions::{Mission, Since};
+use crate::observing::{MoonRiseSet, MoonSky, SiteFromEarth};
+use crate::pool::{SpiceRequest, SpiceResponse, WorkerPool};
+use crate::power::{Panel, PanelPower};
+use crate::shadow::Shadows;
+use crate::subpoint::SubPoint;
+use crate::terminator::{Terminator, TerminatorDistance, TerminatorKind};
+use crate::types::*;
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce(&Arc<Mutex<SpiceLock>>) + Send>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WorkerError {
+    /// The queue is full, try again later.
+    Busy,
+    /// The job did not finish within the configured timeout.
+    Timeout,
+    /// The worker thread is gone, or the job panicked.
+    Failed,
+}
+
+impl std::fmt::Display for WorkerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WorkerError::Busy => write!(f, "spice worker queue is full"),
+            WorkerError::Timeout => write!(f, "spice computation timed out"),
+            WorkerError::Failed => write!(f, "spice computation failed"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+pub struct WorkerStatus {
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub timeout_ms: u64,
+    /// Jobs that panicked since startup; the lock was recovered after each.
+    pub panics: usize,
+    pub processes: usize,
+    pub processes_pending: usize,
+}
+
+impl std::fmt::Display for WorkerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "queue: {}/{}, timeout: {} ms, panics: {}, processes: {} ({} pending)",
+            self.queue_depth,
+            self.queue_capacity,
+            self.timeout_ms,
+            self.panics,
+            self.processes,
+            self.processes_pending
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct SpiceWorker {
+    tx: mpsc::Sender<Job>,
+    depth: Arc<AtomicUsize>,
+    panics: Arc<AtomicUsize>,
+    capacity: usize,
+    timeout: Duration,
+    pool: Option<Arc<WorkerPool>>,
+}
+
+impl SpiceWorker {
+    /// Spawns the worker thread, which owns the lock for the life of the process.
+    pub fn new(sl_mutex: Arc<Mutex<SpiceLock>>, capacity: usize, timeout: Duration) -> SpiceWorker {
+        let (tx, mut rx) = mpsc::channel::<Job>(capacity);
+        let depth = Arc::new(AtomicUsize::new(0));
+
+        let thread_depth = depth.clone();
+        let thread_sl = sl_mutex;
+        std::thread::Builder::new()
+            .name("spice-worker".to_string())
+            .spawn(move || {
+                while let Some(job) = rx.blocking_recv() {
+                    thread_depth.fetch_sub(1, Ordering::SeqCst);
+                    job(&thread_sl);
+                }
+            })
+            .unwrap();
+
+        SpiceWorker {
+            tx,
+            depth,
+            panics: Arc::new(AtomicUsize::new(0)),
+            capacity,
+            timeout,
+            pool: None,
+        }
+    }
+
+    /// Sends `call`s to a pool of worker processes instead of the local thread.
+    pub fn with_pool(mut self, pool: WorkerPool) -> SpiceWorker {
+        self.pool = Some(Arc::new(pool));
+        self
+    }
+
+    /// Queues `f` on the worker thread and waits for its result.
+    ///
+    /// Fails fast with `Busy` when the queue is full rather than piling up
+    /// requests. A job whose caller has already timed out is skipped.
+    ///
+    /// The timeout only stops the wait: CSPICE can't be interrupted, so a
+    /// job that has started keeps the thread and the lock until it finishes,
+    /// and everything queued behind it waits too. Jobs must bound their own
+    /// work to fit well inside the timeout. A job that panics is dropped and
+    /// the lock recovered, so later jobs still run.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, WorkerError>
+    where
+        F: FnOnce(Arc<Mutex<SpiceLock>>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let panics = self.panics.clone();
+        let job: Job = Box::new(move |sl_mutex| {
+            if reply_tx.is_closed() {
+                return;
+            }
+            let job_sl = sl_mutex.clone();
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(job_sl)));
+            match res {
+                Ok(res) => {
+                    let _ = reply_tx.send(res);
+                }
+                Err(_) => {
+                    tracing::error!("spice job panicked; recovering the lock");
+                    panics.fetch_add(1, Ordering::SeqCst);
+                    crate::recover_lock(sl_mutex);
+                }
+            }
+        });
+
+        self.depth.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.tx.try_send(job) {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+            return match e {
+                mpsc::error::TrySendError::Full(_) => Err(WorkerError::Busy),
+                mpsc::error::TrySendError::Closed(_) => Err(WorkerError::Failed),
+            };
+        }
+
+        match tokio::time::timeout(self.timeout, reply_rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(WorkerError::Failed),
+            Err(_) => Err(WorkerError::Timeout),
+        }
+    }
+
+    /// Runs `req` on the process pool if there is one, else on the local thread.
+    pub async fn call(&self, req: SpiceRequest) -> Result<SpiceResponse, WorkerError> {
+        match &self.pool {
+            Some(pool) => pool.call(req).await,
+            None => self.run(move |sl_mutex| req.execute(sl_mutex)).await,
+        }
+    }
+
+    pub async fn get_et(&self, t: DateTime) -> Result<f64, WorkerError> {
+        match self.call(SpiceRequest::Et { t }).await? {
+            SpiceResponse::Et(et) => Ok(et),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn solar_time(
+        &self,
+        t: DateTime,
+        p: Position,
+    ) -> Result<Result<String, String>, WorkerError> {
+        match self.call(SpiceRequest::SolarTime { t, p }).await? {
+            SpiceResponse::SolarTime(time) => Ok(time),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn solar_azel(&self, t: DateTime, p: Position) -> Result<RAzEl, WorkerError> {
+        match self.call(SpiceRequest::SolarAzel { t, p }).await? {
+            SpiceResponse::RAzEl(azel) => Ok(azel),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn earth_position_from_sun(&self, t: DateTime) -> Result<PositionFull, WorkerError> {
+        match self.call(SpiceRequest::EarthFromSun { t }).await? {
+            SpiceResponse::PositionFull(pos) => Ok(pos),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn earth_position_ecliptic(&self, t: DateTime) -> Result<PositionFull, WorkerError> {
+        match self.call(SpiceRequest::EarthEcliptic { t }).await? {
+            SpiceResponse::PositionFull(pos) => Ok(pos),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn live_sample(
+        &self,
+        t: DateTime,
+        p: Position,
+    ) -> Result<Result<LiveSample, String>, WorkerError> {
+        match self.call(SpiceRequest::Live { t, p }).await? {
+            SpiceResponse::Live(sample) => Ok(sample),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn subsolar_point(
+        &self,
+        t: DateTime,
+        frame: MoonFrame,
+    ) -> Result<Result<SubPoint, String>, WorkerError> {
+        match self.call(SpiceRequest::Subsolar { t, frame }).await? {
+            SpiceResponse::SubPoint(point) => Ok(point),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn sub_earth_point(
+        &self,
+        t: DateTime,
+        frame: MoonFrame,
+    ) -> Result<Result<SubPoint, String>, WorkerError> {
+        match self.call(SpiceRequest::SubEarth { t, frame }).await? {
+            SpiceResponse::SubPoint(point) => Ok(point),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn terminator(
+        &self,
+        t: DateTime,
+        frame: MoonFrame,
+        kind: TerminatorKind,
+        n: usize,
+    ) -> Result<Result<Terminator, String>, WorkerError> {
+        match self
+            .call(SpiceRequest::Terminator { t, frame, kind, n })
+            .await?
+        {
+            SpiceResponse::Terminator(terminator) => Ok(terminator),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn terminator_distance(
+        &self,
+        t: DateTime,
+        p: Position,
+    ) -> Result<Result<TerminatorDistance, String>, WorkerError> {
+        match self.call(SpiceRequest::TerminatorDistance { t, p }).await? {
+            SpiceResponse::TerminatorDistance(distance) => Ok(distance),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    /// The inner error is SPICE's message for a request it could not answer.
+    pub async fn illumination(
+        &self,
+        t: DateTime,
+        p: Position,
+        observer: Observer,
+        surface: Surface,
+    ) -> Result<Result<Illumination, String>, WorkerError> {
+        let req = SpiceRequest::Illumination {
+            t,
+            p,
+            observer,
+            surface,
+        };
+        match self.call(req).await? {
+            SpiceResponse::Illumination(illumination) => Ok(illumination),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn panel_power(
+        &self,
+        t: DateTime,
+        p: Position,
+        panel: Panel,
+        until: Option<DateTime>,
+        step: f64,
+        mask: Option<HorizonMask>,
+    ) -> Result<Result<PanelPower, String>, WorkerError> {
+        let req = SpiceRequest::PanelPower {
+            t,
+            p,
+            panel,
+            until,
+            step,
+            mask,
+        };
+        match self.call(req).await? {
+            SpiceResponse::PanelPower(power) => Ok(power),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    /// The inner error is SPICE's, e.g. terrain asked for with no DSK loaded.
+    pub async fn target_azel(
+        &self,
+        t: DateTime,
+        p: Position,
+        target: &str,
+        surface: Surface,
+    ) -> Result<Result<RAzEl, String>, WorkerError> {
+        let req = SpiceRequest::TargetAzel {
+            t,
+            p,
+            target: target.to_string(),
+            surface,
+        };
+        match self.call(req).await? {
+            SpiceResponse::TargetAzel(azel) => Ok(azel),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn shadows(
+        &self,
+        t: DateTime,
+        p: Position,
+        heights: Vec<f64>,
+        below: Option<f64>,
+        mask: Option<HorizonMask>,
+    ) -> Result<Result<Shadows, String>, WorkerError> {
+        let req = SpiceRequest::Shadows {
+            t,
+            p,
+            heights,
+            below,
+            mask,
+        };
+        match self.call(req).await? {
+            SpiceResponse::Shadows(shadows) => Ok(shadows),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn visibility(
+        &self,
+        t: DateTime,
+        mask: HorizonMask,
+        target: &str,
+        until: Option<DateTime>,
+        step: f64,
+    ) -> Result<Result<Visibility, String>, WorkerError> {
+        let req = SpiceRequest::Visibility {
+            t,
+            mask,
+            target: target.to_string(),
+            until,
+            step,
+        };
+        match self.call(req).await? {
+            SpiceResponse::Visibility(visibility) => Ok(visibility),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    /// One row of an illumination map.
+    pub async fn map_cells(
+        &self,
+        sites: Vec<Position>,
+        t: DateTime,
+        until: DateTime,
+        step: f64,
+        surface: Surface,
+    ) -> Result<Result<Vec<MapCell>, String>, WorkerError> {
+        let req = SpiceRequest::MapCells {
+            sites,
+            t,
+            until,
+            step,
+            surface,
+        };
+        match self.call(req).await? {
+            SpiceResponse::MapCells(cells) => Ok(cells),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn eclipses(
+        &self,
+        t: DateTime,
+        until: DateTime,
+        p: Position,
+    ) -> Result<Result<Eclipses, String>, WorkerError> {
+        match self.call(SpiceRequest::Eclipses { t, until, p }).await? {
+            SpiceResponse::Eclipses(eclipses) => Ok(eclipses),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn earth_view(
+        &self,
+        t: DateTime,
+        p: Position,
+    ) -> Result<Result<EarthView, String>, WorkerError> {
+        match self.call(SpiceRequest::EarthView { t, p }).await? {
+            SpiceResponse::EarthView(view) => Ok(view),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn earth_track(
+        &self,
+        t: DateTime,
+        until: DateTime,
+        step: f64,
+        p: Position,
+    ) -> Result<Result<EarthTrack, String>, WorkerError> {
+        match self
+            .call(SpiceRequest::EarthTrack { t, until, step, p })
+            .await?
+        {
+            SpiceResponse::EarthTrack(track) => Ok(track),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn moon_sky(
+        &self,
+        t: DateTime,
+        o: Position,
+    ) -> Result<Result<MoonSky, String>, WorkerError> {
+        match self.call(SpiceRequest::MoonSky { t, o }).await? {
+            SpiceResponse::MoonSky(sky) => Ok(sky),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn moon_rise_set(
+        &self,
+        t: DateTime,
+        until: DateTime,
+        step: f64,
+        o: Position,
+    ) -> Result<Result<MoonRiseSet, String>, WorkerError> {
+        match self
+            .call(SpiceRequest::MoonRiseSet { t, until, step, o })
+            .await?
+        {
+            SpiceResponse::MoonRiseSet(events) => Ok(events),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn site_from_earth(
+        &self,
+        t: DateTime,
+        o: Position,
+        p: Position,
+    ) -> Result<Result<SiteFromEarth, String>, WorkerError> {
+        match self.call(SpiceRequest::SiteFromEarth { t, o, p }).await? {
+            SpiceResponse::SiteFromEarth(view) => Ok(view),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn dsn(
+        &self,
+        t: DateTime,
+        until: DateTime,
+        step: f64,
+        p: Option<Position>,
+        stations: Vec<Station>,
+    ) -> Result<Result<DsnVisibility, String>, WorkerError> {
+        let req = SpiceRequest::Dsn {
+            t,
+            until,
+            step,
+            p,
+            stations,
+        };
+        match self.call(req).await? {
+            SpiceResponse::Dsn(visibility) => Ok(visibility),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn comms(
+        &self,
+        t: DateTime,
+        station: Station,
+        target: CommsTarget,
+        frequency: f64,
+    ) -> Result<Result<Comms, String>, WorkerError> {
+        let req = SpiceRequest::Comms {
+            t,
+            station,
+            target,
+            frequency,
+        };
+        match self.call(req).await? {
+            SpiceResponse::Comms(comms) => Ok(comms),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn mars_time(
+        &self,
+        t: DateTime,
+        p: Position,
+        mission: Option<Mission>,
+    ) -> Result<Result<MarsTime, String>, WorkerError> {
+        match self.call(SpiceRequest::MarsTime { t, p, mission }).await? {
+            SpiceResponse::MarsTime(time) => Ok(time),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub async fn mission_clock(
+        &self,
+        t: DateTime,
+        p: Position,
+        since: Since,
+        epoch: Option<DateTime>,
+        mission: Option<Mission>,
+    ) -> Result<MissionClock, WorkerError> {
+        let req = SpiceRequest::MissionClock {
+            t,
+            p,
+            since,
+            epoch,
+            mission,
+        };
+        match self.call(req).await? {
+            SpiceResponse::MissionClock(clock) => Ok(clock),
+            _ => Err(WorkerError::Failed),
+        }
+    }
+
+    pub fn status(&self) -> WorkerStatus {
+        WorkerStatus {
+            queue_depth: self.depth.load(Ordering::SeqCst),
+            queue_capacity: self.capacity,
+            timeout_ms: self.timeout.as_millis() as u64,
+            panics: self.panics.load(Ordering::SeqCst),
+            processes: self.pool.as_ref().map_or(0, |pool| pool.size()),
+            processes_pending: self.pool.as_ref().map_or(0, |pool| pool.pending()),
+        }
+    }
+}